@@ -37,3 +37,24 @@ pub fn ansi_escape(s: &str) -> Text<'static> {
         },
     }
 }
+
+/// Returns the text of `s` with ANSI escape sequences removed, for output
+/// that is shown to something other than a terminal.
+pub fn strip_ansi(s: &str) -> String {
+    let mut text = ansi_escape(s)
+        .lines
+        .iter()
+        .map(|line| {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    // `ansi_escape` does not produce an empty line for a trailing newline.
+    if s.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
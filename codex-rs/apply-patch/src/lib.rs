@@ -1,12 +1,15 @@
+mod mismatch;
 mod parser;
 mod seek_sequence;
+mod transaction;
+mod unified_diff;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::Utf8Error;
 
-use anyhow::Context;
 use anyhow::Result;
 pub use parser::Hunk;
 pub use parser::ParseError;
@@ -15,6 +18,9 @@ use parser::UpdateFileChunk;
 pub use parser::parse_patch;
 use similar::TextDiff;
 use thiserror::Error;
+pub use transaction::FilePreImage;
+pub use transaction::FileTransaction;
+pub use transaction::StagedChange;
 use tree_sitter::LanguageError;
 use tree_sitter::Parser;
 use tree_sitter_bash::LANGUAGE as BASH;
@@ -31,6 +37,12 @@ pub enum ApplyPatchError {
     /// Error that occurs while computing replacements when applying patch chunks
     #[error("{0}")]
     ComputeReplacements(String),
+    /// An update targets a file that is not UTF-8 text.
+    #[error(
+        "Cannot update {}: it is a binary file ({len} bytes). apply_patch can only edit text files",
+        path.display()
+    )]
+    BinaryFile { path: PathBuf, len: usize },
 }
 
 impl From<std::io::Error> for ApplyPatchError {
@@ -93,11 +105,17 @@ pub enum ApplyPatchFileChange {
         content: String,
     },
     Delete,
+    /// Create (or replace `path` with) a symlink to `target`.
+    Symlink {
+        target: PathBuf,
+    },
     Update {
         unified_diff: String,
         move_path: Option<PathBuf>,
         /// new_content that will result after the unified_diff is applied.
         new_content: String,
+        /// Contents of the file that `new_content` was computed from.
+        original_content: String,
     },
 }
 
@@ -133,6 +151,29 @@ impl ApplyPatchAction {
         &self.changes
     }
 
+    /// A change to a single file computed outside the patch format, e.g. by a
+    /// structured edit tool. `original_content` is `None` if the file does not
+    /// exist yet. `path` must be absolute.
+    pub fn new_write(path: &Path, original_content: Option<&str>, new_content: String) -> Self {
+        let change = match original_content {
+            None => ApplyPatchFileChange::Add {
+                content: new_content,
+            },
+            Some(original_content) => ApplyPatchFileChange::Update {
+                unified_diff: TextDiff::from_lines(original_content, new_content.as_str())
+                    .unified_diff()
+                    .context_radius(1)
+                    .to_string(),
+                move_path: None,
+                new_content,
+                original_content: original_content.to_string(),
+            },
+        };
+        Self {
+            changes: HashMap::from([(path.to_path_buf(), change)]),
+        }
+    }
+
     /// Should be used exclusively for testing. (Not worth the overhead of
     /// creating a feature flag for this.)
     pub fn new_add_for_test(path: &Path, content: String) -> Self {
@@ -160,12 +201,16 @@ pub fn maybe_parse_apply_patch_verified(argv: &[String], cwd: &Path) -> MaybeApp
                     Hunk::DeleteFile { .. } => {
                         changes.insert(path, ApplyPatchFileChange::Delete);
                     }
+                    Hunk::AddSymlink { target, .. } => {
+                        changes.insert(path, ApplyPatchFileChange::Symlink { target });
+                    }
                     Hunk::UpdateFile {
                         move_path, chunks, ..
                     } => {
                         let ApplyPatchFileUpdate {
                             unified_diff,
                             content: contents,
+                            original_content,
                         } = match unified_diff_from_chunks(&path, &chunks) {
                             Ok(diff) => diff,
                             Err(e) => {
@@ -178,6 +223,7 @@ pub fn maybe_parse_apply_patch_verified(argv: &[String], cwd: &Path) -> MaybeApp
                                 unified_diff,
                                 move_path: move_path.map(|p| cwd.join(p)),
                                 new_content: contents,
+                                original_content,
                             },
                         );
                     }
@@ -303,7 +349,7 @@ pub fn apply_hunks(
                 // The file is being added, so it doesn't exist yet.
                 None
             }
-            Hunk::DeleteFile { path } => Some(path.as_path()),
+            Hunk::DeleteFile { path, .. } | Hunk::AddSymlink { path, .. } => Some(path.as_path()),
             Hunk::UpdateFile {
                 path, move_path, ..
             } => match move_path {
@@ -345,8 +391,79 @@ pub struct AffectedPaths {
 }
 
 /// Apply the hunks to the filesystem, returning which files were added, modified, or deleted.
-/// Returns an error if the patch could not be applied.
-fn apply_hunks_to_files(hunks: &[Hunk]) -> anyhow::Result<AffectedPaths> {
+/// Either every hunk is applied or, if the patch could not be applied, none
+/// of them are (see [`FileTransaction`]).
+pub fn apply_hunks_to_files(hunks: &[Hunk]) -> anyhow::Result<AffectedPaths> {
+    let (transaction, affected) = stage_hunks(hunks)?;
+    transaction.commit()?;
+    Ok(affected)
+}
+
+/// Checks that the hunks apply cleanly without touching the filesystem and
+/// returns the changes they would make as a unified diff.
+pub fn preview_hunks(hunks: &[Hunk]) -> anyhow::Result<String> {
+    let (transaction, _) = stage_hunks(hunks)?;
+    let mut diff = String::new();
+    for (path, change) in transaction.changes() {
+        let new_contents = match change {
+            StagedChange::Write(contents) => Some(contents.as_slice()),
+            StagedChange::Symlink(target) => {
+                diff.push_str(&format!(
+                    "symlink {} -> {}\n",
+                    path.display(),
+                    target.display()
+                ));
+                continue;
+            }
+            StagedChange::Delete => None,
+        };
+        let old_contents = match std::fs::read(path) {
+            Ok(contents) => Some(contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(IoError {
+                    context: format!("Failed to read file {}", path.display()),
+                    source: err,
+                }
+                .into());
+            }
+        };
+        let old_header = match old_contents {
+            Some(_) => path.display().to_string(),
+            None => "/dev/null".to_string(),
+        };
+        let new_header = match new_contents {
+            Some(_) => path.display().to_string(),
+            None => "/dev/null".to_string(),
+        };
+        let old_text = old_contents.as_deref().map(std::str::from_utf8);
+        let new_text = new_contents.map(std::str::from_utf8);
+        match (old_text, new_text) {
+            (Some(Err(_)), _) | (_, Some(Err(_))) => {
+                diff.push_str(&format!(
+                    "Binary files {old_header} and {new_header} differ\n"
+                ));
+            }
+            (old_text, new_text) => {
+                let text_diff = TextDiff::from_lines(
+                    old_text.and_then(Result::ok).unwrap_or_default(),
+                    new_text.and_then(Result::ok).unwrap_or_default(),
+                );
+                diff.push_str(
+                    &text_diff
+                        .unified_diff()
+                        .header(&old_header, &new_header)
+                        .to_string(),
+                );
+            }
+        }
+    }
+    Ok(diff)
+}
+
+/// Computes every change the hunks make, in memory, along with the paths they
+/// affect.
+fn stage_hunks(hunks: &[Hunk]) -> anyhow::Result<(FileTransaction, AffectedPaths)> {
     if hunks.is_empty() {
         anyhow::bail!("No files were modified.");
     }
@@ -354,61 +471,59 @@ fn apply_hunks_to_files(hunks: &[Hunk]) -> anyhow::Result<AffectedPaths> {
     let mut added: Vec<PathBuf> = Vec::new();
     let mut modified: Vec<PathBuf> = Vec::new();
     let mut deleted: Vec<PathBuf> = Vec::new();
+    let mut transaction = FileTransaction::new();
     for hunk in hunks {
         match hunk {
             Hunk::AddFile { path, contents } => {
-                if let Some(parent) = path.parent() {
-                    if !parent.as_os_str().is_empty() {
-                        std::fs::create_dir_all(parent).with_context(|| {
-                            format!("Failed to create parent directories for {}", path.display())
-                        })?;
-                    }
-                }
-                std::fs::write(path, contents)
-                    .with_context(|| format!("Failed to write file {}", path.display()))?;
+                transaction.write(path, contents.as_str());
                 added.push(path.clone());
             }
-            Hunk::DeleteFile { path } => {
-                std::fs::remove_file(path)
-                    .with_context(|| format!("Failed to delete file {}", path.display()))?;
+            Hunk::DeleteFile { path, .. } => {
+                transaction.delete(path)?;
                 deleted.push(path.clone());
             }
+            Hunk::AddSymlink { path, target } => {
+                transaction.symlink(path, target);
+                added.push(path.clone());
+            }
             Hunk::UpdateFile {
                 path,
                 move_path,
                 chunks,
             } => {
+                let original_contents = transaction.read(path).map_err(|err| IoError {
+                    context: format!("Failed to read file to update {}", path.display()),
+                    source: err,
+                })?;
+                let original_contents = decode_text_file(path, original_contents)?;
                 let AppliedPatch { new_contents, .. } =
-                    derive_new_contents_from_chunks(path, chunks)?;
+                    derive_new_contents_from_text(original_contents, path, chunks)?;
                 if let Some(dest) = move_path {
-                    if let Some(parent) = dest.parent() {
-                        if !parent.as_os_str().is_empty() {
-                            std::fs::create_dir_all(parent).with_context(|| {
-                                format!(
-                                    "Failed to create parent directories for {}",
-                                    dest.display()
-                                )
-                            })?;
-                        }
-                    }
-                    std::fs::write(dest, new_contents)
-                        .with_context(|| format!("Failed to write file {}", dest.display()))?;
-                    std::fs::remove_file(path)
-                        .with_context(|| format!("Failed to remove original {}", path.display()))?;
+                    transaction.rename(path, dest, new_contents)?;
                     modified.push(dest.clone());
                 } else {
-                    std::fs::write(path, new_contents)
-                        .with_context(|| format!("Failed to write file {}", path.display()))?;
+                    transaction.write(path, new_contents);
                     modified.push(path.clone());
                 }
             }
         }
     }
-    Ok(AffectedPaths {
-        added,
-        modified,
-        deleted,
-    })
+    // A path touched by several hunks is reported once, under its final state.
+    added.retain(|path| !transaction.is_deleted(path));
+    modified.retain(|path| !transaction.is_deleted(path) && !added.contains(path));
+    deleted.retain(|path| transaction.is_deleted(path));
+    for paths in [&mut added, &mut modified, &mut deleted] {
+        let mut seen = std::collections::HashSet::new();
+        paths.retain(|path| seen.insert(path.clone()));
+    }
+    Ok((
+        transaction,
+        AffectedPaths {
+            added,
+            modified,
+            deleted,
+        },
+    ))
 }
 
 struct AppliedPatch {
@@ -422,7 +537,7 @@ fn derive_new_contents_from_chunks(
     path: &Path,
     chunks: &[UpdateFileChunk],
 ) -> std::result::Result<AppliedPatch, ApplyPatchError> {
-    let original_contents = match std::fs::read_to_string(path) {
+    let original_contents = match std::fs::read(path) {
         Ok(contents) => contents,
         Err(err) => {
             return Err(ApplyPatchError::IoError(IoError {
@@ -431,10 +546,50 @@ fn derive_new_contents_from_chunks(
             }));
         }
     };
+    let original_contents = decode_text_file(path, original_contents)?;
+    derive_new_contents_from_text(original_contents, path, chunks)
+}
+
+/// Returns `contents` as text, or [`ApplyPatchError::BinaryFile`] if it
+/// contains NUL bytes or is not valid UTF-8.
+pub fn decode_text_file(
+    path: &Path,
+    contents: Vec<u8>,
+) -> std::result::Result<String, ApplyPatchError> {
+    let len = contents.len();
+    if is_binary(&contents) {
+        return Err(ApplyPatchError::BinaryFile {
+            path: path.to_path_buf(),
+            len,
+        });
+    }
+    String::from_utf8(contents).map_err(|_| ApplyPatchError::BinaryFile {
+        path: path.to_path_buf(),
+        len,
+    })
+}
+
+/// Same heuristic as git: a NUL byte near the start of the file.
+fn is_binary(contents: &[u8]) -> bool {
+    const SNIFF_LEN: usize = 8000;
+    contents[..contents.len().min(SNIFF_LEN)].contains(&0)
+}
 
-    let mut original_lines: Vec<String> = original_contents
+/// Like [`derive_new_contents_from_chunks`], for contents that have already
+/// been read. `path` is only used in error messages.
+///
+/// The file's line ending style and final-newline state are preserved unless a
+/// chunk explicitly changes the latter.
+fn derive_new_contents_from_text(
+    original_contents: String,
+    path: &Path,
+    chunks: &[UpdateFileChunk],
+) -> std::result::Result<AppliedPatch, ApplyPatchError> {
+    let line_ending = LineEnding::detect(&original_contents);
+    let mut original_lines: Vec<String> = line_ending
+        .normalize(&original_contents)
         .split('\n')
-        .map(|s| s.to_string())
+        .map(str::to_string)
         .collect();
 
     // Drop the trailing empty element that results from the final newline so
@@ -443,19 +598,80 @@ fn derive_new_contents_from_chunks(
         original_lines.pop();
     }
 
+    // An empty file is treated as newline-terminated so that added lines end
+    // with a newline.
+    let trailing_newline = chunks
+        .iter()
+        .rev()
+        .find_map(|c| c.trailing_newline)
+        .unwrap_or(original_contents.is_empty() || original_contents.ends_with('\n'));
+
     let replacements = compute_replacements(&original_lines, path, chunks)?;
-    let new_lines = apply_replacements(original_lines, &replacements);
-    let mut new_lines = new_lines;
-    if !new_lines.last().is_some_and(|s| s.is_empty()) {
+    let mut new_lines = apply_replacements(original_lines, &replacements);
+    if trailing_newline && !new_lines.last().is_some_and(|s| s.is_empty()) {
         new_lines.push(String::new());
     }
-    let new_contents = new_lines.join("\n");
+    let new_contents = new_lines.join(line_ending.as_str());
     Ok(AppliedPatch {
         original_contents,
         new_contents,
     })
 }
 
+/// Line terminator used by a file, so that edited and added lines match it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// `CrLf` if most lines of `contents` end with `\r\n`.
+    pub fn detect(contents: &str) -> Self {
+        let lines = contents.matches('\n').count();
+        let crlf_lines = contents.matches("\r\n").count();
+        if crlf_lines > 0 && crlf_lines * 2 > lines {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// `contents` of a file with this line ending, with lines ending in `\n`.
+    pub fn normalize(self, contents: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(contents),
+            LineEnding::CrLf => Cow::Owned(rejoin_lines(contents, "\n")),
+        }
+    }
+
+    /// Inverse of [`LineEnding::normalize`]: `contents` with every `\n`
+    /// replaced by this line ending.
+    pub fn restore(self, contents: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(contents),
+            LineEnding::CrLf => Cow::Owned(rejoin_lines(contents, "\r\n")),
+        }
+    }
+}
+
+/// Joins the lines of `contents`, with any `\r` before a `\n` removed, with
+/// `separator`.
+fn rejoin_lines(contents: &str, separator: &str) -> String {
+    contents
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
 /// Compute a list of replacements needed to transform `original_lines` into the
 /// new lines, given the patch `chunks`. Each replacement is returned as
 /// `(start_index, old_len, new_lines)`.
@@ -467,19 +683,24 @@ fn compute_replacements(
     let mut replacements: Vec<(usize, usize, Vec<String>)> = Vec::new();
     let mut line_index: usize = 0;
 
-    for chunk in chunks {
+    for (chunk_index, chunk) in chunks.iter().enumerate() {
         // If a chunk has a `change_context`, we use seek_sequence to find it, then
         // adjust our `line_index` to continue from there.
         if let Some(ctx_line) = &chunk.change_context {
-            if let Some(idx) =
-                seek_sequence::seek_sequence(original_lines, &[ctx_line.clone()], line_index, false)
-            {
+            if let Some(idx) = seek_sequence::seek_sequence(
+                original_lines,
+                std::slice::from_ref(ctx_line),
+                line_index,
+                false,
+            ) {
                 line_index = idx + 1;
             } else {
                 return Err(ApplyPatchError::ComputeReplacements(format!(
-                    "Failed to find context '{}' in {}",
+                    "Failed to find context '{}' in {} ({}).\n{}",
                     ctx_line,
-                    path.display()
+                    path.display(),
+                    describe_chunk(chunk_index, chunks),
+                    mismatch::describe_mismatch(original_lines, std::slice::from_ref(ctx_line))
                 )));
             }
         }
@@ -533,10 +754,13 @@ fn compute_replacements(
             replacements.push((start_idx, pattern.len(), new_slice.to_vec()));
             line_index = start_idx + pattern.len();
         } else {
+            let expected: String = pattern.iter().map(|line| format!("\n  {line}")).collect();
             return Err(ApplyPatchError::ComputeReplacements(format!(
-                "Failed to find expected lines {:?} in {}",
-                chunk.old_lines,
-                path.display()
+                "Failed to find expected lines in {} ({}):{}\n{}",
+                path.display(),
+                describe_chunk(chunk_index, chunks),
+                expected,
+                mismatch::describe_mismatch(original_lines, pattern)
             )));
         }
     }
@@ -544,6 +768,15 @@ fn compute_replacements(
     Ok(replacements)
 }
 
+/// Names a chunk for error messages, e.g. `chunk 2 of 3, after '@@ fn main'`.
+fn describe_chunk(index: usize, chunks: &[UpdateFileChunk]) -> String {
+    let mut description = format!("chunk {} of {}", index + 1, chunks.len());
+    if let Some(ctx_line) = &chunks[index].change_context {
+        description.push_str(&format!(", after '@@ {ctx_line}'"));
+    }
+    description
+}
+
 /// Apply the `(start_index, old_len, new_lines)` replacements to `original_lines`,
 /// returning the modified file contents as a vector of lines.
 fn apply_replacements(
@@ -577,6 +810,7 @@ fn apply_replacements(
 pub struct ApplyPatchFileUpdate {
     unified_diff: String,
     content: String,
+    original_content: String,
 }
 
 pub fn unified_diff_from_chunks(
@@ -600,6 +834,7 @@ pub fn unified_diff_from_chunks_with_context(
     Ok(ApplyPatchFileUpdate {
         unified_diff,
         content: new_contents,
+        original_content: original_contents,
     })
 }
 
@@ -767,6 +1002,304 @@ PATCH"#,
         assert_eq!(contents, "foo\nbaz\n");
     }
 
+    #[test]
+    fn test_failed_hunk_leaves_earlier_files_untouched() {
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        let added = dir.path().join("added.txt");
+        fs::write(&first, "one\n").unwrap();
+        fs::write(&second, "two\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+-one
++uno
+*** Add File: {}
++new
+*** Update File: {}
+@@
+-does not match
++dos"#,
+            first.display(),
+            added.display(),
+            second.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert!(stdout.is_empty());
+        assert!(!stderr.is_empty());
+        assert_eq!(fs::read_to_string(&first).unwrap(), "one\n");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "two\n");
+        assert!(!added.exists());
+    }
+
+    #[test]
+    fn test_update_then_move_same_file_in_one_patch() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src.txt");
+        let dest = dir.path().join("dest.txt");
+        fs::write(&src, "a\nb\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+-a
++A
+*** Update File: {}
+*** Move to: {}
+@@
+-b
++B"#,
+            src.display(),
+            src.display(),
+            dest.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "A\nB\n");
+        let expected_out = format!(
+            "Success. Updated the following files:\nM {}\n",
+            dest.display()
+        );
+        assert_eq!(String::from_utf8(stdout).unwrap(), expected_out);
+    }
+
+    #[test]
+    fn test_apply_git_diff() {
+        let dir = tempdir().unwrap();
+        let modified = dir.path().join("modified.txt");
+        let renamed = dir.path().join("renamed.txt");
+        let added = dir.path().join("added.txt");
+        fs::write(&modified, "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.path().join("old.txt"), "moved\n").unwrap();
+        let patch = format!(
+            r#"diff --git a/{modified} b/{modified}
+--- a/{modified}
++++ b/{modified}
+@@ -2,2 +2,2 @@
+ two
+-three
++THREE
+\ No newline at end of file
+diff --git a/{old} b/{renamed}
+similarity index 100%
+rename from {old}
+rename to {renamed}
+diff --git a/{added} b/{added}
+new file mode 100644
+--- /dev/null
++++ b/{added}
+@@ -0,0 +1 @@
++new
+"#,
+            modified = modified.display(),
+            old = dir.path().join("old.txt").display(),
+            renamed = renamed.display(),
+            added = added.display(),
+        );
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(String::from_utf8(stderr).unwrap(), "");
+        assert_eq!(fs::read_to_string(&modified).unwrap(), "one\ntwo\nTHREE");
+        assert_eq!(fs::read_to_string(&renamed).unwrap(), "moved\n");
+        assert!(!dir.path().join("old.txt").exists());
+        assert_eq!(fs::read_to_string(&added).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn test_update_preserves_crlf_line_endings() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("crlf.txt");
+        fs::write(&path, "foo\r\nbar\r\nbaz\r\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+ foo
+-bar
++BAR
++qux"#,
+            path.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "foo\r\nBAR\r\nqux\r\nbaz\r\n"
+        );
+    }
+
+    #[test]
+    fn test_update_preserves_missing_final_newline() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("no_newline.txt");
+        fs::write(&path, "foo\nbar").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+ foo
+-bar
++BAR"#,
+            path.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo\nBAR");
+    }
+
+    #[test]
+    fn test_unified_diff_can_add_final_newline() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "one\ntwo").unwrap();
+        let patch = format!(
+            r#"--- a/{path}
++++ b/{path}
+@@ -1,2 +1,2 @@
+ one
+-two
+\ No newline at end of file
++two
+"#,
+            path = path.display()
+        );
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_update_and_move_preserve_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        let moved = dir.path().join("moved.sh");
+        fs::write(&script, "#!/bin/sh\necho one\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+-echo one
++echo two"#,
+            script.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        let mode = fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+*** Move to: {}
+@@
+-echo two
++echo three"#,
+            script.display(),
+            moved.display()
+        ));
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert!(!script.exists());
+        assert_eq!(
+            fs::read_to_string(&moved).unwrap(),
+            "#!/bin/sh\necho three\n"
+        );
+        let mode = fs::metadata(&moved).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[test]
+    fn test_mismatched_chunk_reports_closest_region() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        fs::write(&path, "fn a() {\n    one();\n}\nfn b() {\n    two(x);\n}\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@ fn a() {{
+-    one();
++    uno();
+@@
+ fn b() {{
+-    two(y);
++    dos(y);"#,
+            path.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        let stderr = String::from_utf8(stderr).unwrap();
+        assert!(
+            stderr.starts_with(&format!(
+                "Failed to find expected lines in {} (chunk 2 of 2):\n  fn b() {{\n      two(y);\n\
+                 Closest match at lines 4-5 (93% similar). Diff from the expected lines (-) to the file (+):\n\
+                 \x20fn b() {{\n-    two(y);\n+    two(x);\n",
+                path.display()
+            )),
+            "{stderr}"
+        );
+        // Nothing was written.
+        assert!(fs::read_to_string(&path).unwrap().contains("one();"));
+    }
+
+    #[test]
+    fn test_update_rejects_binary_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("image.bin");
+        fs::write(&path, b"\x89PNG\r\n\x00\x00foo\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Update File: {}
+@@
+-foo
++bar"#,
+            path.display()
+        ));
+        let hunks = parse_patch(&patch).unwrap();
+        let Err(err) = apply_hunks_to_files(&hunks) else {
+            panic!("expected the update to fail");
+        };
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Cannot update {}: it is a binary file (12 bytes). apply_patch can only edit text files",
+                path.display()
+            )
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"\x89PNG\r\n\x00\x00foo\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_symlink_and_update_through_it() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "one\n").unwrap();
+        let patch = wrap_patch(&format!(
+            r#"*** Add Symlink: {} -> target.txt
+*** Update File: {}
+@@
+-one
++two"#,
+            link.display(),
+            link.display()
+        ));
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        apply_patch(&patch, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(String::from_utf8(stderr).unwrap(), "");
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("target.txt"));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "two\n");
+    }
+
     #[test]
     fn test_update_file_hunk_can_move_file() {
         let dir = tempdir().unwrap();
@@ -970,6 +1503,7 @@ PATCH"#,
         let expected = ApplyPatchFileUpdate {
             unified_diff: expected_diff.to_string(),
             content: "foo\nBAR\nbaz\nQUX\n".to_string(),
+            original_content: "foo\nbar\nbaz\nqux\n".to_string(),
         };
         assert_eq!(expected, diff);
     }
@@ -1006,6 +1540,7 @@ PATCH"#,
         let expected = ApplyPatchFileUpdate {
             unified_diff: expected_diff.to_string(),
             content: "FOO\nbar\nbaz\n".to_string(),
+            original_content: "foo\nbar\nbaz\n".to_string(),
         };
         assert_eq!(expected, diff);
     }
@@ -1043,6 +1578,7 @@ PATCH"#,
         let expected = ApplyPatchFileUpdate {
             unified_diff: expected_diff.to_string(),
             content: "foo\nbar\nBAZ\n".to_string(),
+            original_content: "foo\nbar\nbaz\n".to_string(),
         };
         assert_eq!(expected, diff);
     }
@@ -1077,6 +1613,7 @@ PATCH"#,
         let expected = ApplyPatchFileUpdate {
             unified_diff: expected_diff.to_string(),
             content: "foo\nbar\nbaz\nquux\n".to_string(),
+            original_content: "foo\nbar\nbaz\n".to_string(),
         };
         assert_eq!(expected, diff);
     }
@@ -1132,6 +1669,7 @@ PATCH"#,
         let expected = ApplyPatchFileUpdate {
             unified_diff: expected_diff.to_string(),
             content: "a\nB\nc\nd\nE\nf\ng\n".to_string(),
+            original_content: "a\nb\nc\nd\ne\nf\n".to_string(),
         };
 
         assert_eq!(expected, diff);
@@ -1191,6 +1729,7 @@ g
                         .to_string(),
                         move_path: None,
                         new_content: "updated session directory content\n".to_string(),
+                        original_content: "session directory content\n".to_string(),
                     },
                 )]),
             })
@@ -0,0 +1,103 @@
+//! Entry-point for the standalone `apply_patch` executable, so that patches
+//! in the `*** Begin Patch` format (or unified/git diffs) can also be applied
+//! from a shell.
+//!
+//! Exit codes:
+//! - `0`: the patch applied (or, with `--check`/`--dry-run`, would apply).
+//! - `1`: the patch is well-formed but does not apply to the files on disk.
+//! - `2`: the patch could not be parsed, or the command line is invalid.
+use std::io::Read;
+use std::io::Write;
+use std::process::ExitCode;
+
+use clap::Parser;
+use codex_apply_patch::Hunk;
+use codex_apply_patch::apply_hunks_to_files;
+use codex_apply_patch::parse_patch;
+use codex_apply_patch::preview_hunks;
+use codex_apply_patch::print_summary;
+
+const EXIT_DOES_NOT_APPLY: u8 = 1;
+const EXIT_INVALID_PATCH: u8 = 2;
+
+/// Apply a patch to the files in the current directory.
+#[derive(Parser, Debug)]
+#[command(name = "apply_patch", version)]
+struct Cli {
+    /// The patch to apply. Read from stdin if omitted or `-`.
+    patch: Option<String>,
+
+    /// Only check that the patch applies cleanly; do not modify any files.
+    #[arg(long, conflicts_with = "dry_run")]
+    check: bool,
+
+    /// Print the resulting changes as a unified diff instead of applying them.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Apply the patch in reverse, undoing a previously applied patch.
+    #[arg(long)]
+    reverse: bool,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let patch = match cli.patch.as_deref() {
+        Some(patch) if patch != "-" => patch.to_string(),
+        _ => {
+            let mut patch = String::new();
+            if let Err(err) = std::io::stdin().read_to_string(&mut patch) {
+                eprintln!("Failed to read patch from stdin: {err}");
+                return ExitCode::from(EXIT_INVALID_PATCH);
+            }
+            patch
+        }
+    };
+
+    let hunks = match parse_patch(&patch) {
+        Ok(hunks) => hunks,
+        Err(err) => {
+            eprintln!("Invalid patch: {err}");
+            return ExitCode::from(EXIT_INVALID_PATCH);
+        }
+    };
+    let hunks = if cli.reverse {
+        match hunks.into_iter().rev().map(Hunk::reversed).collect() {
+            Ok(hunks) => hunks,
+            Err(err) => {
+                eprintln!("Invalid patch: {err}");
+                return ExitCode::from(EXIT_INVALID_PATCH);
+            }
+        }
+    } else {
+        hunks
+    };
+
+    if cli.check || cli.dry_run {
+        return match preview_hunks(&hunks) {
+            Ok(diff) => {
+                if cli.dry_run {
+                    print!("{diff}");
+                }
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{err:#}");
+                ExitCode::from(EXIT_DOES_NOT_APPLY)
+            }
+        };
+    }
+
+    match apply_hunks_to_files(&hunks) {
+        Ok(affected) => {
+            let mut stdout = std::io::stdout().lock();
+            let _ = print_summary(&affected, &mut stdout);
+            let _ = stdout.flush();
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err:#}");
+            ExitCode::from(EXIT_DOES_NOT_APPLY)
+        }
+    }
+}
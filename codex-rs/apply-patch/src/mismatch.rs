@@ -0,0 +1,141 @@
+//! Diagnostics for patch chunks whose lines cannot be located in the target
+//! file. The goal is an error message precise enough that the author of the
+//! patch (usually a model) can fix it in a single retry: we report the region
+//! of the file that most resembles the expected lines, how similar it is, and
+//! a line diff between the two.
+
+use std::fmt::Write;
+
+use similar::ChangeTag;
+use similar::TextDiff;
+
+/// Upper bound on the number of line comparisons spent looking for the closest
+/// region, so that diagnostics stay cheap on very large files.
+const MAX_LINE_COMPARISONS: usize = 250_000;
+
+/// Regions less similar than this are not worth showing.
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// The region of a file that best resembles a chunk's expected lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ClosestMatch {
+    /// 0-based index of the first line of the region.
+    pub start: usize,
+    /// Mean per-line similarity in `0.0..=1.0`.
+    pub similarity: f32,
+}
+
+/// Finds the window of `pattern.len()` lines in `lines` that is most similar to
+/// `pattern`, ignoring leading and trailing whitespace. Returns `None` if the
+/// file is too short or too large to search, or nothing is similar enough.
+pub(crate) fn closest_match(lines: &[String], pattern: &[String]) -> Option<ClosestMatch> {
+    if pattern.is_empty() || pattern.len() > lines.len() {
+        return None;
+    }
+    let windows = lines.len() - pattern.len() + 1;
+    if windows.saturating_mul(pattern.len()) > MAX_LINE_COMPARISONS {
+        return None;
+    }
+
+    let mut best: Option<ClosestMatch> = None;
+    for start in 0..windows {
+        let total: f32 = pattern
+            .iter()
+            .zip(&lines[start..])
+            .map(|(expected, actual)| line_similarity(expected, actual))
+            .sum();
+        let similarity = total / pattern.len() as f32;
+        if best.is_none_or(|b| similarity > b.similarity) {
+            best = Some(ClosestMatch { start, similarity });
+        }
+    }
+    best.filter(|b| b.similarity >= MIN_SIMILARITY)
+}
+
+fn line_similarity(expected: &str, actual: &str) -> f32 {
+    let (expected, actual) = (expected.trim(), actual.trim());
+    if expected == actual {
+        return 1.0;
+    }
+    TextDiff::from_chars(expected, actual).ratio()
+}
+
+/// Describes why `pattern` was not found in `lines`: the closest region with
+/// its 1-based line range and similarity, followed by a diff from the expected
+/// lines (`-`) to the actual ones (`+`).
+pub(crate) fn describe_mismatch(lines: &[String], pattern: &[String]) -> String {
+    let Some(ClosestMatch { start, similarity }) = closest_match(lines, pattern) else {
+        return "No similar region was found in the file.".to_string();
+    };
+    let end = start + pattern.len();
+    let range = if pattern.len() == 1 {
+        format!("line {}", start + 1)
+    } else {
+        format!("lines {}-{end}", start + 1)
+    };
+
+    let mut message = format!(
+        "Closest match at {range} ({:.0}% similar). Diff from the expected lines (-) to the file (+):",
+        similarity * 100.0
+    );
+    let expected = pattern.join("\n");
+    let actual = lines[start..end].join("\n");
+    let diff = TextDiff::from_lines(expected.as_str(), actual.as_str());
+    for change in diff.iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Equal => ' ',
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+        };
+        let _ = write!(message, "\n{sign}{}", change.value().trim_end_matches('\n'));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn to_vec(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_closest_region() {
+        let lines = to_vec(&[
+            "fn a() {",
+            "    one();",
+            "}",
+            "fn b() {",
+            "    two(x);",
+            "}",
+        ]);
+        let pattern = to_vec(&["fn b() {", "    two(y);"]);
+        let found = closest_match(&lines, &pattern).unwrap();
+        assert_eq!(found.start, 3);
+        assert!(found.similarity > 0.9 && found.similarity < 1.0);
+    }
+
+    #[test]
+    fn describes_mismatch_with_diff() {
+        let lines = to_vec(&["alpha", "beta", "gamma"]);
+        let pattern = to_vec(&["beta", "gamme"]);
+        assert_eq!(
+            describe_mismatch(&lines, &pattern),
+            "Closest match at lines 2-3 (90% similar). Diff from the expected lines (-) to the file (+):\n beta\n-gamme\n+gamma"
+        );
+    }
+
+    #[test]
+    fn reports_when_nothing_is_similar() {
+        let lines = to_vec(&["alpha", "beta"]);
+        let pattern = to_vec(&["zzzzzz"]);
+        assert_eq!(
+            describe_mismatch(&lines, &pattern),
+            "No similar region was found in the file."
+        );
+    }
+}
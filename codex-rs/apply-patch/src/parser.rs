@@ -7,9 +7,10 @@
 //! begin_patch: "*** Begin Patch" LF
 //! end_patch: "*** End Patch" LF?
 //!
-//! hunk: add_hunk | delete_hunk | update_hunk
+//! hunk: add_hunk | delete_hunk | update_hunk | symlink_hunk
 //! add_hunk: "*** Add File: " filename LF add_line+
 //! delete_hunk: "*** Delete File: " filename LF
+//! symlink_hunk: "*** Add Symlink: " filename " -> " filename LF
 //! update_hunk: "*** Update File: " filename LF change_move? change?
 //! filename: /(.+)/
 //! add_line: "+" /(.+)/ LF -> line
@@ -27,10 +28,15 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::unified_diff::is_unified_diff;
+use crate::unified_diff::parse_unified_diff;
+
 const BEGIN_PATCH_MARKER: &str = "*** Begin Patch";
 const END_PATCH_MARKER: &str = "*** End Patch";
 const ADD_FILE_MARKER: &str = "*** Add File: ";
 const DELETE_FILE_MARKER: &str = "*** Delete File: ";
+const ADD_SYMLINK_MARKER: &str = "*** Add Symlink: ";
+const SYMLINK_TARGET_SEPARATOR: &str = " -> ";
 const UPDATE_FILE_MARKER: &str = "*** Update File: ";
 const MOVE_TO_MARKER: &str = "*** Move to: ";
 const EOF_MARKER: &str = "*** End of File";
@@ -63,6 +69,15 @@ pub enum Hunk {
     },
     DeleteFile {
         path: PathBuf,
+        /// The deleted file's contents, if the patch includes them (unified
+        /// diffs do). Only used to reverse the deletion.
+        contents: Option<String>,
+    },
+    /// Creates a symlink at `path` pointing to `target`, replacing any file or
+    /// symlink already there.
+    AddSymlink {
+        path: PathBuf,
+        target: PathBuf,
     },
     UpdateFile {
         path: PathBuf,
@@ -78,10 +93,67 @@ impl Hunk {
     pub fn resolve_path(&self, cwd: &Path) -> PathBuf {
         match self {
             Hunk::AddFile { path, .. } => cwd.join(path),
-            Hunk::DeleteFile { path } => cwd.join(path),
+            Hunk::DeleteFile { path, .. } => cwd.join(path),
+            Hunk::AddSymlink { path, .. } => cwd.join(path),
             Hunk::UpdateFile { path, .. } => cwd.join(path),
         }
     }
+
+    /// Returns the hunk that undoes this one. A deletion can only be reversed
+    /// if the patch carries the deleted contents, as unified diffs do but
+    /// `*** Delete File` hunks do not. Adding a symlink cannot be reversed
+    /// either, since whatever it replaced is not part of the patch.
+    pub fn reversed(self) -> Result<Hunk, ParseError> {
+        match self {
+            AddFile { path, contents } => Ok(DeleteFile {
+                path,
+                contents: Some(contents),
+            }),
+            AddSymlink { path, .. } => Err(InvalidPatchError(format!(
+                "Cannot reverse adding the symlink {}: the patch does not contain what it replaced",
+                path.display()
+            ))),
+            DeleteFile {
+                path,
+                contents: Some(contents),
+            } => Ok(AddFile { path, contents }),
+            DeleteFile {
+                path,
+                contents: None,
+            } => Err(InvalidPatchError(format!(
+                "Cannot reverse the deletion of {}: the patch does not contain its contents",
+                path.display()
+            ))),
+            UpdateFile {
+                path,
+                move_path,
+                chunks,
+            } => {
+                let chunks = chunks
+                    .into_iter()
+                    .map(|chunk| UpdateFileChunk {
+                        change_context: chunk.change_context,
+                        old_lines: chunk.new_lines,
+                        new_lines: chunk.old_lines,
+                        is_end_of_file: chunk.is_end_of_file,
+                        trailing_newline: chunk.trailing_newline.map(|newline| !newline),
+                    })
+                    .collect();
+                Ok(match move_path {
+                    Some(move_path) => UpdateFile {
+                        path: move_path,
+                        move_path: Some(path),
+                        chunks,
+                    },
+                    None => UpdateFile {
+                        path,
+                        move_path: None,
+                        chunks,
+                    },
+                })
+            }
+        }
+    }
 }
 
 use Hunk::*;
@@ -100,9 +172,20 @@ pub struct UpdateFileChunk {
     /// If set to true, `old_lines` must occur at the end of the source file.
     /// (Tolerance around trailing newlines should be encouraged.)
     pub is_end_of_file: bool,
+
+    /// Whether the file should end with a newline once this chunk is applied.
+    /// `None` keeps the file's current final-newline state; unified diffs set
+    /// it when their `\ No newline at end of file` markers show that the
+    /// chunk adds or removes the final newline.
+    pub trailing_newline: Option<bool>,
 }
 
+/// Parses either the `*** Begin Patch` format described above or a standard
+/// unified / git diff (see [`crate::unified_diff`]).
 pub fn parse_patch(patch: &str) -> Result<Vec<Hunk>, ParseError> {
+    if is_unified_diff(patch) {
+        return parse_unified_diff(patch);
+    }
     let mode = if PARSE_IN_STRICT_MODE {
         ParseMode::Strict
     } else {
@@ -264,6 +347,24 @@ fn parse_one_hunk(lines: &[&str], line_number: usize) -> Result<(Hunk, usize), P
         return Ok((
             DeleteFile {
                 path: PathBuf::from(path),
+                contents: None,
+            },
+            1,
+        ));
+    } else if let Some(rest) = first_line.strip_prefix(ADD_SYMLINK_MARKER) {
+        // Add Symlink
+        let Some((path, target)) = rest.split_once(SYMLINK_TARGET_SEPARATOR) else {
+            return Err(InvalidHunkError {
+                message: format!(
+                    "Add symlink hunk '{first_line}' must have the form '{ADD_SYMLINK_MARKER}{{path}}{SYMLINK_TARGET_SEPARATOR}{{target}}'"
+                ),
+                line_number,
+            });
+        };
+        return Ok((
+            AddSymlink {
+                path: PathBuf::from(path),
+                target: PathBuf::from(target),
             },
             1,
         ));
@@ -325,7 +426,7 @@ fn parse_one_hunk(lines: &[&str], line_number: usize) -> Result<(Hunk, usize), P
 
     Err(InvalidHunkError {
         message: format!(
-            "'{first_line}' is not a valid hunk header. Valid hunk headers: '*** Add File: {{path}}', '*** Delete File: {{path}}', '*** Update File: {{path}}'"
+            "'{first_line}' is not a valid hunk header. Valid hunk headers: '*** Add File: {{path}}', '*** Delete File: {{path}}', '*** Update File: {{path}}', '*** Add Symlink: {{path}} -> {{target}}'"
         ),
         line_number,
     })
@@ -371,6 +472,7 @@ fn parse_update_file_chunk(
         old_lines: Vec::new(),
         new_lines: Vec::new(),
         is_end_of_file: false,
+        trailing_newline: None,
     };
     let mut parsed_lines = 0;
     for line in &lines[start_index..] {
@@ -479,7 +581,8 @@ fn test_parse_patch() {
                 contents: "abc\ndef\n".to_string()
             },
             DeleteFile {
-                path: PathBuf::from("path/delete.py")
+                path: PathBuf::from("path/delete.py"),
+                contents: None,
             },
             UpdateFile {
                 path: PathBuf::from("path/update.py"),
@@ -488,7 +591,8 @@ fn test_parse_patch() {
                     change_context: Some("def f():".to_string()),
                     old_lines: vec!["    pass".to_string()],
                     new_lines: vec!["    return 123".to_string()],
-                    is_end_of_file: false
+                    is_end_of_file: false,
+                    trailing_newline: None
                 }]
             }
         ])
@@ -513,7 +617,8 @@ fn test_parse_patch() {
                     change_context: None,
                     old_lines: vec![],
                     new_lines: vec!["line".to_string()],
-                    is_end_of_file: false
+                    is_end_of_file: false,
+                    trailing_newline: None
                 }],
             },
             AddFile {
@@ -542,6 +647,7 @@ fn test_parse_patch() {
                 old_lines: vec!["import foo".to_string()],
                 new_lines: vec!["import foo".to_string(), "bar".to_string()],
                 is_end_of_file: false,
+                trailing_newline: None,
             }],
         }])
     );
@@ -562,6 +668,7 @@ fn test_parse_patch_lenient() {
             old_lines: vec!["import foo".to_string()],
             new_lines: vec!["import foo".to_string(), "bar".to_string()],
             is_end_of_file: false,
+            trailing_newline: None,
         }],
     }];
     let expected_error =
@@ -627,13 +734,62 @@ fn test_parse_one_hunk() {
         parse_one_hunk(&["bad"], 234),
         Err(InvalidHunkError {
             message: "'bad' is not a valid hunk header. \
-            Valid hunk headers: '*** Add File: {path}', '*** Delete File: {path}', '*** Update File: {path}'".to_string(),
+            Valid hunk headers: '*** Add File: {path}', '*** Delete File: {path}', '*** Update File: {path}', '*** Add Symlink: {path} -> {target}'".to_string(),
             line_number: 234
         })
     );
+    assert_eq!(
+        parse_one_hunk(&["*** Add Symlink: bin/tool -> ../tools/tool.sh"], 3),
+        Ok((
+            AddSymlink {
+                path: PathBuf::from("bin/tool"),
+                target: PathBuf::from("../tools/tool.sh"),
+            },
+            1
+        ))
+    );
+    assert!(parse_one_hunk(&["*** Add Symlink: bin/tool"], 3).is_err());
     // Other edge cases are already covered by tests above/below.
 }
 
+#[test]
+fn test_reversed() {
+    let chunk = UpdateFileChunk {
+        change_context: None,
+        old_lines: vec!["last".to_string()],
+        new_lines: vec!["LAST".to_string()],
+        is_end_of_file: true,
+        trailing_newline: Some(false),
+    };
+    assert_eq!(
+        UpdateFile {
+            path: PathBuf::from("old.txt"),
+            move_path: Some(PathBuf::from("new.txt")),
+            chunks: vec![chunk],
+        }
+        .reversed(),
+        Ok(UpdateFile {
+            path: PathBuf::from("new.txt"),
+            move_path: Some(PathBuf::from("old.txt")),
+            chunks: vec![UpdateFileChunk {
+                change_context: None,
+                old_lines: vec!["LAST".to_string()],
+                new_lines: vec!["last".to_string()],
+                is_end_of_file: true,
+                trailing_newline: Some(true),
+            }],
+        })
+    );
+    assert!(
+        AddSymlink {
+            path: PathBuf::from("bin/tool"),
+            target: PathBuf::from("../tool"),
+        }
+        .reversed()
+        .is_err()
+    );
+}
+
 #[test]
 fn test_update_file_chunk() {
     assert_eq!(
@@ -695,7 +851,8 @@ fn test_update_file_chunk() {
                     "add".to_string(),
                     "context2".to_string()
                 ],
-                is_end_of_file: false
+                is_end_of_file: false,
+                trailing_newline: None
             }),
             6
         ))
@@ -707,7 +864,8 @@ fn test_update_file_chunk() {
                 change_context: None,
                 old_lines: vec![],
                 new_lines: vec!["line".to_string()],
-                is_end_of_file: true
+                is_end_of_file: true,
+                trailing_newline: None
             }),
             3
         ))
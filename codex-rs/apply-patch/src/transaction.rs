@@ -0,0 +1,594 @@
+//! All-or-nothing application of a set of file changes.
+//!
+//! A [`FileTransaction`] collects the final state of every path a patch
+//! touches without writing anything. Later hunks that read a path see the
+//! contents staged by earlier hunks, so a patch that updates the same file
+//! twice (or moves a file and then edits it) behaves as if it had been applied
+//! sequentially.
+//!
+//! [`FileTransaction::commit`] then:
+//!
+//! 1. records the pre-image of every path,
+//! 2. writes each new file (or symlink) to a temporary sibling (creating
+//!    parent directories as needed),
+//! 3. renames the temporaries into place and removes deleted files.
+//!
+//! If any step fails, the paths already committed are restored from their
+//! pre-images, the temporaries and any directories we created are removed,
+//! and the working tree is left exactly as it was.
+//!
+//! Writing to an existing symlink writes to the file it points to, leaving the
+//! link in place. Deleting a symlink removes the link itself.
+
+use std::collections::BTreeMap;
+use std::fs::Permissions;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context;
+
+/// State of a file before a transaction touched it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePreImage {
+    pub path: PathBuf,
+    /// `None` if the file did not exist or was a symlink.
+    pub contents: Option<Vec<u8>>,
+    /// The file's permissions; `None` if it did not exist or was a symlink.
+    pub permissions: Option<Permissions>,
+    /// Where the path pointed if it was a symlink.
+    pub symlink_target: Option<PathBuf>,
+}
+
+/// Final state of a path once a transaction is committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StagedChange {
+    Write(Vec<u8>),
+    Symlink(PathBuf),
+    Delete,
+}
+
+#[derive(Debug, Default)]
+pub struct FileTransaction {
+    changes: BTreeMap<PathBuf, StagedChange>,
+    /// Written paths that take their permissions from another file (the source
+    /// of a move) rather than from their own pre-image.
+    permission_sources: BTreeMap<PathBuf, PathBuf>,
+    /// Written paths with explicitly given permissions, which take precedence.
+    permissions: BTreeMap<PathBuf, Permissions>,
+}
+
+#[derive(Debug, Clone)]
+struct CapturedPreImage {
+    contents: Option<Vec<u8>>,
+    permissions: Option<Permissions>,
+    symlink_target: Option<PathBuf>,
+}
+
+impl FileTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if no changes have been staged.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns `true` if `path` is staged for deletion.
+    pub fn is_deleted(&self, path: &Path) -> bool {
+        matches!(self.changes.get(path), Some(StagedChange::Delete))
+    }
+
+    /// Reads `path` as it will look once the changes staged so far are
+    /// committed.
+    pub fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let path = self.write_target(path);
+        match self.changes.get(&path) {
+            Some(StagedChange::Write(contents)) => Ok(contents.clone()),
+            Some(StagedChange::Delete) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "file is deleted earlier in this patch",
+            )),
+            Some(StagedChange::Symlink(_)) | None => std::fs::read(&path),
+        }
+    }
+
+    /// The path a write to `path` ends up at: the file a symlink (on disk or
+    /// staged) points to, or `path` itself.
+    fn write_target(&self, path: &Path) -> PathBuf {
+        match self.changes.get(path) {
+            Some(StagedChange::Symlink(target)) => {
+                resolve_write_target(&path.parent().unwrap_or(Path::new("")).join(target))
+            }
+            Some(_) => path.to_path_buf(),
+            None => resolve_write_target(path),
+        }
+    }
+
+    /// Like [`FileTransaction::read`], for files expected to be UTF-8.
+    pub fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    /// The staged changes in path order.
+    pub fn changes(&self) -> impl Iterator<Item = (&Path, &StagedChange)> {
+        self.changes
+            .iter()
+            .map(|(path, change)| (path.as_path(), change))
+    }
+
+    /// Stages `contents` to be written to `path`.
+    pub fn write(&mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        let path = self.write_target(&path.into());
+        self.changes
+            .insert(path, StagedChange::Write(contents.into()));
+    }
+
+    /// Stages `contents` to be written to `path` with `permissions`, e.g. to
+    /// recreate a deleted file as it was.
+    pub fn write_with_permissions(
+        &mut self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+        permissions: Permissions,
+    ) {
+        let path = self.write_target(&path.into());
+        self.permissions.insert(path.clone(), permissions);
+        self.write(path, contents);
+    }
+
+    /// Stages a symlink at `path` pointing to `target`, replacing whatever
+    /// `path` currently is (but not what it points to).
+    pub fn symlink(&mut self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) {
+        self.changes
+            .insert(path.into(), StagedChange::Symlink(target.into()));
+    }
+
+    /// Stages moving `from` to `to` with new `contents`. `to` keeps the
+    /// permissions of `from` (e.g. the executable bit). Moving a file onto
+    /// itself is a plain write.
+    pub fn rename(
+        &mut self,
+        from: impl Into<PathBuf>,
+        to: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        let (from, to) = (from.into(), to.into());
+        if from == to {
+            self.write(to, contents);
+            return Ok(());
+        }
+        self.delete(&from)?;
+        let to = self.write_target(&to);
+        self.write(&to, contents);
+        self.permission_sources.insert(to, from);
+        Ok(())
+    }
+
+    /// Stages `path` for deletion. Fails if the file does not exist (taking
+    /// earlier staged changes into account).
+    pub fn delete(&mut self, path: impl Into<PathBuf>) -> anyhow::Result<()> {
+        let path = path.into();
+        let exists = match self.changes.get(&path) {
+            Some(staged) => *staged != StagedChange::Delete,
+            None => path.is_file() || path.is_symlink(),
+        };
+        if !exists {
+            anyhow::bail!("Failed to delete file {}: file not found", path.display());
+        }
+        self.changes.insert(path, StagedChange::Delete);
+        Ok(())
+    }
+
+    /// Applies every staged change, or none of them. Returns the pre-image of
+    /// each path that was changed.
+    pub fn commit(self) -> anyhow::Result<Vec<FilePreImage>> {
+        let mut pre_images = Vec::with_capacity(self.changes.len());
+        for path in self.changes.keys() {
+            pre_images.push(capture_pre_image(path)?);
+        }
+
+        let mut rollback = Rollback::default();
+        match self.stage_and_commit(&pre_images, &mut rollback) {
+            Ok(()) => Ok(self
+                .changes
+                .into_keys()
+                .zip(pre_images)
+                .map(|(path, pre_image)| FilePreImage {
+                    path,
+                    contents: pre_image.contents,
+                    permissions: pre_image.permissions,
+                    symlink_target: pre_image.symlink_target,
+                })
+                .collect()),
+            Err(err) => {
+                rollback.run();
+                Err(err)
+            }
+        }
+    }
+
+    fn stage_and_commit(
+        &self,
+        pre_images: &[CapturedPreImage],
+        rollback: &mut Rollback,
+    ) -> anyhow::Result<()> {
+        // Stage every write before touching any existing file.
+        let mut staged: Vec<Option<PathBuf>> = Vec::with_capacity(self.changes.len());
+        for ((path, change), pre_image) in self.changes.iter().zip(pre_images) {
+            if *change == StagedChange::Delete {
+                staged.push(None);
+                continue;
+            }
+            if let Some(parent) = path.parent()
+                && !parent.as_os_str().is_empty()
+            {
+                rollback.create_dir_all(parent).with_context(|| {
+                    format!("Failed to create parent directories for {}", path.display())
+                })?;
+            }
+            let temp = temp_path_for(path);
+            rollback.temp_files.push(temp.clone());
+            match change {
+                StagedChange::Write(contents) => {
+                    std::fs::write(&temp, contents)
+                        .with_context(|| format!("Failed to write file {}", path.display()))?;
+                    let permissions = match self.permission_sources.get(path) {
+                        Some(source) => std::fs::metadata(source).ok().map(|m| m.permissions()),
+                        None => None,
+                    };
+                    let permissions = self
+                        .permissions
+                        .get(path)
+                        .cloned()
+                        .or(permissions)
+                        .or_else(|| pre_image.permissions.clone());
+                    if let Some(permissions) = permissions {
+                        std::fs::set_permissions(&temp, permissions)
+                            .with_context(|| format!("Failed to write file {}", path.display()))?;
+                    }
+                }
+                StagedChange::Symlink(target) => create_symlink(target, &temp)
+                    .with_context(|| format!("Failed to create symlink {}", path.display()))?,
+                StagedChange::Delete => {}
+            }
+            staged.push(Some(temp));
+        }
+
+        // Commit: after this point every change is recorded for rollback.
+        for ((path, temp), pre_image) in self.changes.keys().zip(staged).zip(pre_images) {
+            match temp {
+                Some(temp) => std::fs::rename(&temp, path)
+                    .with_context(|| format!("Failed to write file {}", path.display()))?,
+                None => std::fs::remove_file(path)
+                    .with_context(|| format!("Failed to delete file {}", path.display()))?,
+            }
+            rollback.committed.push((path.clone(), pre_image.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// If `path` is an existing symlink, the file it ultimately points to;
+/// otherwise `path` itself.
+fn resolve_write_target(path: &Path) -> PathBuf {
+    if path.is_symlink()
+        && let Ok(target) = std::fs::canonicalize(path)
+    {
+        return target;
+    }
+    path.to_path_buf()
+}
+
+fn capture_pre_image(path: &Path) -> anyhow::Result<CapturedPreImage> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_symlink() => {
+            let target = std::fs::read_link(path)
+                .with_context(|| format!("Failed to read symlink {}", path.display()))?;
+            Ok(CapturedPreImage {
+                contents: None,
+                permissions: None,
+                symlink_target: Some(target),
+            })
+        }
+        Ok(metadata) if metadata.is_dir() => {
+            anyhow::bail!("Failed to write file {}: is a directory", path.display())
+        }
+        Ok(metadata) => {
+            let contents = std::fs::read(path)
+                .with_context(|| format!("Failed to read file {}", path.display()))?;
+            Ok(CapturedPreImage {
+                contents: Some(contents),
+                permissions: Some(metadata.permissions()),
+                symlink_target: None,
+            })
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(CapturedPreImage {
+            contents: None,
+            permissions: None,
+            symlink_target: None,
+        }),
+        Err(err) => {
+            Err(err).with_context(|| format!("Failed to read metadata for {}", path.display()))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+/// Returns a unique path next to `path` so the final rename stays on the same
+/// filesystem (and is therefore atomic).
+fn temp_path_for(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(
+        ".{file_name}.{}.{unique}.apply-patch-tmp",
+        std::process::id()
+    ))
+}
+
+/// Everything needed to undo a partially applied transaction.
+#[derive(Default)]
+struct Rollback {
+    /// Directories created while staging, outermost first.
+    created_dirs: Vec<PathBuf>,
+    /// Temporary files that may still exist.
+    temp_files: Vec<PathBuf>,
+    /// Paths already changed, with their pre-image.
+    committed: Vec<(PathBuf, CapturedPreImage)>,
+}
+
+impl Rollback {
+    fn create_dir_all(&mut self, dir: &Path) -> std::io::Result<()> {
+        let mut missing = Vec::new();
+        let mut current = Some(dir);
+        while let Some(dir) = current {
+            if dir.as_os_str().is_empty() || dir.exists() {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+        for dir in missing.into_iter().rev() {
+            std::fs::create_dir(&dir)?;
+            self.created_dirs.push(dir);
+        }
+        Ok(())
+    }
+
+    /// Best-effort restoration of the pre-transaction state.
+    fn run(self) {
+        for (path, pre_image) in self.committed.into_iter().rev() {
+            let temp = temp_path_for(&path);
+            let restored = match pre_image {
+                CapturedPreImage {
+                    symlink_target: Some(target),
+                    ..
+                } => create_symlink(&target, &temp),
+                CapturedPreImage {
+                    contents: Some(contents),
+                    permissions,
+                    ..
+                } => std::fs::write(&temp, contents).and_then(|()| match permissions {
+                    Some(permissions) => std::fs::set_permissions(&temp, permissions),
+                    None => Ok(()),
+                }),
+                CapturedPreImage { .. } => {
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+            };
+            if restored
+                .and_then(|()| std::fs::rename(&temp, &path))
+                .is_err()
+            {
+                let _ = std::fs::remove_file(&temp);
+            }
+        }
+        for temp in self.temp_files {
+            let _ = std::fs::remove_file(temp);
+        }
+        for dir in self.created_dirs.into_iter().rev() {
+            // Only succeeds if the directory is empty again.
+            let _ = std::fs::remove_dir(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn commit_applies_writes_and_deletes() {
+        let dir = tempdir().unwrap();
+        let keep = dir.path().join("keep.txt");
+        let gone = dir.path().join("gone.txt");
+        std::fs::write(&keep, "old\n").unwrap();
+        std::fs::write(&gone, "bye\n").unwrap();
+        let permissions = |path: &Path| Some(std::fs::metadata(path).unwrap().permissions());
+        let (keep_permissions, gone_permissions) = (permissions(&keep), permissions(&gone));
+
+        let mut txn = FileTransaction::new();
+        txn.write(&keep, "new\n");
+        txn.write(dir.path().join("nested/added.txt"), "added\n");
+        txn.delete(&gone).unwrap();
+        let pre_images = txn.commit().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&keep).unwrap(), "new\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("nested/added.txt")).unwrap(),
+            "added\n"
+        );
+        assert!(!gone.exists());
+        assert_eq!(dir_entries(dir.path()), vec!["keep.txt", "nested"]);
+        assert_eq!(
+            pre_images,
+            vec![
+                FilePreImage {
+                    path: gone,
+                    contents: Some(b"bye\n".to_vec()),
+                    permissions: gone_permissions,
+                    symlink_target: None,
+                },
+                FilePreImage {
+                    path: keep,
+                    contents: Some(b"old\n".to_vec()),
+                    permissions: keep_permissions,
+                    symlink_target: None,
+                },
+                FilePreImage {
+                    path: dir.path().join("nested/added.txt"),
+                    contents: None,
+                    permissions: None,
+                    symlink_target: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_staging_leaves_tree_untouched() {
+        let dir = tempdir().unwrap();
+        let existing = dir.path().join("a.txt");
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&existing, "original\n").unwrap();
+        std::fs::write(&blocker, "not a directory\n").unwrap();
+
+        let mut txn = FileTransaction::new();
+        txn.write(&existing, "changed\n");
+        txn.write(dir.path().join("new/dir/b.txt"), "b\n");
+        // `blocker` is a file, so its "child" cannot be created.
+        txn.write(blocker.join("c.txt"), "c\n");
+        assert!(txn.commit().is_err());
+
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "original\n");
+        assert_eq!(dir_entries(dir.path()), vec!["a.txt", "blocker"]);
+    }
+
+    fn pre_image(contents: Option<&[u8]>) -> CapturedPreImage {
+        CapturedPreImage {
+            contents: contents.map(<[u8]>::to_vec),
+            permissions: None,
+            symlink_target: None,
+        }
+    }
+
+    #[test]
+    fn rollback_restores_committed_paths() {
+        let dir = tempdir().unwrap();
+        let modified = dir.path().join("modified.txt");
+        let deleted = dir.path().join("deleted.txt");
+        let added = dir.path().join("added.txt");
+        std::fs::write(&modified, "before\n").unwrap();
+        std::fs::write(&deleted, "keep me\n").unwrap();
+
+        // Simulate a failure after these three paths were committed.
+        std::fs::write(&modified, "after\n").unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+        std::fs::write(&added, "new\n").unwrap();
+        let rollback = Rollback {
+            committed: vec![
+                (modified.clone(), pre_image(Some(b"before\n"))),
+                (deleted.clone(), pre_image(Some(b"keep me\n"))),
+                (added.clone(), pre_image(None)),
+            ],
+            ..Default::default()
+        };
+        rollback.run();
+
+        assert_eq!(std::fs::read_to_string(&modified).unwrap(), "before\n");
+        assert_eq!(std::fs::read_to_string(&deleted).unwrap(), "keep me\n");
+        assert!(!added.exists());
+        assert_eq!(dir_entries(dir.path()), vec!["deleted.txt", "modified.txt"]);
+    }
+
+    #[test]
+    fn reads_see_staged_changes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "disk\n").unwrap();
+
+        let mut txn = FileTransaction::new();
+        assert_eq!(txn.read_to_string(&path).unwrap(), "disk\n");
+        txn.write(&path, "staged\n");
+        assert_eq!(txn.read_to_string(&path).unwrap(), "staged\n");
+        txn.delete(&path).unwrap();
+        assert!(txn.read_to_string(&path).is_err());
+        assert!(txn.delete(&path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_follow_symlinks_and_symlinks_can_be_replaced() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        let other = dir.path().join("other.txt");
+        std::fs::write(&target, "old\n").unwrap();
+        std::fs::write(&other, "other\n").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+
+        let mut txn = FileTransaction::new();
+        txn.write(&link, "new\n");
+        txn.commit().unwrap();
+        assert!(link.is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new\n");
+
+        let mut txn = FileTransaction::new();
+        txn.symlink(&link, "other.txt");
+        let pre_images = txn.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "other\n");
+        assert_eq!(
+            pre_images,
+            vec![FilePreImage {
+                path: link.clone(),
+                contents: None,
+                permissions: None,
+                symlink_target: Some(PathBuf::from("target.txt")),
+            }]
+        );
+
+        let mut txn = FileTransaction::new();
+        txn.delete(&link).unwrap();
+        txn.commit().unwrap();
+        assert!(!link.is_symlink());
+        assert!(other.exists());
+    }
+}
@@ -0,0 +1,649 @@
+//! Parses standard unified diffs (`diff -u`) and git diffs (`diff --git`) into
+//! the same [`Hunk`] representation as the `*** Begin Patch` format, so they
+//! go through the same verification, approval and application path.
+//!
+//! Supported:
+//!
+//! - `--- old` / `+++ new` file headers, with optional `a/` and `b/` prefixes
+//!   and trailing timestamps;
+//! - `/dev/null` on either side for new and deleted files;
+//! - git extended headers (`new file mode`, `deleted file mode`,
+//!   `rename from`/`rename to`, `index`, ...), including pure renames;
+//! - `\ No newline at end of file`.
+//!
+//! File modes are not represented in [`Hunk`], so diffs that add a file with
+//! a mode other than `100644` or change a file's mode (`old mode`/`new mode`)
+//! are rejected rather than applied without the mode.
+//!
+//! Each `@@` hunk becomes one [`UpdateFileChunk`]. Hunk line numbers are not
+//! used to position chunks; like the native format, chunks are located by
+//! their context and removed lines, which must appear in order.
+
+use std::path::PathBuf;
+
+use crate::parser::Hunk;
+use crate::parser::ParseError;
+use crate::parser::ParseError::*;
+use crate::parser::UpdateFileChunk;
+
+const GIT_DIFF_MARKER: &str = "diff --git ";
+const OLD_FILE_MARKER: &str = "--- ";
+const NEW_FILE_MARKER: &str = "+++ ";
+const HUNK_MARKER: &str = "@@ ";
+const RENAME_FROM_MARKER: &str = "rename from ";
+const RENAME_TO_MARKER: &str = "rename to ";
+const NEW_FILE_MODE_MARKER: &str = "new file mode ";
+const DELETED_FILE_MODE_MARKER: &str = "deleted file mode ";
+const NEW_MODE_MARKER: &str = "new mode ";
+/// The only mode a file can be added with.
+const REGULAR_FILE_MODE: &str = "100644";
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+const DEV_NULL: &str = "/dev/null";
+
+/// Returns `true` if `patch` looks like a unified or git diff rather than a
+/// `*** Begin Patch` envelope.
+pub(crate) fn is_unified_diff(patch: &str) -> bool {
+    let Some(first_line) = patch.lines().find(|line| !line.trim().is_empty()) else {
+        return false;
+    };
+    first_line.starts_with(GIT_DIFF_MARKER)
+        || first_line.starts_with(OLD_FILE_MARKER)
+        || first_line.starts_with("diff ")
+        || first_line.starts_with("Index: ")
+}
+
+pub(crate) fn parse_unified_diff(patch: &str) -> Result<Vec<Hunk>, ParseError> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut hunks = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        if line.starts_with(GIT_DIFF_MARKER) || line.starts_with(OLD_FILE_MARKER) {
+            let (hunk, consumed) = parse_file_diff(&lines[index..], index + 1)?;
+            hunks.extend(hunk);
+            index += consumed;
+        } else {
+            // Preamble such as `Index:` lines, `diff -u a b` or commit
+            // messages from `git format-patch`.
+            index += 1;
+        }
+    }
+    if hunks.is_empty() {
+        return Err(InvalidPatchError(
+            "The diff does not contain any file changes".to_string(),
+        ));
+    }
+    Ok(hunks)
+}
+
+/// Parses the diff for one file starting at `lines[0]` (either a
+/// `diff --git` or a `---` line). Returns `None` for git diffs without any
+/// change we represent.
+fn parse_file_diff(
+    lines: &[&str],
+    line_number: usize,
+) -> Result<(Option<Hunk>, usize), ParseError> {
+    let mut index = 0;
+    let mut is_git = false;
+    let mut rename_from = None;
+    let mut rename_to = None;
+    let mut new_file = false;
+    let mut deleted_file = false;
+
+    if lines[0].starts_with(GIT_DIFF_MARKER) {
+        is_git = true;
+        index += 1;
+        // Extended headers run until the `---` line or the next file.
+        while let Some(line) = lines.get(index) {
+            if line.starts_with(OLD_FILE_MARKER) || line.starts_with(GIT_DIFF_MARKER) {
+                break;
+            }
+            if let Some(path) = line.strip_prefix(RENAME_FROM_MARKER) {
+                rename_from = Some(unquote(path));
+            } else if let Some(path) = line.strip_prefix(RENAME_TO_MARKER) {
+                rename_to = Some(unquote(path));
+            } else if let Some(mode) = line.strip_prefix(NEW_FILE_MODE_MARKER) {
+                if mode.trim() != REGULAR_FILE_MODE {
+                    return Err(InvalidHunkError {
+                        message: format!(
+                            "Unsupported file mode in '{line}': files can only be added with mode {REGULAR_FILE_MODE}; change the mode separately, e.g. with chmod"
+                        ),
+                        line_number: line_number + index,
+                    });
+                }
+                new_file = true;
+            } else if line.starts_with(NEW_MODE_MARKER) {
+                return Err(InvalidHunkError {
+                    message: format!(
+                        "Mode changes are not supported ('{line}'); change the mode separately, e.g. with chmod"
+                    ),
+                    line_number: line_number + index,
+                });
+            } else if line.starts_with(DELETED_FILE_MODE_MARKER) {
+                deleted_file = true;
+            } else if line.starts_with("Binary files ") || *line == "GIT binary patch" {
+                return Err(InvalidHunkError {
+                    message: "Binary diffs are not supported".to_string(),
+                    line_number: line_number + index,
+                });
+            }
+            index += 1;
+        }
+    }
+
+    let (old_path, new_path) = match lines.get(index) {
+        Some(line) if line.starts_with(OLD_FILE_MARKER) => {
+            let old_path = header_path(&line[OLD_FILE_MARKER.len()..]);
+            let Some(new_line) = lines
+                .get(index + 1)
+                .and_then(|l| l.strip_prefix(NEW_FILE_MARKER))
+            else {
+                return Err(InvalidHunkError {
+                    message: format!("Expected a '+++' line after '{line}'"),
+                    line_number: line_number + index + 1,
+                });
+            };
+            index += 2;
+            (old_path, header_path(new_line))
+        }
+        _ => {
+            // A git diff without content changes: a pure rename, or an
+            // empty file being added or deleted.
+            if new_file || deleted_file {
+                let Some(path) = git_diff_path(lines[0]) else {
+                    return Err(InvalidHunkError {
+                        message: format!("Could not parse the file path from '{}'", lines[0]),
+                        line_number,
+                    });
+                };
+                let path = PathBuf::from(path);
+                let hunk = if new_file {
+                    Hunk::AddFile {
+                        path,
+                        contents: String::new(),
+                    }
+                } else {
+                    Hunk::DeleteFile {
+                        path,
+                        contents: Some(String::new()),
+                    }
+                };
+                return Ok((Some(hunk), index));
+            }
+            return match (rename_from, rename_to) {
+                (Some(from), Some(to)) => Ok((
+                    Some(Hunk::UpdateFile {
+                        path: PathBuf::from(from),
+                        move_path: Some(PathBuf::from(to)),
+                        chunks: Vec::new(),
+                    }),
+                    index,
+                )),
+                _ if is_git => Ok((None, index)),
+                _ => Err(InvalidHunkError {
+                    message: format!("Could not parse file paths from '{}'", lines[0]),
+                    line_number,
+                }),
+            };
+        }
+    };
+
+    // Strip the `a/` and `b/` prefixes used by git and `diff -ru a b`.
+    let strip_prefixes = is_git
+        || (old_path.as_deref().is_none_or(|p| p.starts_with("a/"))
+            && new_path.as_deref().is_none_or(|p| p.starts_with("b/")));
+    let strip = |path: Option<String>, prefix: &str| {
+        path.map(|p| match p.strip_prefix(prefix) {
+            Some(stripped) if strip_prefixes => stripped.to_string(),
+            _ => p,
+        })
+    };
+    let old_path = strip(old_path, "a/");
+    let new_path = strip(new_path, "b/");
+
+    let chunks_start = index;
+    let mut chunks = Vec::new();
+    while let Some(line) = lines.get(index) {
+        if !line.starts_with(HUNK_MARKER) {
+            break;
+        }
+        let (chunk, consumed) = parse_chunk(&lines[index..], line_number + index)?;
+        chunks.push(chunk);
+        index += consumed;
+    }
+
+    let hunk = match (old_path, new_path) {
+        (None, None) => {
+            return Err(InvalidHunkError {
+                message: "Both sides of the diff are /dev/null".to_string(),
+                line_number,
+            });
+        }
+        (None, Some(path)) => {
+            let missing_newline = chunks
+                .last()
+                .is_some_and(|c| c.trailing_newline == Some(false));
+            Hunk::AddFile {
+                path: PathBuf::from(path),
+                contents: join_lines(chunks.iter().flat_map(|c| &c.new_lines), missing_newline),
+            }
+        }
+        (Some(path), None) => {
+            // A deletion has no new side, so any marker is about the old one.
+            let missing_newline = lines[chunks_start..index]
+                .iter()
+                .any(|line| line.starts_with(NO_NEWLINE_MARKER));
+            Hunk::DeleteFile {
+                path: PathBuf::from(path),
+                contents: Some(join_lines(
+                    chunks.iter().flat_map(|c| &c.old_lines),
+                    missing_newline,
+                )),
+            }
+        }
+        (Some(old_path), Some(new_path)) => {
+            if chunks.is_empty() && old_path == new_path {
+                return Ok((None, index));
+            }
+            let move_path = (old_path != new_path).then(|| PathBuf::from(new_path));
+            Hunk::UpdateFile {
+                path: PathBuf::from(old_path),
+                move_path,
+                chunks,
+            }
+        }
+    };
+    Ok((Some(hunk), index))
+}
+
+/// Joins the lines of a whole file, as listed in the hunks of a diff that
+/// adds or deletes it.
+fn join_lines<'a>(lines: impl Iterator<Item = &'a String>, missing_newline: bool) -> String {
+    let mut contents: String = lines.map(|line| format!("{line}\n")).collect();
+    if missing_newline {
+        contents.pop();
+    }
+    contents
+}
+
+/// Parses one `@@ -l,s +l,s @@` hunk. The line counts in the header decide
+/// where the hunk ends, so content lines that happen to start with `---` or
+/// `@@` are handled correctly.
+fn parse_chunk(lines: &[&str], line_number: usize) -> Result<(UpdateFileChunk, usize), ParseError> {
+    let Some((old_start, mut old_remaining, mut new_remaining)) = parse_hunk_header(lines[0])
+    else {
+        return Err(InvalidHunkError {
+            message: format!("Invalid hunk header '{}'", lines[0]),
+            line_number,
+        });
+    };
+
+    let mut chunk = UpdateFileChunk {
+        change_context: None,
+        old_lines: Vec::new(),
+        new_lines: Vec::new(),
+        is_end_of_file: false,
+        trailing_newline: None,
+    };
+    let mut has_context = false;
+    // Which side(s) the most recent line belonged to, for `\ No newline`.
+    let mut last_line_sides = (false, false);
+    let mut old_missing_newline = false;
+    let mut new_missing_newline = false;
+    let mut index = 1;
+    while index < lines.len() {
+        let line = lines[index];
+        if line.starts_with(NO_NEWLINE_MARKER) {
+            let (old_side, new_side) = last_line_sides;
+            old_missing_newline |= old_side;
+            new_missing_newline |= new_side;
+            index += 1;
+            continue;
+        }
+        if old_remaining == 0 && new_remaining == 0 {
+            break;
+        }
+        // Some tools strip the single space from empty context lines.
+        let (marker, text) = match line.chars().next() {
+            Some(marker) => (marker, &line[marker.len_utf8()..]),
+            None => (' ', ""),
+        };
+        match marker {
+            ' ' if old_remaining > 0 && new_remaining > 0 => {
+                chunk.old_lines.push(text.to_string());
+                chunk.new_lines.push(text.to_string());
+                old_remaining -= 1;
+                new_remaining -= 1;
+                has_context = true;
+                last_line_sides = (true, true);
+            }
+            '-' if old_remaining > 0 => {
+                chunk.old_lines.push(text.to_string());
+                old_remaining -= 1;
+                last_line_sides = (true, false);
+            }
+            '+' if new_remaining > 0 => {
+                chunk.new_lines.push(text.to_string());
+                new_remaining -= 1;
+                last_line_sides = (false, true);
+            }
+            _ => {
+                return Err(InvalidHunkError {
+                    message: format!(
+                        "Unexpected line in hunk: '{line}'. Expected {old_remaining} more old and {new_remaining} more new lines"
+                    ),
+                    line_number: line_number + index,
+                });
+            }
+        }
+        index += 1;
+    }
+    if old_missing_newline || new_missing_newline {
+        chunk.is_end_of_file = true;
+        // The markers say exactly how the file ends before and after this
+        // chunk; only a change is recorded, so the chunk can be reversed.
+        chunk.trailing_newline =
+            (old_missing_newline != new_missing_newline).then_some(!new_missing_newline);
+    }
+    if old_remaining > 0 || new_remaining > 0 {
+        return Err(InvalidHunkError {
+            message: format!(
+                "Hunk ended early: expected {old_remaining} more old and {new_remaining} more new lines"
+            ),
+            line_number: line_number + index,
+        });
+    }
+
+    // Without context or removed lines the chunk could only be appended to the
+    // end of the file, which is wrong unless the file is empty.
+    if chunk.old_lines.is_empty() && !has_context && old_start != 0 {
+        return Err(InvalidHunkError {
+            message: "Hunks that only add lines must include context lines".to_string(),
+            line_number,
+        });
+    }
+
+    Ok((chunk, index))
+}
+
+/// Returns `(old_start, old_count, new_count)` for a `@@ -l[,s] +l[,s] @@` line.
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let rest = line.strip_prefix(HUNK_MARKER)?;
+    let (ranges, _section_heading) = rest.split_once(" @@")?;
+    let (old_range, new_range) = ranges.split_once(' ')?;
+    let (old_start, old_count) = parse_range(old_range.strip_prefix('-')?)?;
+    let (_, new_count) = parse_range(new_range.strip_prefix('+')?)?;
+    Some((old_start, old_count, new_count))
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// The path of a `diff --git a/<path> b/<path>` line whose two paths are the
+/// same, as they are when a file is added or deleted.
+fn git_diff_path(line: &str) -> Option<String> {
+    let rest = line.strip_prefix(GIT_DIFF_MARKER)?;
+    // Equal paths make both halves the same length.
+    let (old, new) = rest.split_at_checked(rest.len().checked_sub(1)? / 2)?;
+    let (old, new) = (unquote(old), unquote(new.strip_prefix(' ')?));
+    let path = old.strip_prefix("a/")?;
+    (new.strip_prefix("b/")? == path).then(|| path.to_string())
+}
+
+/// Path from a `---`/`+++` header, or `None` for `/dev/null`. Drops the
+/// tab-separated timestamp written by `diff -u`.
+fn header_path(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or(header).trim_end();
+    let path = unquote(path);
+    (path != DEV_NULL).then_some(path)
+}
+
+/// Undoes git's C-style quoting of paths with special characters.
+fn unquote(path: &str) -> String {
+    let Some(inner) = path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(d @ b'0'..=b'7') => {
+                // Octal escape for non-ASCII bytes, e.g. `\303\251`.
+                let mut value = u32::from(d - b'0');
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(d - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn chunk(old: &[&str], new: &[&str]) -> UpdateFileChunk {
+        UpdateFileChunk {
+            change_context: None,
+            old_lines: old.iter().map(|s| s.to_string()).collect(),
+            new_lines: new.iter().map(|s| s.to_string()).collect(),
+            is_end_of_file: false,
+            trailing_newline: None,
+        }
+    }
+
+    #[test]
+    fn detects_diff_formats() {
+        assert!(is_unified_diff("diff --git a/x b/x\n"));
+        assert!(is_unified_diff("\n--- a/x\n+++ b/x\n"));
+        assert!(!is_unified_diff("*** Begin Patch\n*** End Patch"));
+    }
+
+    #[test]
+    fn parses_git_diff_with_all_change_kinds() {
+        let diff = r#"diff --git a/src/lib.rs b/src/lib.rs
+index 83db48f..bf269f4 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@ mod foo;
+ fn a() {}
+-fn b() {}
++fn b() { todo!() }
+ fn c() {}
+@@ -10,2 +10,3 @@ fn c() {}
+ fn x() {}
++fn y() {}
+ fn z() {}
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000..3b18e51
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++hello
++world
+\ No newline at end of file
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 3b18e51..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/old name.txt b/new name.txt
+similarity index 100%
+rename from old name.txt
+rename to new name.txt
+"#;
+        assert_eq!(
+            parse_unified_diff(diff),
+            Ok(vec![
+                Hunk::UpdateFile {
+                    path: PathBuf::from("src/lib.rs"),
+                    move_path: None,
+                    chunks: vec![
+                        chunk(
+                            &["fn a() {}", "fn b() {}", "fn c() {}"],
+                            &["fn a() {}", "fn b() { todo!() }", "fn c() {}"]
+                        ),
+                        chunk(
+                            &["fn x() {}", "fn z() {}"],
+                            &["fn x() {}", "fn y() {}", "fn z() {}"]
+                        ),
+                    ],
+                },
+                Hunk::AddFile {
+                    path: PathBuf::from("new.txt"),
+                    contents: "hello\nworld".to_string(),
+                },
+                Hunk::DeleteFile {
+                    path: PathBuf::from("gone.txt"),
+                    contents: Some("bye\n".to_string()),
+                },
+                Hunk::UpdateFile {
+                    path: PathBuf::from("old name.txt"),
+                    move_path: Some(PathBuf::from("new name.txt")),
+                    chunks: Vec::new(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_file_modes_it_cannot_apply() {
+        let mode_change = "diff --git a/script.sh b/script.sh\n\
+                           old mode 100644\n\
+                           new mode 100755\n";
+        assert_eq!(
+            parse_unified_diff(mode_change),
+            Err(InvalidHunkError {
+                message: "Mode changes are not supported ('new mode 100755'); change the mode separately, e.g. with chmod".to_string(),
+                line_number: 3,
+            })
+        );
+
+        let executable = "diff --git a/run.sh b/run.sh\n\
+                          new file mode 100755\n\
+                          --- /dev/null\n\
+                          +++ b/run.sh\n\
+                          @@ -0,0 +1 @@\n\
+                          +echo hi\n";
+        assert!(matches!(
+            parse_unified_diff(executable),
+            Err(InvalidHunkError { line_number: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_git_diffs_of_empty_files() {
+        let diff = "diff --git a/empty.txt b/empty.txt\n\
+                    new file mode 100644\n\
+                    index 0000000..e69de29\n\
+                    diff --git \"a/old empty.txt\" \"b/old empty.txt\"\n\
+                    deleted file mode 100644\n\
+                    index e69de29..0000000\n";
+        assert_eq!(
+            parse_unified_diff(diff),
+            Ok(vec![
+                Hunk::AddFile {
+                    path: PathBuf::from("empty.txt"),
+                    contents: String::new(),
+                },
+                Hunk::DeleteFile {
+                    path: PathBuf::from("old empty.txt"),
+                    contents: Some(String::new()),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_plain_unified_diff_with_rename_and_eof_markers() {
+        let diff = "--- old.txt\t2024-01-01 00:00:00.000000000 +0000\n\
+                    +++ new.txt\t2024-01-02 00:00:00.000000000 +0000\n\
+                    @@ -1,2 +1,2 @@\n \
+                    keep\n\
+                    -last\n\
+                    \\ No newline at end of file\n\
+                    +LAST\n\
+                    \\ No newline at end of file\n";
+        let mut expected = chunk(&["keep", "last"], &["keep", "LAST"]);
+        expected.is_end_of_file = true;
+        assert_eq!(
+            parse_unified_diff(diff),
+            Ok(vec![Hunk::UpdateFile {
+                path: PathBuf::from("old.txt"),
+                move_path: Some(PathBuf::from("new.txt")),
+                chunks: vec![expected],
+            }])
+        );
+    }
+
+    #[test]
+    fn hunk_counts_decide_where_hunks_end() {
+        // The removed line looks like a file header.
+        let diff = "--- a/x\n+++ b/x\n@@ -1,2 +1,1 @@\n keep\n--- not a header\n";
+        assert_eq!(
+            parse_unified_diff(diff),
+            Ok(vec![Hunk::UpdateFile {
+                path: PathBuf::from("x"),
+                move_path: None,
+                chunks: vec![chunk(&["keep", "-- not a header"], &["keep"])],
+            }])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_diffs() {
+        assert_eq!(
+            parse_unified_diff("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n keep\n"),
+            Err(InvalidHunkError {
+                message: "Hunk ended early: expected 1 more old and 1 more new lines".to_string(),
+                line_number: 5,
+            })
+        );
+        assert_eq!(
+            parse_unified_diff("--- a/x\n+++ b/x\n@@ -5,0 +6,1 @@\n+added\n"),
+            Err(InvalidHunkError {
+                message: "Hunks that only add lines must include context lines".to_string(),
+                line_number: 3,
+            })
+        );
+        assert_eq!(
+            parse_unified_diff(
+                "diff --git a/x.png b/x.png\nBinary files a/x.png and b/x.png differ\n"
+            ),
+            Err(InvalidHunkError {
+                message: "Binary diffs are not supported".to_string(),
+                line_number: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn unquotes_git_paths() {
+        assert_eq!(unquote(r#""a/caf\303\251 \"x\".txt""#), "a/café \"x\".txt");
+        assert_eq!(unquote("a/plain.txt"), "a/plain.txt");
+    }
+}
@@ -0,0 +1,145 @@
+#![allow(clippy::unwrap_used)]
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+use pretty_assertions::assert_eq;
+use tempfile::tempdir;
+
+fn run(cwd: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_apply_patch"))
+        .args(args)
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+const PATCH: &str = "*** Begin Patch
+*** Update File: file.txt
+@@
+ one
+-two
++TWO
+*** End Patch";
+
+#[test]
+fn applies_patch_from_argument_and_reverses_it() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    fs::write(&file, "one\ntwo\n").unwrap();
+
+    let output = run(dir.path(), &[PATCH], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Success. Updated the following files:\nM file.txt\n"
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\nTWO\n");
+
+    let output = run(dir.path(), &["--reverse"], PATCH);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+}
+
+#[test]
+fn reverses_a_diff_that_deletes_a_file() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("gone.txt");
+    fs::write(&file, "bye\nfor now").unwrap();
+    let diff = "diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+--- a/gone.txt
++++ /dev/null
+@@ -1,2 +0,0 @@
+-bye
+-for now
+\\ No newline at end of file
+";
+
+    let output = run(dir.path(), &[diff], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!file.exists());
+
+    let output = run(dir.path(), &["--reverse", diff], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "bye\nfor now");
+
+    // The native format does not include the deleted contents.
+    let patch = "*** Begin Patch\n*** Delete File: gone.txt\n*** End Patch";
+    let output = run(dir.path(), &["--reverse", patch], "");
+    assert_ne!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "bye\nfor now");
+}
+
+#[test]
+fn reverses_a_diff_that_removes_the_final_newline() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    fs::write(&file, "one\ntwo\n").unwrap();
+    let diff = "--- a/file.txt
++++ b/file.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++TWO
+\\ No newline at end of file
+";
+
+    // Passed on stdin, since the leading `---` would be taken for an option.
+    let output = run(dir.path(), &[], diff);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\nTWO");
+
+    let output = run(dir.path(), &["--reverse"], diff);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+}
+
+#[test]
+fn check_and_dry_run_leave_files_untouched() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    fs::write(&file, "one\ntwo\n").unwrap();
+
+    let output = run(dir.path(), &["--check", "-"], PATCH);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+
+    let output = run(dir.path(), &["--dry-run", PATCH], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "--- file.txt\n+++ file.txt\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n"
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+}
+
+#[test]
+fn exit_codes_distinguish_parse_errors_from_match_failures() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("file.txt"), "something else\n").unwrap();
+
+    let output = run(dir.path(), &["--check", PATCH], "");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to find expected lines"), "{stderr}");
+
+    let output = run(dir.path(), &["not a patch"], "");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Invalid patch:"), "{stderr}");
+}
@@ -0,0 +1,62 @@
+use clap::Parser;
+use codex_common::CliConfigOverrides;
+use codex_core::config::Config;
+use codex_core::config::ConfigOverrides;
+use codex_core::undo::UndoJournal;
+use uuid::Uuid;
+
+#[derive(Debug, Parser)]
+pub struct SessionsCommand {
+    #[clap(skip)]
+    pub config_overrides: CliConfigOverrides,
+
+    #[command(subcommand)]
+    pub cmd: SessionsSubcommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SessionsSubcommand {
+    /// Revert the last patch Codex applied in a session.
+    Undo(UndoArgs),
+}
+
+#[derive(Debug, Parser)]
+pub struct UndoArgs {
+    /// Session to undo in. Defaults to the session that most recently applied
+    /// a patch.
+    #[arg(long = "session")]
+    pub session_id: Option<Uuid>,
+}
+
+pub fn run_main(opts: SessionsCommand) -> anyhow::Result<()> {
+    let SessionsCommand {
+        config_overrides,
+        cmd,
+    } = opts;
+    let overrides_vec = config_overrides
+        .parse_overrides()
+        .map_err(anyhow::Error::msg)?;
+    let config = Config::load_with_cli_overrides(overrides_vec, ConfigOverrides::default())?;
+
+    match cmd {
+        SessionsSubcommand::Undo(UndoArgs { session_id }) => {
+            let journal = match session_id {
+                Some(session_id) => UndoJournal::new(&config.codex_home, session_id),
+                None => match UndoJournal::most_recent(&config.codex_home)? {
+                    Some(journal) => journal,
+                    None => anyhow::bail!("No patches to undo."),
+                },
+            };
+            match journal.undo_last()? {
+                Some(restored) => {
+                    println!("Undid last patch. Restored:");
+                    for path in restored {
+                        println!("  {}", path.display());
+                    }
+                }
+                None => anyhow::bail!("No patches to undo in {}.", journal.path().display()),
+            }
+        }
+    }
+    Ok(())
+}
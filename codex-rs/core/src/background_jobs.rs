@@ -0,0 +1,578 @@
+//! Background processes started with the `exec_background` tool.
+//!
+//! A regular `shell` call waits for the command to exit and kills it on
+//! timeout, so the model cannot start a dev server or file watcher and then
+//! test against it. Background jobs keep running after the tool call returns;
+//! their combined stdout and stderr go into a ring buffer that `read_output`
+//! drains, and `kill` stops them. Jobs started in a PTY also accept input
+//! from `send_input`. Each job leads its own process group, and when the
+//! job's process exits, whatever it left running in that group is killed
+//! too. A job is forgotten once its final output has been read. All jobs of a
+//! session are killed when the session (and with it the registry) is
+//! dropped.
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_channel::Sender;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Child;
+use tokio::process::ChildStdin;
+use tokio::sync::oneshot;
+
+use crate::exec::kill_process_group;
+#[cfg(unix)]
+use crate::exec::signal_process_group;
+use crate::exec::terminal_output_to_text;
+use crate::protocol::BackgroundJobBeginEvent;
+use crate::protocol::BackgroundJobEndEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::redact::SecretRedactor;
+
+/// Bytes of output retained per job. Older output is dropped once the model
+/// falls this far behind.
+const OUTPUT_BUFFER_BYTES: usize = 64 * 1024;
+
+/// How long to wait for the output pipes to close after the job exits.
+/// Processes the job started may keep them open after it is gone.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobStatus {
+    Running,
+    /// Exit code, or `None` if the job was terminated by a signal.
+    Exited(Option<i32>),
+}
+
+/// Output produced since the previous read, and the job's current status.
+#[derive(Debug, PartialEq)]
+pub(crate) struct JobOutput {
+    pub status: JobStatus,
+    pub output: Vec<u8>,
+    /// Bytes that were dropped from the ring buffer before they were read.
+    pub dropped_bytes: usize,
+}
+
+impl JobOutput {
+    /// Serializes the output as the result of a `read_output` tool call, with
+    /// secrets redacted.
+    pub(crate) fn format_for_model(&self, redactor: &SecretRedactor) -> String {
+        let (status, exit_code) = match self.status {
+            JobStatus::Running => ("running", None),
+            JobStatus::Exited(exit_code) => ("exited", exit_code),
+        };
+        serde_json::json!({
+            "status": status,
+            "exit_code": exit_code,
+            "output": redactor.redact(&String::from_utf8_lossy(&self.output)),
+            "dropped_bytes": self.dropped_bytes,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct BackgroundJobs {
+    inner: Mutex<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    jobs: BTreeMap<String, Job>,
+}
+
+struct Job {
+    output: Arc<Mutex<OutputBuffer>>,
+    status: Arc<Mutex<JobStatus>>,
+    /// Dropping or sending on this kills the process.
+    kill: Option<oneshot::Sender<()>>,
+    /// The terminal's master side if the job runs in a PTY.
+    input: Option<Arc<tokio::sync::Mutex<ChildStdin>>>,
+    /// The job's process group, which it leads. Killed by `Drop` while the
+    /// job runs, and by the job's task once it exits.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pgid: Option<u32>,
+    /// Set if the job runs outside the sandbox: what it runs, and where.
+    unsandboxed: Option<(Vec<String>, PathBuf)>,
+}
+
+impl BackgroundJobs {
+    /// Registers `child`, which runs `command` in `cwd` and whose stdout and
+    /// stderr must be piped, and returns the new job's id. If `child` runs in
+    /// a PTY, it has a stdin and no stderr instead. `sandboxed` tells whether
+    /// it runs in the sandbox. `BackgroundJobBegin` and, once the job exits,
+    /// `BackgroundJobEnd` events with id `sub_id` are sent on `tx_event`.
+    pub(crate) async fn start(
+        &self,
+        mut child: Child,
+        command: Vec<String>,
+        cwd: PathBuf,
+        sandboxed: bool,
+        sub_id: String,
+        tx_event: Sender<Event>,
+    ) -> io::Result<String> {
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("stdout pipe was unexpectedly not available"))?;
+        let input = child.stdin.take();
+        let stderr = match (child.stderr.take(), &input) {
+            (Some(stderr), _) => Some(stderr),
+            (None, Some(_)) => None,
+            (None, None) => {
+                return Err(io::Error::other(
+                    "stderr pipe was unexpectedly not available",
+                ));
+            }
+        };
+
+        let pgid = child.id();
+        let output = Arc::new(Mutex::new(OutputBuffer::default()));
+        let status = Arc::new(Mutex::new(JobStatus::Running));
+        let (kill_tx, kill_rx) = oneshot::channel();
+
+        let job_id = {
+            let mut registry = self.lock();
+            registry.next_id += 1;
+            let job_id = format!("job-{}", registry.next_id);
+            registry.jobs.insert(
+                job_id.clone(),
+                Job {
+                    output: output.clone(),
+                    status: status.clone(),
+                    kill: Some(kill_tx),
+                    input: input.map(|input| Arc::new(tokio::sync::Mutex::new(input))),
+                    pgid,
+                    unsandboxed: (!sandboxed).then(|| (command.clone(), cwd.clone())),
+                },
+            );
+            job_id
+        };
+
+        // Sent before the job can possibly end, so that front-ends always see
+        // the begin event first.
+        let event = Event {
+            id: sub_id.clone(),
+            msg: EventMsg::BackgroundJobBegin(BackgroundJobBeginEvent {
+                job_id: job_id.clone(),
+                command,
+                cwd,
+            }),
+        };
+        let _ = tx_event.send(event).await;
+
+        let stdout_task = tokio::spawn(copy_to_buffer(stdout, output.clone()));
+        let stderr_task = tokio::spawn(async move {
+            if let Some(stderr) = stderr {
+                copy_to_buffer(stderr, output).await;
+            }
+        });
+        let id = job_id.clone();
+        tokio::spawn(async move {
+            let exit_status = tokio::select! {
+                exit_status = child.wait() => {
+                    // Processes the job left behind would otherwise outlive
+                    // it unseen, keeping its output pipes open. The group
+                    // cannot be reused while any of them is alive.
+                    #[cfg(unix)]
+                    if let Some(pgid) = pgid {
+                        let _ = signal_process_group(pgid as libc::pid_t, libc::SIGKILL);
+                    }
+                    exit_status
+                }
+                // Either `kill` was called or the registry was dropped.
+                _ = kill_rx => {
+                    let _ = kill_process_group(&mut child).await;
+                    child.wait().await
+                }
+            };
+            // Buffer the remaining output before reporting the exit.
+            let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, async {
+                let _ = tokio::join!(stdout_task, stderr_task);
+            })
+            .await;
+            let exit_code = exit_status.ok().and_then(|s| s.code());
+            if let Ok(mut status) = status.lock() {
+                *status = JobStatus::Exited(exit_code);
+            }
+            let event = Event {
+                id: sub_id,
+                msg: EventMsg::BackgroundJobEnd(BackgroundJobEndEvent {
+                    job_id: id,
+                    exit_code,
+                }),
+            };
+            let _ = tx_event.send(event).await;
+        });
+
+        Ok(job_id)
+    }
+
+    /// Returns the output of `job_id` since the previous call, or `None` if
+    /// there is no such job. Once a job has exited, it is removed after its
+    /// remaining output has been returned.
+    pub(crate) fn read(&self, job_id: &str) -> Option<JobOutput> {
+        let mut registry = self.lock();
+        let job = registry.jobs.get(job_id)?;
+        let status = job.status.lock().map_or(JobStatus::Running, |s| *s);
+        let (mut output, dropped_bytes) = job
+            .output
+            .lock()
+            .map_or((Vec::new(), 0), |mut buffer| buffer.take_unread());
+        if job.input.is_some() {
+            output = terminal_output_to_text(&String::from_utf8_lossy(&output)).into_bytes();
+        }
+        if matches!(status, JobStatus::Exited(_)) {
+            registry.jobs.remove(job_id);
+        }
+        Some(JobOutput {
+            status,
+            output,
+            dropped_bytes,
+        })
+    }
+
+    /// Kills `job_id` and returns its status at the time of the call, or
+    /// `None` if there is no such job. The exit is reported asynchronously via
+    /// `BackgroundJobEnd`.
+    pub(crate) fn kill(&self, job_id: &str) -> Option<JobStatus> {
+        let mut registry = self.lock();
+        let job = registry.jobs.get_mut(job_id)?;
+        if let Some(kill) = job.kill.take() {
+            let _ = kill.send(());
+        }
+        Some(job.status.lock().map_or(JobStatus::Running, |s| *s))
+    }
+
+    /// The command and working directory of `job_id` if it runs outside the
+    /// sandbox, where input sent to it is not contained either.
+    pub(crate) fn unsandboxed_command(&self, job_id: &str) -> Option<(Vec<String>, PathBuf)> {
+        self.lock().jobs.get(job_id)?.unsandboxed.clone()
+    }
+
+    /// Writes `input` to the terminal of `job_id`, or returns `None` if there
+    /// is no such job.
+    pub(crate) async fn send_input(&self, job_id: &str, input: &str) -> Option<io::Result<()>> {
+        let job_input = self.lock().jobs.get(job_id)?.input.clone();
+        let Some(job_input) = job_input else {
+            return Some(Err(io::Error::other(format!(
+                "{job_id} was not started with tty, so it does not accept input"
+            ))));
+        };
+        let mut job_input = job_input.lock().await;
+        let result = async {
+            job_input.write_all(input.as_bytes()).await?;
+            job_input.flush().await
+        }
+        .await;
+        Some(result)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        // A panic while holding the lock cannot leave the registry in an
+        // inconsistent state, so recover from poisoning.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for BackgroundJobs {
+    /// Dropping the kill senders stops the jobs, but only if the runtime
+    /// still polls their tasks; when it is shutting down, those tasks are
+    /// cancelled and the processes the jobs started would be orphaned. So
+    /// kill the process groups directly.
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for job in self.lock().jobs.values() {
+            let running = job
+                .status
+                .lock()
+                .map_or(true, |status| *status == JobStatus::Running);
+            if running && let Some(pgid) = job.pgid {
+                let _ = signal_process_group(pgid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+async fn copy_to_buffer<R: AsyncRead + Unpin>(mut reader: R, output: Arc<Mutex<OutputBuffer>>) {
+    let mut buf = [0u8; 8192];
+    loop {
+        // Reading a PTY fails with `EIO` at the end of the output.
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if let Ok(mut output) = output.lock() {
+                    output.push(&buf[..n]);
+                }
+            }
+        }
+    }
+}
+
+/// Ring buffer of the most recent output that remembers how much of it has
+/// been read.
+#[derive(Default)]
+struct OutputBuffer {
+    data: VecDeque<u8>,
+    /// Bytes pushed since the job started.
+    total: usize,
+    /// Value of `total` at the previous `take_unread`.
+    read: usize,
+}
+
+impl OutputBuffer {
+    fn push(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+        let excess = self.data.len().saturating_sub(OUTPUT_BUFFER_BYTES);
+        self.data.drain(..excess);
+        self.total += bytes.len();
+    }
+
+    /// Unread output and the number of unread bytes that were already dropped.
+    fn take_unread(&mut self) -> (Vec<u8>, usize) {
+        let buffered_from = self.total - self.data.len();
+        let dropped = buffered_from.saturating_sub(self.read);
+        let start = self.read.max(buffered_from) - buffered_from;
+        self.read = self.total;
+        (self.data.range(start..).copied().collect(), dropped)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::process::Stdio;
+    use tokio::process::Command;
+
+    #[test]
+    fn output_buffer_reports_dropped_bytes() {
+        let mut buffer = OutputBuffer::default();
+        buffer.push(b"hello ");
+        assert_eq!(buffer.take_unread(), (b"hello ".to_vec(), 0));
+        buffer.push(&vec![b'x'; OUTPUT_BUFFER_BYTES]);
+        buffer.push(b"end");
+        let (output, dropped) = buffer.take_unread();
+        assert_eq!(dropped, 3);
+        assert_eq!(output.len(), OUTPUT_BUFFER_BYTES);
+        assert!(output.ends_with(b"xend"));
+        assert_eq!(buffer.take_unread(), (Vec::new(), 0));
+    }
+
+    #[tokio::test]
+    async fn kill_stops_job_and_reports_end() {
+        let child = Command::new("sh")
+            .args(["-c", "echo started; exec sleep 30"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let (tx_event, rx_event) = async_channel::unbounded();
+        let jobs = BackgroundJobs::default();
+        let job_id = jobs
+            .start(
+                child,
+                vec!["sh".to_string()],
+                PathBuf::from("/"),
+                false,
+                "sub".to_string(),
+                tx_event,
+            )
+            .await
+            .unwrap();
+        assert_eq!(job_id, "job-1");
+        assert_eq!(
+            jobs.unsandboxed_command(&job_id),
+            Some((vec!["sh".to_string()], PathBuf::from("/")))
+        );
+        let event = rx_event.recv().await.unwrap();
+        assert!(matches!(event.msg, EventMsg::BackgroundJobBegin(_)));
+
+        // Wait for the first line of output.
+        let mut output = Vec::new();
+        for _ in 0..100 {
+            output.extend(jobs.read(&job_id).unwrap().output);
+            if !output.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(output, b"started\n");
+
+        assert_eq!(jobs.kill(&job_id), Some(JobStatus::Running));
+        let event = tokio::time::timeout(Duration::from_secs(5), rx_event.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EventMsg::BackgroundJobEnd(end) = event.msg else {
+            panic!("unexpected event {event:?}");
+        };
+        assert_eq!(end.job_id, job_id);
+        assert_eq!(end.exit_code, None);
+        assert_eq!(jobs.read(&job_id).unwrap().status, JobStatus::Exited(None));
+        // Exited jobs are forgotten once their output has been read.
+        assert_eq!(jobs.read(&job_id), None);
+        assert_eq!(jobs.kill("job-2"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn drop_kills_process_group_after_runtime_shutdown() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let jobs = BackgroundJobs::default();
+        let grandchild = runtime.block_on(async {
+            let child = Command::new("sh")
+                .args(["-c", "sleep 30 & echo $!; wait"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .process_group(0)
+                .kill_on_drop(true)
+                .spawn()
+                .unwrap();
+            let (tx_event, _rx_event) = async_channel::unbounded();
+            let job_id = jobs
+                .start(
+                    child,
+                    vec!["sh".to_string()],
+                    PathBuf::from("/"),
+                    false,
+                    "sub".to_string(),
+                    tx_event,
+                )
+                .await
+                .unwrap();
+            let mut output = Vec::new();
+            for _ in 0..100 {
+                output.extend(jobs.read(&job_id).unwrap().output);
+                if output.ends_with(b"\n") {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            String::from_utf8(output).unwrap().trim().to_string()
+        });
+        // Cancels the job's task, so only `Drop` can stop `sleep`.
+        drop(runtime);
+        drop(jobs);
+
+        assert!(exits_soon(&grandchild), "sleep {grandchild} survived");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn exit_kills_processes_left_in_the_group() {
+        let child = Command::new("sh")
+            .args(["-c", "sleep 30 & echo $!"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let (tx_event, rx_event) = async_channel::unbounded();
+        let jobs = BackgroundJobs::default();
+        let job_id = jobs
+            .start(
+                child,
+                vec!["sh".to_string()],
+                PathBuf::from("/"),
+                true,
+                "sub".to_string(),
+                tx_event,
+            )
+            .await
+            .unwrap();
+        let _begin = rx_event.recv().await.unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), rx_event.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event.msg, EventMsg::BackgroundJobEnd(_)));
+
+        let output = jobs.read(&job_id).unwrap();
+        assert_eq!(output.status, JobStatus::Exited(Some(0)));
+        let grandchild = String::from_utf8(output.output).unwrap().trim().to_string();
+        assert!(exits_soon(&grandchild), "sleep {grandchild} survived");
+    }
+
+    /// Whether process `pid` is gone, or a zombie waiting to be reaped,
+    /// within two seconds.
+    #[cfg(target_os = "linux")]
+    fn exits_soon(pid: &str) -> bool {
+        let stat = format!("/proc/{pid}/stat");
+        (0..100).any(|_| {
+            let alive = std::fs::read_to_string(&stat).is_ok_and(|stat| {
+                !stat
+                    .rsplit(')')
+                    .next()
+                    .unwrap()
+                    .trim_start()
+                    .starts_with('Z')
+            });
+            if alive {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            !alive
+        })
+    }
+
+    #[tokio::test]
+    async fn send_input_reaches_job_in_pty() {
+        let command = vec!["cat".to_string()];
+        let env =
+            std::collections::HashMap::from([("PATH".to_string(), std::env::var("PATH").unwrap())]);
+        let child = crate::exec::spawn_command_under_sandbox(
+            command.clone(),
+            std::env::temp_dir(),
+            env,
+            crate::exec::SandboxType::None,
+            &crate::protocol::SandboxPolicy::new_full_auto_policy(),
+            &None,
+            crate::exec::StdioPolicy::Pty,
+        )
+        .await
+        .unwrap();
+        let (tx_event, _rx_event) = async_channel::unbounded();
+        let jobs = BackgroundJobs::default();
+        let job_id = jobs
+            .start(
+                child,
+                command,
+                std::env::temp_dir(),
+                false,
+                "sub".to_string(),
+                tx_event,
+            )
+            .await
+            .unwrap();
+
+        jobs.send_input(&job_id, "hello\n").await.unwrap().unwrap();
+        // The terminal echoes the input, and `cat` then prints it again.
+        let mut output = Vec::new();
+        for _ in 0..100 {
+            output.extend(jobs.read(&job_id).unwrap().output);
+            if output == b"hello\nhello\n" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), "hello\nhello\n");
+        jobs.kill(&job_id);
+    }
+}
@@ -0,0 +1,328 @@
+//! Offline record/replay of model traffic ("cassettes").
+//!
+//! A cassette is a directory containing one pair of files per model request:
+//!
+//! ```text
+//! 0000.request.json   # the JSON payload POST'ed to the provider
+//! 0000.sse            # the raw SSE bytes the provider streamed back
+//! 0001.request.json
+//! 0001.sse
+//! ...
+//! ```
+//!
+//! In `record` mode every successful request is forwarded to the provider as
+//! usual and both the payload and the response stream are written to the
+//! cassette. In `replay` mode no network traffic happens at all: the `N`th
+//! request made by the session is answered with `N.sse`. In `replay-by-hash`
+//! mode the response is instead looked up by hashing the request payload, so
+//! scenarios keep working when unrelated requests are added or reordered.
+//!
+//! Cassettes are enabled with `CODEX_RS_CASSETTE_DIR` and
+//! `CODEX_RS_CASSETTE_MODE` (see `flags.rs`), which makes it possible to run
+//! multi-turn agent scenarios, including tool calls and approvals, in CI.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use futures::Stream;
+use futures::TryStreamExt;
+use serde_json::Value;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::flags::CODEX_RS_CASSETTE_DIR;
+use crate::flags::CODEX_RS_CASSETTE_MODE;
+
+const REQUEST_SUFFIX: &str = ".request.json";
+const RESPONSE_SUFFIX: &str = ".sse";
+
+/// Payload fields that differ between runs of the same scenario (the prompt
+/// cache key is the session id), ignored when matching requests.
+const VOLATILE_FIELDS: &[&str] = &["prompt_cache_key"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CassetteMode {
+    /// Talk to the provider and save every exchange to the cassette.
+    Record,
+    /// Answer requests with the recorded responses, in order.
+    Replay,
+    /// Answer requests with the recorded response whose request payload
+    /// hashes to the same value.
+    ReplayByHash,
+}
+
+impl CassetteMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "record" => Some(Self::Record),
+            "replay" => Some(Self::Replay),
+            "replay-by-hash" => Some(Self::ReplayByHash),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Cassette {
+    dir: PathBuf,
+    mode: CassetteMode,
+    /// Index of the next exchange to record or replay.
+    next_index: AtomicUsize,
+    /// Lazily built map from request hash to response file, only used in
+    /// [`CassetteMode::ReplayByHash`].
+    responses_by_hash: Mutex<Option<HashMap<String, PathBuf>>>,
+}
+
+impl Cassette {
+    pub(crate) fn new(dir: impl Into<PathBuf>, mode: CassetteMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+            next_index: AtomicUsize::new(0),
+            responses_by_hash: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cassette configured via environment variables, if any.
+    pub(crate) fn from_env() -> Option<Arc<Self>> {
+        let dir = (*CODEX_RS_CASSETTE_DIR)?;
+        let Some(mode) = CassetteMode::parse(&CODEX_RS_CASSETTE_MODE) else {
+            warn!(
+                mode = *CODEX_RS_CASSETTE_MODE,
+                "unknown CODEX_RS_CASSETTE_MODE; expected record, replay or replay-by-hash"
+            );
+            return None;
+        };
+        Some(Arc::new(Self::new(dir, mode)))
+    }
+
+    pub(crate) fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(self.mode, CassetteMode::Replay | CassetteMode::ReplayByHash)
+    }
+
+    /// Returns the recorded SSE response for `payload` as a byte stream that
+    /// can be fed to the regular SSE processors.
+    pub(crate) fn replay(
+        &self,
+        payload: &Value,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Unpin + use<>> {
+        let response_path = match self.mode {
+            CassetteMode::ReplayByHash => self.response_path_for_hash(&request_hash(payload))?,
+            CassetteMode::Replay | CassetteMode::Record => {
+                let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+                let request_path = self.path_for(index, REQUEST_SUFFIX);
+                match std::fs::read(&request_path) {
+                    Ok(recorded) => {
+                        let matches = serde_json::from_slice::<Value>(&recorded)
+                            .is_ok_and(|recorded| comparable(&recorded) == comparable(payload));
+                        if !matches {
+                            warn!(
+                                "request {index} differs from {}; replaying anyway",
+                                request_path.display()
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        return Err(missing_entry(format!(
+                            "cassette has no request {index} ({}): {e}",
+                            request_path.display()
+                        )));
+                    }
+                }
+                self.path_for(index, RESPONSE_SUFFIX)
+            }
+        };
+
+        let body = std::fs::read(&response_path).map_err(|e| {
+            missing_entry(format!(
+                "failed to read cassette response {}: {e}",
+                response_path.display()
+            ))
+        })?;
+        Ok(futures::stream::iter([Ok(Bytes::from(body))]))
+    }
+
+    /// Saves `payload` as the next request in the cassette and returns a
+    /// stream that writes every chunk of `stream` to the matching response
+    /// file as it passes through.
+    pub(crate) fn record<S>(
+        &self,
+        payload: &Value,
+        stream: S,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Unpin + use<S>>
+    where
+        S: Stream<Item = Result<Bytes>> + Unpin,
+    {
+        std::fs::create_dir_all(&self.dir)?;
+        let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+        std::fs::write(
+            self.path_for(index, REQUEST_SUFFIX),
+            serde_json::to_string_pretty(payload)?,
+        )?;
+
+        let response_path = self.path_for(index, RESPONSE_SUFFIX);
+        let mut file = File::create(&response_path)?;
+        Ok(stream.inspect_ok(move |chunk| {
+            if let Err(e) = file.write_all(chunk) {
+                warn!("failed to write to {}: {e}", response_path.display());
+            }
+        }))
+    }
+
+    fn path_for(&self, index: usize, suffix: &str) -> PathBuf {
+        self.dir.join(format!("{index:04}{suffix}"))
+    }
+
+    fn response_path_for_hash(&self, hash: &str) -> Result<PathBuf> {
+        let mut guard = self
+            .responses_by_hash
+            .lock()
+            .map_err(|_| CodexErr::Io(std::io::Error::other("cassette index mutex poisoned")))?;
+        let responses = match guard.as_mut() {
+            Some(responses) => responses,
+            None => guard.insert(index_responses_by_hash(&self.dir)?),
+        };
+        responses.get(hash).cloned().ok_or_else(|| {
+            missing_entry(format!(
+                "cassette {} has no response for request hash {hash}",
+                self.dir.display()
+            ))
+        })
+    }
+}
+
+/// `payload` without its [`VOLATILE_FIELDS`].
+fn comparable(payload: &Value) -> Value {
+    let mut payload = payload.clone();
+    if let Some(fields) = payload.as_object_mut() {
+        for field in VOLATILE_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    payload
+}
+
+/// Hash of the compact JSON serialization of a request payload, without its
+/// [`VOLATILE_FIELDS`]. Requests are re-parsed before hashing so whitespace in
+/// hand-edited cassettes does not matter.
+fn request_hash(payload: &Value) -> String {
+    let digest = Sha256::digest(comparable(payload).to_string().as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn index_responses_by_hash(dir: &Path) -> Result<HashMap<String, PathBuf>> {
+    let mut responses = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(stem) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(REQUEST_SUFFIX))
+        else {
+            continue;
+        };
+        let payload: Value = serde_json::from_slice(&std::fs::read(&path)?)?;
+        responses.insert(
+            request_hash(&payload),
+            dir.join(format!("{stem}{RESPONSE_SUFFIX}")),
+        );
+    }
+    Ok(responses)
+}
+
+fn missing_entry(message: String) -> CodexErr {
+    CodexErr::Io(std::io::Error::new(std::io::ErrorKind::NotFound, message))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    async fn collect(stream: impl Stream<Item = Result<Bytes>> + Unpin) -> String {
+        let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    async fn record_two_exchanges(dir: &Path) {
+        let cassette = Cassette::new(dir, CassetteMode::Record);
+        for (request, response) in [("first", "data: one\n\n"), ("second", "data: two\n\n")] {
+            let upstream = futures::stream::iter([Ok(Bytes::from(response))]);
+            let recorded = cassette
+                .record(&json!({ "input": request }), upstream)
+                .unwrap();
+            // The recording stream must pass the bytes through unchanged.
+            assert_eq!(collect(recorded).await, response);
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_responses_in_order() {
+        let dir = TempDir::new().unwrap();
+        record_two_exchanges(dir.path()).await;
+
+        let cassette = Cassette::new(dir.path(), CassetteMode::Replay);
+        let first = cassette.replay(&json!({ "input": "first" })).unwrap();
+        assert_eq!(collect(first).await, "data: one\n\n");
+        // Ordered replay does not care whether the request matches.
+        let second = cassette.replay(&json!({ "input": "changed" })).unwrap();
+        assert_eq!(collect(second).await, "data: two\n\n");
+        assert!(cassette.replay(&json!({})).is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_responses_by_hash() {
+        let dir = TempDir::new().unwrap();
+        record_two_exchanges(dir.path()).await;
+
+        let cassette = Cassette::new(dir.path(), CassetteMode::ReplayByHash);
+        let second = cassette.replay(&json!({ "input": "second" })).unwrap();
+        assert_eq!(collect(second).await, "data: two\n\n");
+        let first = cassette.replay(&json!({ "input": "first" })).unwrap();
+        assert_eq!(collect(first).await, "data: one\n\n");
+        assert!(cassette.replay(&json!({ "input": "unknown" })).is_err());
+
+        let mut stream = cassette.replay(&json!({ "input": "first" })).unwrap();
+        assert!(stream.next().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn ignores_prompt_cache_key_when_matching() {
+        let dir = TempDir::new().unwrap();
+        let cassette = Cassette::new(dir.path(), CassetteMode::Record);
+        let upstream = futures::stream::iter([Ok(Bytes::from("data: one\n\n"))]);
+        let recorded = cassette
+            .record(
+                &json!({ "input": "first", "prompt_cache_key": "session-1" }),
+                upstream,
+            )
+            .unwrap();
+        collect(recorded).await;
+
+        let cassette = Cassette::new(dir.path(), CassetteMode::ReplayByHash);
+        let replayed = cassette
+            .replay(&json!({ "input": "first", "prompt_cache_key": "session-2" }))
+            .unwrap();
+        assert_eq!(collect(replayed).await, "data: one\n\n");
+    }
+}
@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
@@ -16,13 +17,18 @@ use tracing::debug;
 use tracing::trace;
 
 use crate::ModelProviderInfo;
+use crate::cassette::Cassette;
+use crate::cassette::CassetteMode;
 use crate::client_common::Prompt;
+use crate::client_common::RequestSize;
 use crate::client_common::ResponseEvent;
 use crate::client_common::ResponseStream;
+use crate::client_common::create_response_format_for_chat;
 use crate::error::CodexErr;
 use crate::error::Result;
 use crate::flags::OPENAI_REQUEST_MAX_RETRIES;
 use crate::flags::OPENAI_STREAM_IDLE_TIMEOUT_MS;
+use crate::http_trace::HttpTrace;
 use crate::models::ContentItem;
 use crate::models::ResponseItem;
 use crate::openai_tools::create_tools_json_for_chat_completions_api;
@@ -34,6 +40,8 @@ pub(crate) async fn stream_chat_completions(
     model: &str,
     client: &reqwest::Client,
     provider: &ModelProviderInfo,
+    cassette: Option<&Cassette>,
+    http_trace: Option<Arc<HttpTrace>>,
 ) -> Result<ResponseStream> {
     // Build messages array
     let mut messages = Vec::<serde_json::Value>::new();
@@ -107,12 +115,17 @@ pub(crate) async fn stream_chat_completions(
     }
 
     let tools_json = create_tools_json_for_chat_completions_api(prompt, model)?;
-    let payload = json!({
+    let request_size = RequestSize::measure_chat(&full_instructions, &tools_json, &messages[1..]);
+    request_size.log("chat");
+    let mut payload = json!({
         "model": model,
         "messages": messages,
         "stream": true,
         "tools": tools_json,
     });
+    if let Some(output_schema) = &prompt.output_schema {
+        payload["response_format"] = create_response_format_for_chat(output_schema);
+    }
 
     let base_url = provider.base_url.trim_end_matches('/');
     let url = format!("{}/chat/completions", base_url);
@@ -122,6 +135,20 @@ pub(crate) async fn stream_chat_completions(
         serde_json::to_string_pretty(&payload).unwrap_or_default()
     );
 
+    if let Some(cassette) = cassette.filter(|c| c.is_replay()) {
+        let stream = cassette.replay(&payload)?;
+        let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(16);
+        tokio::spawn(process_chat_sse(stream, tx_event, http_trace));
+        return Ok(ResponseStream {
+            rx_event,
+            request_size,
+        });
+    }
+
+    if let Some(http_trace) = &http_trace {
+        http_trace.record_request(&url, &payload);
+    }
+
     let api_key = provider.api_key()?;
     let mut attempt = 0;
     loop {
@@ -141,8 +168,22 @@ pub(crate) async fn stream_chat_completions(
             Ok(resp) if resp.status().is_success() => {
                 let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(16);
                 let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
-                tokio::spawn(process_chat_sse(stream, tx_event));
-                return Ok(ResponseStream { rx_event });
+                match cassette {
+                    Some(cassette) if cassette.mode() == CassetteMode::Record => {
+                        tokio::spawn(process_chat_sse(
+                            cassette.record(&payload, stream)?,
+                            tx_event,
+                            http_trace,
+                        ));
+                    }
+                    _ => {
+                        tokio::spawn(process_chat_sse(stream, tx_event, http_trace));
+                    }
+                }
+                return Ok(ResponseStream {
+                    rx_event,
+                    request_size,
+                });
             }
             Ok(res) => {
                 let status = res.status();
@@ -180,8 +221,11 @@ pub(crate) async fn stream_chat_completions(
 /// Lightweight SSE processor for the Chat Completions streaming format. The
 /// output is mapped onto Codex's internal [`ResponseEvent`] so that the rest
 /// of the pipeline can stay agnostic of the underlying wire format.
-async fn process_chat_sse<S>(stream: S, tx_event: mpsc::Sender<Result<ResponseEvent>>)
-where
+async fn process_chat_sse<S>(
+    stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    http_trace: Option<Arc<HttpTrace>>,
+) where
     S: Stream<Item = Result<Bytes>> + Unpin,
 {
     let mut stream = stream.eventsource();
@@ -227,6 +271,10 @@ where
             }
         };
 
+        if let Some(http_trace) = &http_trace {
+            http_trace.record_sse_event(&sse.event, &sse.data);
+        }
+
         // OpenAI Chat streaming sends a literal string "[DONE]" when finished.
         if sse.data.trim() == "[DONE]" {
             let _ = tx_event
@@ -1,5 +1,6 @@
 use std::io::BufRead;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
@@ -16,13 +17,17 @@ use tracing::debug;
 use tracing::trace;
 use tracing::warn;
 
+use crate::cassette::Cassette;
+use crate::cassette::CassetteMode;
 use crate::chat_completions::AggregateStreamExt;
 use crate::chat_completions::stream_chat_completions;
 use crate::client_common::Prompt;
+use crate::client_common::RequestSize;
 use crate::client_common::ResponseEvent;
 use crate::client_common::ResponseStream;
 use crate::client_common::ResponsesApiRequest;
 use crate::client_common::create_reasoning_param_for_request;
+use crate::client_common::create_text_param_for_request;
 use crate::config_types::ReasoningEffort as ReasoningEffortConfig;
 use crate::config_types::ReasoningSummary as ReasoningSummaryConfig;
 use crate::error::CodexErr;
@@ -31,6 +36,7 @@ use crate::error::Result;
 use crate::flags::CODEX_RS_SSE_FIXTURE;
 use crate::flags::OPENAI_REQUEST_MAX_RETRIES;
 use crate::flags::OPENAI_STREAM_IDLE_TIMEOUT_MS;
+use crate::http_trace::HttpTrace;
 use crate::model_provider_info::ModelProviderInfo;
 use crate::model_provider_info::WireApi;
 use crate::models::ResponseItem;
@@ -44,6 +50,10 @@ pub struct ModelClient {
     provider: ModelProviderInfo,
     effort: ReasoningEffortConfig,
     summary: ReasoningSummaryConfig,
+    /// Record/replay cassette configured via `CODEX_RS_CASSETTE_DIR`.
+    cassette: Option<Arc<Cassette>>,
+    /// Trace of requests and SSE events (`--trace-http`).
+    http_trace: Option<Arc<HttpTrace>>,
 }
 
 impl ModelClient {
@@ -52,6 +62,7 @@ impl ModelClient {
         provider: ModelProviderInfo,
         effort: ReasoningEffortConfig,
         summary: ReasoningSummaryConfig,
+        http_trace: Option<Arc<HttpTrace>>,
     ) -> Self {
         Self {
             model: model.to_string(),
@@ -59,6 +70,8 @@ impl ModelClient {
             provider,
             effort,
             summary,
+            cassette: Cassette::from_env(),
+            http_trace,
         }
     }
 
@@ -70,13 +83,20 @@ impl ModelClient {
             WireApi::Responses => self.stream_responses(prompt).await,
             WireApi::Chat => {
                 // Create the raw streaming connection first.
-                let response_stream =
-                    stream_chat_completions(prompt, &self.model, &self.client, &self.provider)
-                        .await?;
+                let response_stream = stream_chat_completions(
+                    prompt,
+                    &self.model,
+                    &self.client,
+                    &self.provider,
+                    self.cassette.as_deref(),
+                    self.http_trace.clone(),
+                )
+                .await?;
 
                 // Wrap it with the aggregation adapter so callers see *only*
                 // the final assistant message per turn (matching the
                 // behaviour of the Responses API).
+                let request_size = response_stream.request_size;
                 let mut aggregated = response_stream.aggregate();
 
                 // Bridge the aggregated stream back into a standard
@@ -93,21 +113,27 @@ impl ModelClient {
                     }
                 });
 
-                Ok(ResponseStream { rx_event: rx })
+                Ok(ResponseStream {
+                    rx_event: rx,
+                    request_size,
+                })
             }
         }
     }
 
     /// Implementation for the OpenAI *Responses* experimental API.
     async fn stream_responses(&self, prompt: &Prompt) -> Result<ResponseStream> {
+        let full_instructions = prompt.get_full_instructions(&self.model);
+        let tools_json = create_tools_json_for_responses_api(prompt, &self.model)?;
+        let request_size = RequestSize::measure(&full_instructions, &tools_json, &prompt.input);
+        request_size.log("responses");
+
         if let Some(path) = &*CODEX_RS_SSE_FIXTURE {
             // short circuit for tests
             warn!(path, "Streaming from fixture");
-            return stream_from_fixture(path).await;
+            return stream_from_fixture(path, request_size).await;
         }
 
-        let full_instructions = prompt.get_full_instructions(&self.model);
-        let tools_json = create_tools_json_for_responses_api(prompt, &self.model)?;
         let reasoning = create_reasoning_param_for_request(&self.model, self.effort, self.summary);
         let payload = ResponsesApiRequest {
             model: &self.model,
@@ -120,6 +146,8 @@ impl ModelClient {
             previous_response_id: prompt.prev_id.clone(),
             store: prompt.store,
             stream: true,
+            prompt_cache_key: prompt.prompt_cache_key.as_deref(),
+            text: create_text_param_for_request(prompt.output_schema.as_ref()),
         };
 
         let base_url = self.provider.base_url.clone();
@@ -127,6 +155,20 @@ impl ModelClient {
         let url = format!("{}/responses", base_url);
         trace!("POST to {url}: {}", serde_json::to_string(&payload)?);
 
+        if let Some(cassette) = self.cassette.as_deref().filter(|c| c.is_replay()) {
+            let stream = cassette.replay(&serde_json::to_value(&payload)?)?;
+            let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(16);
+            tokio::spawn(process_sse(stream, tx_event, self.http_trace.clone()));
+            return Ok(ResponseStream {
+                rx_event,
+                request_size,
+            });
+        }
+
+        if let Some(http_trace) = &self.http_trace {
+            http_trace.record_request(&url, &serde_json::to_value(&payload)?);
+        }
+
         let mut attempt = 0;
         loop {
             attempt += 1;
@@ -152,9 +194,24 @@ impl ModelClient {
 
                     // spawn task to process SSE
                     let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
-                    tokio::spawn(process_sse(stream, tx_event));
+                    match self.cassette.as_deref() {
+                        Some(cassette) if cassette.mode() == CassetteMode::Record => {
+                            let payload = serde_json::to_value(&payload)?;
+                            tokio::spawn(process_sse(
+                                cassette.record(&payload, stream)?,
+                                tx_event,
+                                self.http_trace.clone(),
+                            ));
+                        }
+                        _ => {
+                            tokio::spawn(process_sse(stream, tx_event, self.http_trace.clone()));
+                        }
+                    }
 
-                    return Ok(ResponseStream { rx_event });
+                    return Ok(ResponseStream {
+                        rx_event,
+                        request_size,
+                    });
                 }
                 Ok(res) => {
                     let status = res.status();
@@ -212,8 +269,11 @@ struct ResponseCompleted {
     id: String,
 }
 
-async fn process_sse<S>(stream: S, tx_event: mpsc::Sender<Result<ResponseEvent>>)
-where
+async fn process_sse<S>(
+    stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    http_trace: Option<Arc<HttpTrace>>,
+) where
     S: Stream<Item = Result<Bytes>> + Unpin,
 {
     let mut stream = stream.eventsource();
@@ -256,6 +316,10 @@ where
             }
         };
 
+        if let Some(http_trace) = &http_trace {
+            http_trace.record_sse_event(&sse.event, &sse.data);
+        }
+
         let event: SseEvent = match serde_json::from_str(&sse.data) {
             Ok(event) => event,
             Err(e) => {
@@ -329,7 +393,10 @@ where
 }
 
 /// used in tests to stream from a text SSE file
-async fn stream_from_fixture(path: impl AsRef<Path>) -> Result<ResponseStream> {
+async fn stream_from_fixture(
+    path: impl AsRef<Path>,
+    request_size: RequestSize,
+) -> Result<ResponseStream> {
     let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(16);
     let f = std::fs::File::open(path.as_ref())?;
     let lines = std::io::BufReader::new(f).lines();
@@ -343,6 +410,9 @@ async fn stream_from_fixture(path: impl AsRef<Path>) -> Result<ResponseStream> {
 
     let rdr = std::io::Cursor::new(content);
     let stream = ReaderStream::new(rdr).map_err(CodexErr::Io);
-    tokio::spawn(process_sse(stream, tx_event));
-    Ok(ResponseStream { rx_event })
+    tokio::spawn(process_sse(stream, tx_event, None));
+    Ok(ResponseStream {
+        rx_event,
+        request_size,
+    })
 }
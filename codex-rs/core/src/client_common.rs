@@ -2,6 +2,7 @@ use crate::config_types::ReasoningEffort as ReasoningEffortConfig;
 use crate::config_types::ReasoningSummary as ReasoningSummaryConfig;
 use crate::error::Result;
 use crate::models::ResponseItem;
+use crate::protocol::ModelRequestSizeEvent;
 use codex_apply_patch::APPLY_PATCH_TOOL_INSTRUCTIONS;
 use futures::Stream;
 use serde::Serialize;
@@ -11,6 +12,7 @@ use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
 use tokio::sync::mpsc;
+use tracing::debug;
 
 /// The `instructions` field in the payload sent to a model should always start
 /// with this content.
@@ -33,6 +35,17 @@ pub struct Prompt {
     /// the "fully qualified" tool name (i.e., prefixed with the server name),
     /// which should be reported to the model in place of Tool::name.
     pub extra_tools: HashMap<String, mcp_types::Tool>,
+
+    /// Whether to offer the `persistent_shell` tool.
+    pub persistent_shell: bool,
+
+    /// Key that lets the provider route requests from the same session to
+    /// the same prompt cache. Only sent to the Responses API.
+    pub prompt_cache_key: Option<String>,
+
+    /// JSON schema the model's final message must conform to. The model may
+    /// still call tools; the format only constrains its text output.
+    pub output_schema: Option<serde_json::Value>,
 }
 
 impl Prompt {
@@ -48,6 +61,106 @@ impl Prompt {
     }
 }
 
+/// Serialized size, in bytes, of each part of a model request, measured on
+/// the payload as it is sent. Logged at `debug` level and reported as a
+/// `ModelRequestSize` event for every request to make slow or expensive
+/// sessions easier to diagnose.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RequestSize {
+    pub(crate) instructions: usize,
+    pub(crate) tools: usize,
+    /// Items the model has already seen in an earlier request of this
+    /// conversation (only non-empty when the full transcript is re-sent).
+    pub(crate) history: usize,
+    /// Items appended since the model's last output.
+    pub(crate) new_input: usize,
+}
+
+impl RequestSize {
+    /// Measures a Responses API request.
+    pub(crate) fn measure(
+        instructions: &str,
+        tools: &[serde_json::Value],
+        input: &[ResponseItem],
+    ) -> Self {
+        Self::measure_items(instructions, tools, input, is_model_output)
+    }
+
+    /// Measures a Chat Completions request; `messages` excludes the system
+    /// message carrying the instructions.
+    pub(crate) fn measure_chat(
+        instructions: &str,
+        tools: &[serde_json::Value],
+        messages: &[serde_json::Value],
+    ) -> Self {
+        Self::measure_items(instructions, tools, messages, |message| {
+            message["role"] == "assistant"
+        })
+    }
+
+    fn measure_items<T: Serialize>(
+        instructions: &str,
+        tools: &[serde_json::Value],
+        input: &[T],
+        is_model_output: impl Fn(&T) -> bool,
+    ) -> Self {
+        // Everything up to and including the last item produced by the model
+        // is history; whatever follows is new input for this request.
+        let split = input
+            .iter()
+            .rposition(is_model_output)
+            .map_or(0, |idx| idx + 1);
+        let (history, new_input) = input.split_at(split);
+        Self {
+            instructions: instructions.len(),
+            tools: tools.iter().map(json_len).sum(),
+            history: history.iter().map(json_len).sum(),
+            new_input: new_input.iter().map(json_len).sum(),
+        }
+    }
+
+    pub(crate) fn total(&self) -> usize {
+        self.instructions + self.tools + self.history + self.new_input
+    }
+
+    pub(crate) fn log(&self, wire_api: &str) {
+        debug!(
+            wire_api,
+            instructions_bytes = self.instructions,
+            tools_bytes = self.tools,
+            history_bytes = self.history,
+            new_input_bytes = self.new_input,
+            total_bytes = self.total(),
+            "model request size"
+        );
+    }
+}
+
+impl From<RequestSize> for ModelRequestSizeEvent {
+    fn from(size: RequestSize) -> Self {
+        Self {
+            instructions_bytes: size.instructions,
+            tools_bytes: size.tools,
+            history_bytes: size.history,
+            new_input_bytes: size.new_input,
+        }
+    }
+}
+
+fn json_len<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+}
+
+fn is_model_output(item: &ResponseItem) -> bool {
+    match item {
+        ResponseItem::Message { role, .. } => role == "assistant",
+        ResponseItem::Reasoning { .. }
+        | ResponseItem::FunctionCall { .. }
+        | ResponseItem::LocalShellCall { .. } => true,
+        ResponseItem::FunctionCallOutput { .. } | ResponseItem::Other => false,
+    }
+}
+
 #[derive(Debug)]
 pub enum ResponseEvent {
     OutputItemDone(ResponseItem),
@@ -124,6 +237,55 @@ pub(crate) struct ResponsesApiRequest<'a> {
     /// true when using the Responses API.
     pub(crate) store: bool,
     pub(crate) stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) prompt_cache_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) text: Option<TextControls<'a>>,
+}
+
+/// Name reported to the provider for the structured output format.
+const OUTPUT_SCHEMA_NAME: &str = "codex_output_schema";
+
+/// `text` parameter of the Responses API, used to request structured output.
+#[derive(Debug, Serialize)]
+pub(crate) struct TextControls<'a> {
+    pub(crate) format: TextFormat<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TextFormat<'a> {
+    #[serde(rename = "type")]
+    pub(crate) kind: &'static str,
+    pub(crate) name: &'static str,
+    pub(crate) schema: &'a serde_json::Value,
+    pub(crate) strict: bool,
+}
+
+pub(crate) fn create_text_param_for_request(
+    output_schema: Option<&serde_json::Value>,
+) -> Option<TextControls<'_>> {
+    output_schema.map(|schema| TextControls {
+        format: TextFormat {
+            kind: "json_schema",
+            name: OUTPUT_SCHEMA_NAME,
+            schema,
+            strict: true,
+        },
+    })
+}
+
+/// Chat Completions equivalent of [`create_text_param_for_request`].
+pub(crate) fn create_response_format_for_chat(
+    output_schema: &serde_json::Value,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": OUTPUT_SCHEMA_NAME,
+            "schema": output_schema,
+            "strict": true,
+        },
+    })
 }
 
 pub(crate) fn create_reasoning_param_for_request(
@@ -162,6 +324,8 @@ pub fn model_supports_reasoning_summaries(model: &str) -> bool {
 
 pub(crate) struct ResponseStream {
     pub(crate) rx_event: mpsc::Receiver<Result<ResponseEvent>>,
+    /// Size of the request this stream answers.
+    pub(crate) request_size: RequestSize,
 }
 
 impl Stream for ResponseStream {
@@ -171,3 +335,89 @@ impl Stream for ResponseStream {
         self.rx_event.poll_recv(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::models::ContentItem;
+    use crate::models::FunctionCallOutputPayload;
+    use pretty_assertions::assert_eq;
+
+    fn message(role: &str, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            role: role.to_string(),
+            content: vec![ContentItem::InputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn request_size_splits_history_from_new_input() {
+        let history = vec![
+            message("user", "list files"),
+            ResponseItem::FunctionCall {
+                name: "shell".to_string(),
+                arguments: r#"{"command":["ls"]}"#.to_string(),
+                call_id: "call_1".to_string(),
+            },
+        ];
+        let new_input = vec![
+            ResponseItem::FunctionCallOutput {
+                call_id: "call_1".to_string(),
+                output: FunctionCallOutputPayload {
+                    content: "README.md".to_string(),
+                    success: Some(true),
+                },
+            },
+            message("user", "thanks"),
+        ];
+        let tools = vec![serde_json::json!({"type": "local_shell"})];
+        let input = [history.clone(), new_input.clone()].concat();
+
+        let size = RequestSize::measure("be helpful", &tools, &input);
+
+        assert_eq!(
+            size,
+            RequestSize {
+                instructions: "be helpful".len(),
+                tools: json_len(&tools[0]),
+                history: history.iter().map(json_len).sum(),
+                new_input: new_input.iter().map(json_len).sum(),
+            }
+        );
+        assert_eq!(
+            size.total(),
+            size.instructions + size.tools + size.history + size.new_input
+        );
+    }
+
+    #[test]
+    fn chat_request_size_splits_at_last_assistant_message() {
+        let messages = vec![
+            serde_json::json!({"role": "user", "content": "list files"}),
+            serde_json::json!({"role": "assistant", "content": null, "tool_calls": []}),
+            serde_json::json!({"role": "tool", "tool_call_id": "call_1", "content": "README.md"}),
+        ];
+        let size = RequestSize::measure_chat("be helpful", &[], &messages);
+        assert_eq!(
+            size,
+            RequestSize {
+                instructions: "be helpful".len(),
+                tools: 0,
+                history: json_len(&messages[0]) + json_len(&messages[1]),
+                new_input: json_len(&messages[2]),
+            }
+        );
+    }
+
+    #[test]
+    fn request_size_without_model_output_is_all_new_input() {
+        let input = vec![message("user", "hello")];
+        let size = RequestSize::measure("", &[], &input);
+        assert_eq!(size.history, 0);
+        assert_eq!(size.new_input, json_len(&input[0]));
+    }
+}
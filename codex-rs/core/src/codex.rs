@@ -10,12 +10,13 @@ use std::sync::Mutex;
 use std::sync::atomic::AtomicU64;
 use std::time::Duration;
 
-use anyhow::Context;
 use async_channel::Receiver;
 use async_channel::Sender;
 use codex_apply_patch::AffectedPaths;
 use codex_apply_patch::ApplyPatchAction;
 use codex_apply_patch::ApplyPatchFileChange;
+use codex_apply_patch::FilePreImage;
+use codex_apply_patch::FileTransaction;
 use codex_apply_patch::MaybeApplyPatchVerified;
 use codex_apply_patch::maybe_parse_apply_patch_verified;
 use codex_apply_patch::print_summary;
@@ -34,31 +35,57 @@ use tracing::warn;
 use uuid::Uuid;
 
 use crate::WireApi;
+use crate::background_jobs::BackgroundJobs;
+use crate::background_jobs::JobStatus;
 use crate::client::ModelClient;
 use crate::client_common::Prompt;
 use crate::client_common::ResponseEvent;
 use crate::config::Config;
+use crate::config_types::ExecTimeouts;
 use crate::config_types::ShellEnvironmentPolicy;
+use crate::config_types::ShellOutputLimits;
 use crate::conversation_history::ConversationHistory;
+use crate::edit_tool::edit_file_action;
+use crate::edit_tool::write_file_action;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
 use crate::error::SandboxErr;
+use crate::exec::DEFAULT_TIMEOUT_MS;
 use crate::exec::ExecParams;
 use crate::exec::ExecToolCallOutput;
 use crate::exec::SandboxType;
+use crate::exec::StdioPolicy;
+use crate::exec::StdoutStream;
 use crate::exec::process_exec_tool_call;
+use crate::exec::spawn_command_under_sandbox;
 use crate::exec_env::create_env;
+use crate::exec_env::create_env_from_vars;
+use crate::exec_timeout::effective_timeout_ms;
 use crate::flags::OPENAI_STREAM_MAX_RETRIES;
+use crate::git_snapshot::GitSnapshots;
+use crate::http_trace::HttpTrace;
+use crate::is_safe_command::is_known_safe_command;
 use crate::mcp_connection_manager::McpConnectionManager;
 use crate::mcp_connection_manager::try_parse_fully_qualified_tool_name;
 use crate::mcp_tool_call::handle_mcp_tool_call;
+use crate::models::BackgroundJobToolCallParams;
 use crate::models::ContentItem;
+use crate::models::EditFileToolCallParams;
+use crate::models::ExecBackgroundToolCallParams;
 use crate::models::FunctionCallOutputPayload;
 use crate::models::LocalShellAction;
+use crate::models::PersistentShellToolCallParams;
 use crate::models::ReasoningItemReasoningSummary;
 use crate::models::ResponseInputItem;
 use crate::models::ResponseItem;
+use crate::models::SendInputToolCallParams;
 use crate::models::ShellToolCallParams;
+use crate::models::WriteFileToolCallParams;
+use crate::output_capture::SHELL_OUTPUT_SUBDIR;
+use crate::output_capture::file_name_for_call_id;
+use crate::output_capture::prune_shell_output;
+use crate::persistent_shell::PersistentShell;
+use crate::persistent_shell::PersistentShellOutput;
 use crate::project_doc::get_user_instructions;
 use crate::protocol::AgentMessageEvent;
 use crate::protocol::AgentReasoningEvent;
@@ -76,15 +103,28 @@ use crate::protocol::InputItem;
 use crate::protocol::Op;
 use crate::protocol::PatchApplyBeginEvent;
 use crate::protocol::PatchApplyEndEvent;
+use crate::protocol::PatchConflictEvent;
+use crate::protocol::PatchUndoneEvent;
 use crate::protocol::ReviewDecision;
 use crate::protocol::SandboxPolicy;
 use crate::protocol::SessionConfiguredEvent;
+use crate::protocol::SessionDiffEvent;
+use crate::protocol::SnapshotRestoredEvent;
 use crate::protocol::Submission;
 use crate::protocol::TaskCompleteEvent;
+use crate::redact::REDACTED;
+use crate::redact::SecretRedactor;
+use crate::redact::adds_redaction_placeholder;
 use crate::rollout::RolloutRecorder;
 use crate::safety::SafetyCheck;
 use crate::safety::assess_command_safety;
 use crate::safety::assess_patch_safety;
+use crate::safety::assess_unlisted_command_safety;
+use crate::safety::get_platform_sandbox;
+use crate::shell::UserShell;
+use crate::stale_files::FileFingerprints;
+use crate::stale_files::StaleFilesError;
+use crate::undo::UndoJournal;
 use crate::user_notification::UserNotification;
 use crate::util::backoff;
 
@@ -116,6 +156,7 @@ impl Codex {
             disable_response_storage: config.disable_response_storage,
             notify: config.notify.clone(),
             cwd: config.cwd.clone(),
+            output_schema: config.output_schema.clone(),
         };
 
         let config = Arc::new(config);
@@ -177,6 +218,23 @@ pub(crate) struct Session {
     approval_policy: AskForApproval,
     sandbox_policy: SandboxPolicy,
     shell_environment_policy: ShellEnvironmentPolicy,
+    shell_output_limits: ShellOutputLimits,
+    exec_timeouts: ExecTimeouts,
+    /// Directory holding the complete output of truncated shell commands.
+    shell_output_dir: PathBuf,
+    /// Whether the `persistent_shell` tool is offered to the model.
+    persistent_shell_enabled: bool,
+    /// Shell backing the `persistent_shell` tool, spawned on first use.
+    persistent_shell: tokio::sync::Mutex<Option<PersistentShell>>,
+    /// Jobs started with `exec_background`; killed when the session ends.
+    background_jobs: BackgroundJobs,
+    /// The user's shell, if commands should run in it (`login_shell`).
+    user_shell: Option<UserShell>,
+    /// Environment of the user's login shell, captured at session start and
+    /// used in place of that of the Codex process.
+    env_snapshot: Option<HashMap<String, String>>,
+    /// Applied to command output before it is shown or sent to the model.
+    secret_redactor: SecretRedactor,
     writable_roots: Mutex<Vec<PathBuf>>,
 
     /// Manager for external MCP servers/tools.
@@ -191,6 +249,18 @@ pub(crate) struct Session {
     rollout: Mutex<Option<crate::rollout::RolloutRecorder>>,
     state: Mutex<State>,
     codex_linux_sandbox_exe: Option<PathBuf>,
+
+    /// Unique ID for this session, also used as the prompt cache key.
+    session_id: Uuid,
+
+    /// Structured output format requested for the final assistant message.
+    output_schema: Option<serde_json::Value>,
+
+    /// Pre-images of every patch applied in this session, for `UndoLastPatch`.
+    undo_journal: UndoJournal,
+
+    /// Per-turn working tree snapshots; `None` outside a git repo.
+    git_snapshots: Option<GitSnapshots>,
 }
 
 impl Session {
@@ -199,6 +269,26 @@ impl Session {
             .map(PathBuf::from)
             .map_or_else(|| self.cwd.clone(), |p| self.cwd.join(p))
     }
+
+    fn create_env(&self) -> HashMap<String, String> {
+        match &self.env_snapshot {
+            Some(vars) => create_env_from_vars(vars, &self.shell_environment_policy),
+            None => create_env(&self.shell_environment_policy),
+        }
+    }
+
+    /// Returns `command` rewritten to run in the user's shell when
+    /// `login_shell` is enabled. Only applied when spawning, so that approval
+    /// and the UI see the command as the model wrote it. Known-safe commands
+    /// are left alone: they may run without the sandbox, and the rc file
+    /// would run there with them.
+    fn command_for_user_shell(&self, command: &[String]) -> Vec<String> {
+        self.user_shell
+            .as_ref()
+            .filter(|_| !is_known_safe_command(command))
+            .and_then(|shell| shell.wrap_command(command))
+            .unwrap_or_else(|| command.to_vec())
+    }
 }
 
 /// Mutable state of the agent
@@ -210,6 +300,8 @@ struct State {
     pending_approvals: HashMap<String, oneshot::Sender<ReviewDecision>>,
     pending_input: Vec<ResponseInputItem>,
     zdr_transcript: Option<ConversationHistory>,
+    /// Number of user turns started so far; the next snapshot is `turn + 1`.
+    turn: u64,
 }
 
 impl Session {
@@ -230,6 +322,21 @@ impl Session {
         }
     }
 
+    /// Records a git snapshot of the working tree for the turn that is about
+    /// to start. Failures are logged and otherwise ignored.
+    async fn snapshot_turn(&self) {
+        let turn = {
+            let mut state = self.state.lock().unwrap();
+            state.turn += 1;
+            state.turn
+        };
+        if let Some(snapshots) = &self.git_snapshots
+            && let Err(e) = snapshots.create(turn).await
+        {
+            warn!("failed to snapshot working tree for turn {turn}: {e:#}");
+        }
+    }
+
     /// Sends the given event to the client and swallows the send event, if
     /// any, logging it as an error.
     pub(crate) async fn send_event(&self, event: Event) {
@@ -243,6 +350,7 @@ impl Session {
         sub_id: String,
         command: Vec<String>,
         cwd: PathBuf,
+        stdin: Option<String>,
         reason: Option<String>,
     ) -> oneshot::Receiver<ReviewDecision> {
         let (tx_approve, rx_approve) = oneshot::channel();
@@ -251,6 +359,7 @@ impl Session {
             msg: EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
                 command,
                 cwd,
+                stdin,
                 reason,
             }),
         };
@@ -333,6 +442,7 @@ impl Session {
                 call_id: call_id.to_string(),
                 command: params.command.clone(),
                 cwd: params.cwd.clone(),
+                timeout_ms: params.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS),
             }),
         };
         let _ = self.tx_event.send(event).await;
@@ -361,6 +471,25 @@ impl Session {
         let _ = self.tx_event.send(event).await;
     }
 
+    /// Redacts secrets from the output of a command before it is shown or
+    /// sent to the model, and tells the user if anything was redacted.
+    async fn redact_exec_output(
+        &self,
+        sub_id: &str,
+        mut output: ExecToolCallOutput,
+    ) -> ExecToolCallOutput {
+        let redacted_stdout = self.secret_redactor.redact_in_place(&mut output.stdout);
+        let redacted_stderr = self.secret_redactor.redact_in_place(&mut output.stderr);
+        if redacted_stdout || redacted_stderr {
+            self.notify_background_event(
+                sub_id,
+                "Redacted what looks like secrets from the command output",
+            )
+            .await;
+        }
+        output
+    }
+
     /// Helper that emits a BackgroundEvent with the given message. This keeps
     /// the call‑sites terse so adding more diagnostics does not clutter the
     /// core agent logic.
@@ -464,6 +593,7 @@ impl State {
             } else {
                 None
             },
+            turn: self.turn,
             ..Default::default()
         }
     }
@@ -564,6 +694,7 @@ async fn submission_loop(
                 disable_response_storage,
                 notify,
                 cwd,
+                output_schema,
             } => {
                 info!("Configuring session: model={model}; provider={provider:?}");
                 if !cwd.is_absolute() {
@@ -579,11 +710,22 @@ async fn submission_loop(
                     return;
                 }
 
+                let http_trace = match &config.trace_http {
+                    Some(path) => match HttpTrace::open(path) {
+                        Ok(trace) => Some(Arc::new(trace)),
+                        Err(e) => {
+                            warn!("failed to open HTTP trace file {}: {e}", path.display());
+                            None
+                        }
+                    },
+                    None => None,
+                };
                 let client = ModelClient::new(
                     model.clone(),
                     provider.clone(),
                     model_reasoning_effort,
                     model_reasoning_summary,
+                    http_trace,
                 );
 
                 // abort any current running session and clone its state
@@ -608,6 +750,14 @@ async fn submission_loop(
                 };
 
                 let writable_roots = Mutex::new(get_writable_roots(&cwd));
+                let git_snapshots = GitSnapshots::for_cwd(&cwd, session_id).await;
+                if let Some(snapshots) = git_snapshots.clone() {
+                    tokio::spawn(async move {
+                        if let Err(e) = snapshots.prune_expired().await {
+                            tracing::warn!("failed to prune old git snapshots: {e}");
+                        }
+                    });
+                }
 
                 // Error messages to dispatch after SessionConfigured is sent.
                 let mut mcp_connection_errors = Vec::<Event>::new();
@@ -652,6 +802,32 @@ async fn submission_loop(
                         }
                     };
 
+                // Saved command output is only useful while its session is
+                // fresh; clean up after earlier sessions in the background.
+                let shell_output_root = config.codex_home.join(SHELL_OUTPUT_SUBDIR);
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = prune_shell_output(&shell_output_root) {
+                        tracing::warn!("failed to prune old shell output: {e}");
+                    }
+                });
+
+                let (user_shell, env_snapshot) = if config.login_shell {
+                    match UserShell::detect() {
+                        Some(shell) => {
+                            let env_snapshot = shell.snapshot_env().await;
+                            (Some(shell), env_snapshot)
+                        }
+                        None => {
+                            tracing::warn!(
+                                "login_shell is enabled, but the user's shell is not bash or zsh"
+                            );
+                            (None, None)
+                        }
+                    }
+                } else {
+                    (None, None)
+                };
+
                 sess = Some(Arc::new(Session {
                     client,
                     tx_event: tx_event.clone(),
@@ -660,6 +836,18 @@ async fn submission_loop(
                     approval_policy,
                     sandbox_policy,
                     shell_environment_policy: config.shell_environment_policy.clone(),
+                    shell_output_limits: config.shell_output,
+                    exec_timeouts: config.exec_timeouts.clone(),
+                    shell_output_dir: config
+                        .codex_home
+                        .join(SHELL_OUTPUT_SUBDIR)
+                        .join(session_id.to_string()),
+                    persistent_shell_enabled: config.persistent_shell,
+                    persistent_shell: tokio::sync::Mutex::new(None),
+                    background_jobs: BackgroundJobs::default(),
+                    user_shell,
+                    secret_redactor: config.secret_redaction.clone(),
+                    env_snapshot,
                     cwd,
                     writable_roots,
                     mcp_connection_manager,
@@ -667,6 +855,10 @@ async fn submission_loop(
                     state: Mutex::new(state),
                     rollout: Mutex::new(rollout_recorder),
                     codex_linux_sandbox_exe: config.codex_linux_sandbox_exe.clone(),
+                    session_id,
+                    output_schema,
+                    undo_journal: UndoJournal::new(&config.codex_home, session_id),
+                    git_snapshots,
                 }));
 
                 // Gather history metadata for SessionConfiguredEvent.
@@ -776,11 +968,77 @@ async fn submission_loop(
                     }
                 });
             }
+            Op::UndoLastPatch => {
+                let sess = match sess.as_ref() {
+                    Some(sess) => sess,
+                    None => {
+                        send_no_session_event(sub.id).await;
+                        continue;
+                    }
+                };
+                let msg = match sess.undo_journal.undo_last() {
+                    Ok(Some(restored)) => EventMsg::PatchUndone(PatchUndoneEvent { restored }),
+                    Ok(None) => EventMsg::Error(ErrorEvent {
+                        message: "No patches to undo in this session.".to_string(),
+                    }),
+                    Err(e) => EventMsg::Error(ErrorEvent {
+                        message: format!("Failed to undo last patch: {e:#}"),
+                    }),
+                };
+                let event = Event { id: sub.id, msg };
+                if let Err(e) = tx_event.send(event).await {
+                    error!("failed to send event: {e:?}");
+                }
+            }
+            Op::RestoreSnapshot { turn } => {
+                let sess = match sess.as_ref() {
+                    Some(sess) => sess,
+                    None => {
+                        send_no_session_event(sub.id).await;
+                        continue;
+                    }
+                };
+                let msg = match &sess.git_snapshots {
+                    Some(snapshots) => match snapshots.restore(turn).await {
+                        Ok(()) => EventMsg::SnapshotRestored(SnapshotRestoredEvent { turn }),
+                        Err(e) => EventMsg::Error(ErrorEvent {
+                            message: format!("Failed to restore snapshot: {e:#}"),
+                        }),
+                    },
+                    None => no_git_snapshots_error(),
+                };
+                sess.send_event(Event { id: sub.id, msg }).await;
+            }
+            Op::GetSessionDiff => {
+                let sess = match sess.as_ref() {
+                    Some(sess) => sess,
+                    None => {
+                        send_no_session_event(sub.id).await;
+                        continue;
+                    }
+                };
+                let msg = match &sess.git_snapshots {
+                    Some(snapshots) => match snapshots.diff_since_start().await {
+                        Ok(diff) => EventMsg::SessionDiff(SessionDiffEvent { diff }),
+                        Err(e) => EventMsg::Error(ErrorEvent {
+                            message: format!("Failed to compute session diff: {e:#}"),
+                        }),
+                    },
+                    None => no_git_snapshots_error(),
+                };
+                sess.send_event(Event { id: sub.id, msg }).await;
+            }
         }
     }
     debug!("Agent loop exited");
 }
 
+fn no_git_snapshots_error() -> EventMsg {
+    EventMsg::Error(ErrorEvent {
+        message: "Snapshots are only available for sessions inside a git repository.".to_string(),
+    })
+}
+
 /// Takes a user message as input and runs a loop where, at each turn, the model
 /// replies with either:
 ///
@@ -806,6 +1064,8 @@ async fn run_task(sess: Arc<Session>, sub_id: String, input: Vec<InputItem>) {
         return;
     }
 
+    sess.snapshot_turn().await;
+
     let initial_input_for_turn = ResponseInputItem::from(input);
     sess.record_conversation_items(&[initial_input_for_turn.clone().into()])
         .await;
@@ -1010,6 +1270,9 @@ async fn run_turn(
         user_instructions: sess.instructions.clone(),
         store,
         extra_tools,
+        persistent_shell: sess.persistent_shell_enabled,
+        prompt_cache_key: Some(sess.session_id.to_string()),
+        output_schema: sess.output_schema.clone(),
     };
 
     let mut retries = 0;
@@ -1063,6 +1326,13 @@ async fn try_run_turn(
     prompt: &Prompt,
 ) -> CodexResult<Vec<ProcessedResponseItem>> {
     let mut stream = sess.client.clone().stream(prompt).await?;
+    let _ = sess
+        .tx_event
+        .send(Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::ModelRequestSize(stream.request_size.into()),
+        })
+        .await;
 
     // Buffer all the incoming messages from the stream first, then execute them.
     // If we execute a function call in the middle of handling the stream, it can time out.
@@ -1140,6 +1410,8 @@ async fn handle_response_item(
                 command: action.command,
                 workdir: action.working_directory,
                 timeout_ms: action.timeout_ms,
+                tty: false,
+                stdin: None,
             };
             let effective_call_id = match (call_id, id) {
                 (Some(call_id), _) => call_id,
@@ -1156,7 +1428,7 @@ async fn handle_response_item(
                 }
             };
 
-            let exec_params = to_exec_params(params, sess);
+            let exec_params = to_exec_params(params, sess, &effective_call_id);
             Some(
                 handle_container_exec_with_params(
                     exec_params,
@@ -1193,6 +1465,50 @@ async fn handle_function_call(
             };
             handle_container_exec_with_params(params, sess, sub_id, call_id).await
         }
+        "persistent_shell" if sess.persistent_shell_enabled => {
+            match serde_json::from_str::<PersistentShellToolCallParams>(&arguments) {
+                Ok(params) => handle_persistent_shell_call(sess, sub_id, call_id, params).await,
+                Err(e) => invalid_arguments_output(call_id, e),
+            }
+        }
+        "exec_background" => match serde_json::from_str::<ExecBackgroundToolCallParams>(&arguments)
+        {
+            Ok(params) => handle_exec_background_call(sess, sub_id, call_id, params).await,
+            Err(e) => invalid_arguments_output(call_id, e),
+        },
+        "read_output" | "kill" => {
+            match serde_json::from_str::<BackgroundJobToolCallParams>(&arguments) {
+                Ok(BackgroundJobToolCallParams { job_id }) => {
+                    handle_background_job_call(sess, &name, call_id, &job_id)
+                }
+                Err(e) => invalid_arguments_output(call_id, e),
+            }
+        }
+        "send_input" => match serde_json::from_str::<SendInputToolCallParams>(&arguments) {
+            Ok(params) => handle_send_input_call(sess, sub_id, call_id, params).await,
+            Err(e) => invalid_arguments_output(call_id, e),
+        },
+        "edit_file" | "write_file" => {
+            let action = match name.as_str() {
+                "edit_file" => serde_json::from_str::<EditFileToolCallParams>(&arguments)
+                    .map_err(|e| format!("failed to parse function arguments: {e}"))
+                    .and_then(|params| edit_file_action(params, &sess.cwd)),
+                _ => serde_json::from_str::<WriteFileToolCallParams>(&arguments)
+                    .map_err(|e| format!("failed to parse function arguments: {e}"))
+                    .and_then(|params| write_file_action(params, &sess.cwd)),
+            };
+            match action {
+                Ok(action) => apply_patch(sess, sub_id, call_id, action).await,
+                // Return to the model for resample.
+                Err(content) => ResponseInputItem::FunctionCallOutput {
+                    call_id,
+                    output: FunctionCallOutputPayload {
+                        content: format!("error: {content}"),
+                        success: Some(false),
+                    },
+                },
+            }
+        }
         _ => {
             match try_parse_fully_qualified_tool_name(&name) {
                 Some((server, tool_name)) => {
@@ -1218,12 +1534,17 @@ async fn handle_function_call(
     }
 }
 
-fn to_exec_params(params: ShellToolCallParams, sess: &Session) -> ExecParams {
+fn to_exec_params(params: ShellToolCallParams, sess: &Session, call_id: &str) -> ExecParams {
+    let timeout_ms = effective_timeout_ms(&sess.exec_timeouts, &params.command, params.timeout_ms);
     ExecParams {
         command: params.command,
         cwd: sess.resolve_path(params.workdir.clone()),
-        timeout_ms: params.timeout_ms,
-        env: create_env(&sess.shell_environment_policy),
+        timeout_ms: Some(timeout_ms),
+        env: sess.create_env(),
+        output_limits: sess.shell_output_limits,
+        full_output_dir: Some(sess.shell_output_dir.join(file_name_for_call_id(call_id))),
+        tty: params.tty,
+        stdin: params.stdin,
     }
 }
 
@@ -1234,7 +1555,7 @@ fn parse_container_exec_arguments(
 ) -> Result<ExecParams, ResponseInputItem> {
     // parse command
     match serde_json::from_str::<ShellToolCallParams>(&arguments) {
-        Ok(shell_tool_call_params) => Ok(to_exec_params(shell_tool_call_params, sess)),
+        Ok(shell_tool_call_params) => Ok(to_exec_params(shell_tool_call_params, sess, call_id)),
         Err(e) => {
             // allow model to re-sample
             let output = ResponseInputItem::FunctionCallOutput {
@@ -1278,7 +1599,87 @@ async fn handle_container_exec_with_params(
         MaybeApplyPatchVerified::NotApplyPatch => (),
     }
 
-    // safety checks
+    let sandbox_type = match approve_command(sess, &params, &sub_id, &call_id).await {
+        Ok(sandbox_type) => sandbox_type,
+        Err(rejected) => return rejected,
+    };
+
+    sess.notify_exec_command_begin(&sub_id, &call_id, &params)
+        .await;
+
+    let output_result = process_exec_tool_call(
+        ExecParams {
+            command: sess.command_for_user_shell(&params.command),
+            ..params.clone()
+        },
+        sandbox_type,
+        sess.ctrl_c.clone(),
+        &sess.sandbox_policy,
+        &sess.codex_linux_sandbox_exe,
+        Some(StdoutStream {
+            sub_id: sub_id.clone(),
+            call_id: call_id.clone(),
+            tx_event: sess.tx_event.clone(),
+            redactor: sess.secret_redactor.clone(),
+        }),
+    )
+    .await;
+
+    match output_result {
+        Ok(output) => {
+            let ExecToolCallOutput {
+                exit_code,
+                stdout,
+                stderr,
+                duration,
+            } = sess.redact_exec_output(&sub_id, output).await;
+
+            sess.notify_exec_command_end(&sub_id, &call_id, &stdout, &stderr, exit_code)
+                .await;
+
+            let is_success = exit_code == 0;
+            let content = format_exec_output(
+                if is_success { &stdout } else { &stderr },
+                exit_code,
+                duration,
+            );
+
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content,
+                    success: Some(is_success),
+                },
+            }
+        }
+        Err(CodexErr::Sandbox(error)) => {
+            handle_sanbox_error(error, sandbox_type, params, sess, sub_id, call_id).await
+        }
+        Err(e) => {
+            // Handle non-sandbox errors
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content: format!("execution error: {e}"),
+                    success: None,
+                },
+            }
+        }
+    }
+}
+
+/// Runs the safety checks for `params`, asking the user if needed. Returns
+/// the sandbox to run the command in, or the tool output to send back to the
+/// model if the command was rejected.
+async fn approve_command(
+    sess: &Session,
+    params: &ExecParams,
+    sub_id: &str,
+    call_id: &str,
+) -> Result<SandboxType, ResponseInputItem> {
+    // `stdin` is not assessed as a command, but since it may be a script for
+    // the command to run, earlier approvals of the command do not cover it.
+    // It is shown to the user if they are asked.
     let safety = {
         let state = sess.state.lock().unwrap();
         assess_command_safety(
@@ -1286,100 +1687,403 @@ async fn handle_container_exec_with_params(
             sess.approval_policy,
             &sess.sandbox_policy,
             &state.approved_commands,
+            params.stdin.as_deref(),
         )
     };
-    let sandbox_type = match safety {
-        SafetyCheck::AutoApprove { sandbox_type } => sandbox_type,
+    match safety {
+        SafetyCheck::AutoApprove { sandbox_type } => Ok(sandbox_type),
         SafetyCheck::AskUser => {
             let rx_approve = sess
                 .request_command_approval(
-                    sub_id.clone(),
+                    sub_id.to_string(),
                     params.command.clone(),
                     params.cwd.clone(),
+                    params.stdin.clone(),
                     None,
                 )
                 .await;
             match rx_approve.await.unwrap_or_default() {
                 ReviewDecision::Approved => (),
                 ReviewDecision::ApprovedForSession => {
-                    sess.add_approved_command(params.command.clone());
+                    // The approval does not extend to other input on stdin.
+                    if params.stdin.is_none() {
+                        sess.add_approved_command(params.command.clone());
+                    }
                 }
                 ReviewDecision::Denied | ReviewDecision::Abort => {
-                    return ResponseInputItem::FunctionCallOutput {
-                        call_id,
+                    return Err(ResponseInputItem::FunctionCallOutput {
+                        call_id: call_id.to_string(),
                         output: crate::models::FunctionCallOutputPayload {
                             content: "exec command rejected by user".to_string(),
                             success: None,
                         },
-                    };
+                    });
                 }
             }
             // No sandboxing is applied because the user has given
             // explicit approval. Often, we end up in this case because
             // the command cannot be run in a sandbox, such as
             // installing a new dependency that requires network access.
-            SandboxType::None
+            Ok(SandboxType::None)
         }
-        SafetyCheck::Reject { reason } => {
-            return ResponseInputItem::FunctionCallOutput {
-                call_id,
-                output: crate::models::FunctionCallOutputPayload {
-                    content: format!("exec command rejected: {reason}"),
-                    success: None,
-                },
+        SafetyCheck::Reject { reason } => Err(ResponseInputItem::FunctionCallOutput {
+            call_id: call_id.to_string(),
+            output: crate::models::FunctionCallOutputPayload {
+                content: format!("exec command rejected: {reason}"),
+                success: None,
+            },
+        }),
+    }
+}
+
+fn invalid_arguments_output(call_id: String, e: serde_json::Error) -> ResponseInputItem {
+    ResponseInputItem::FunctionCallOutput {
+        call_id,
+        output: FunctionCallOutputPayload {
+            content: format!("failed to parse function arguments: {e}"),
+            success: None,
+        },
+    }
+}
+
+async fn handle_exec_background_call(
+    sess: &Session,
+    sub_id: String,
+    call_id: String,
+    params: ExecBackgroundToolCallParams,
+) -> ResponseInputItem {
+    let ExecBackgroundToolCallParams {
+        command,
+        workdir,
+        tty,
+    } = params;
+    let params = ExecParams {
+        command,
+        cwd: sess.resolve_path(workdir),
+        timeout_ms: None,
+        env: sess.create_env(),
+        output_limits: sess.shell_output_limits,
+        full_output_dir: None,
+        tty,
+        stdin: None,
+    };
+    let sandbox_type = match approve_command(sess, &params, &sub_id, &call_id).await {
+        Ok(sandbox_type) => sandbox_type,
+        Err(rejected) => return rejected,
+    };
+
+    let ExecParams {
+        command, cwd, env, ..
+    } = params;
+    let started = async {
+        let child = spawn_command_under_sandbox(
+            sess.command_for_user_shell(&command),
+            cwd.clone(),
+            env,
+            sandbox_type,
+            &sess.sandbox_policy,
+            &sess.codex_linux_sandbox_exe,
+            if tty {
+                StdioPolicy::Pty
+            } else {
+                StdioPolicy::RedirectForShellTool
+            },
+        )
+        .await?;
+        let job_id = sess
+            .background_jobs
+            .start(
+                child,
+                command,
+                cwd,
+                sandbox_type != SandboxType::None,
+                sub_id,
+                sess.tx_event.clone(),
+            )
+            .await?;
+        Ok::<_, CodexErr>(job_id)
+    }
+    .await;
+
+    let (content, success) = match started {
+        Ok(job_id) => (
+            format!("started background job {job_id}; use read_output to see its output"),
+            true,
+        ),
+        Err(e) => (format!("execution error: {e}"), false),
+    };
+    ResponseInputItem::FunctionCallOutput {
+        call_id,
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    }
+}
+
+/// Handles `send_input`. Input for a job that runs outside the sandbox, such
+/// as an interpreter the user approved, is just as unconfined, so the user is
+/// asked for each one unless they never want to be asked.
+async fn handle_send_input_call(
+    sess: &Session,
+    sub_id: String,
+    call_id: String,
+    params: SendInputToolCallParams,
+) -> ResponseInputItem {
+    let SendInputToolCallParams { job_id, input } = params;
+    if sess.approval_policy != AskForApproval::Never
+        && let Some((command, cwd)) = sess.background_jobs.unsandboxed_command(&job_id)
+    {
+        let rx_approve = sess
+            .request_command_approval(
+                sub_id,
+                command,
+                cwd,
+                Some(input.clone()),
+                Some(format!(
+                    "Input for background job {job_id}, which runs outside the sandbox"
+                )),
+            )
+            .await;
+        match rx_approve.await.unwrap_or_default() {
+            ReviewDecision::Approved | ReviewDecision::ApprovedForSession => (),
+            ReviewDecision::Denied | ReviewDecision::Abort => {
+                return ResponseInputItem::FunctionCallOutput {
+                    call_id,
+                    output: FunctionCallOutputPayload {
+                        content: "input rejected by user".to_string(),
+                        success: None,
+                    },
+                };
+            }
+        }
+    }
+
+    let (content, success) = match sess.background_jobs.send_input(&job_id, &input).await {
+        Some(Ok(())) => (format!("sent input to {job_id}"), true),
+        Some(Err(e)) => (format!("error: {e}"), false),
+        None => (format!("error: no background job with id {job_id}"), false),
+    };
+    ResponseInputItem::FunctionCallOutput {
+        call_id,
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    }
+}
+
+/// Handles `read_output` and `kill`.
+fn handle_background_job_call(
+    sess: &Session,
+    tool: &str,
+    call_id: String,
+    job_id: &str,
+) -> ResponseInputItem {
+    let result = if tool == "kill" {
+        sess.background_jobs
+            .kill(job_id)
+            .map(|status| match status {
+                JobStatus::Running => format!("stopping {job_id}"),
+                JobStatus::Exited(_) => format!("{job_id} had already exited"),
+            })
+    } else {
+        sess.background_jobs
+            .read(job_id)
+            .map(|output| output.format_for_model(&sess.secret_redactor))
+    };
+    let (content, success) = match result {
+        Some(content) => (content, true),
+        None => (format!("error: no background job with id {job_id}"), false),
+    };
+    ResponseInputItem::FunctionCallOutput {
+        call_id,
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    }
+}
+
+/// Like [`approve_command`], but without the shortcuts for known-safe and
+/// previously approved commands: earlier commands in the shell may have
+/// redefined `ls` or changed `PATH`, so neither says anything about what a
+/// command will do there. An approval covers this command only.
+async fn approve_persistent_shell_command(
+    sess: &Session,
+    params: &ExecParams,
+    sandbox_type: SandboxType,
+    sub_id: &str,
+    call_id: &str,
+) -> Result<(), ResponseInputItem> {
+    let rejected = |content: String| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.to_string(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: None,
+        },
+    };
+    match assess_unlisted_command_safety(sess.approval_policy, &sess.sandbox_policy) {
+        SafetyCheck::AutoApprove { .. } => Ok(()),
+        SafetyCheck::AskUser => {
+            let reason = if sandbox_type == SandboxType::None {
+                "Runs in the persistent shell, outside the sandbox"
+            } else {
+                "Runs in the persistent shell, which stays sandboxed even if approved"
             };
+            let rx_approve = sess
+                .request_command_approval(
+                    sub_id.to_string(),
+                    params.command.clone(),
+                    params.cwd.clone(),
+                    None,
+                    Some(reason.to_string()),
+                )
+                .await;
+            match rx_approve.await.unwrap_or_default() {
+                ReviewDecision::Approved | ReviewDecision::ApprovedForSession => Ok(()),
+                ReviewDecision::Denied | ReviewDecision::Abort => {
+                    Err(rejected("exec command rejected by user".to_string()))
+                }
+            }
         }
+        SafetyCheck::Reject { reason } => Err(rejected(format!("exec command rejected: {reason}"))),
+    }
+}
+
+async fn handle_persistent_shell_call(
+    sess: &Session,
+    sub_id: String,
+    call_id: String,
+    params: PersistentShellToolCallParams,
+) -> ResponseInputItem {
+    let PersistentShellToolCallParams {
+        command,
+        timeout_ms,
+        restart,
+    } = params;
+    if restart {
+        // Dropping the shell kills it.
+        *sess.persistent_shell.lock().await = None;
+    }
+    let Some(command) = command else {
+        let content = if restart {
+            "started a fresh shell"
+        } else {
+            "error: `command` is required unless `restart` is set"
+        };
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: FunctionCallOutputPayload {
+                content: content.to_string(),
+                success: Some(restart),
+            },
+        };
     };
 
+    let mut shell = sess.persistent_shell.lock().await;
+    // `env` is only used when the shell is spawned; the shell keeps its own
+    // environment and working directory afterwards.
+    let shell_command = vec!["bash".to_string(), "-lc".to_string(), command.clone()];
+    let timeout_ms = effective_timeout_ms(&sess.exec_timeouts, &shell_command, timeout_ms);
+    let params = ExecParams {
+        command: shell_command,
+        cwd: shell
+            .as_ref()
+            .map_or_else(|| sess.cwd.clone(), |shell| shell.cwd().to_path_buf()),
+        timeout_ms: Some(timeout_ms),
+        env: sess.create_env(),
+        output_limits: sess.shell_output_limits,
+        full_output_dir: Some(sess.shell_output_dir.join(file_name_for_call_id(&call_id))),
+        tty: false,
+        stdin: None,
+    };
+    // The shell runs in the session's sandbox no matter how the command was
+    // approved.
+    let sandbox_type = if sess.sandbox_policy.is_unrestricted() {
+        SandboxType::None
+    } else {
+        get_platform_sandbox().unwrap_or(SandboxType::None)
+    };
+    if let Err(rejected) =
+        approve_persistent_shell_command(sess, &params, sandbox_type, &sub_id, &call_id).await
+    {
+        return rejected;
+    }
+
     sess.notify_exec_command_begin(&sub_id, &call_id, &params)
         .await;
 
-    let output_result = process_exec_tool_call(
-        params.clone(),
-        sandbox_type,
-        sess.ctrl_c.clone(),
-        &sess.sandbox_policy,
-        &sess.codex_linux_sandbox_exe,
-    )
+    let result = async {
+        let mut running = match shell.take() {
+            Some(running) => running,
+            None => {
+                PersistentShell::spawn(
+                    params.cwd.clone(),
+                    params.env.clone(),
+                    sandbox_type,
+                    &sess.sandbox_policy,
+                    &sess.codex_linux_sandbox_exe,
+                )
+                .await?
+            }
+        };
+        let output = running
+            .run(
+                &command,
+                params.timeout_ms,
+                sess.ctrl_c.clone(),
+                params.output_limits,
+                params.full_output_dir.clone(),
+                &sess.secret_redactor,
+            )
+            .await?;
+        // After a timeout or interrupt the shell was killed, and after any
+        // other error its state is unknown, so it is only kept on success.
+        if !output.shell_exited {
+            *shell = Some(running);
+        }
+        Ok::<_, CodexErr>(output)
+    }
     .await;
 
-    match output_result {
-        Ok(output) => {
+    match result {
+        Ok(PersistentShellOutput {
+            output,
+            shell_exited,
+        }) => {
             let ExecToolCallOutput {
                 exit_code,
                 stdout,
                 stderr,
                 duration,
-            } = output;
-
+            } = sess.redact_exec_output(&sub_id, output).await;
             sess.notify_exec_command_end(&sub_id, &call_id, &stdout, &stderr, exit_code)
                 .await;
 
             let is_success = exit_code == 0;
-            let content = format_exec_output(
-                if is_success { &stdout } else { &stderr },
-                exit_code,
-                duration,
-            );
-
+            let mut text = if is_success { stdout } else { stderr };
+            if shell_exited {
+                text.push_str("\n[the shell exited; the next command will run in a fresh shell]");
+            }
             ResponseInputItem::FunctionCallOutput {
                 call_id,
                 output: FunctionCallOutputPayload {
-                    content,
+                    content: format_exec_output(&text, exit_code, duration),
                     success: Some(is_success),
                 },
             }
         }
-        Err(CodexErr::Sandbox(error)) => {
-            handle_sanbox_error(error, sandbox_type, params, sess, sub_id, call_id).await
-        }
         Err(e) => {
-            // Handle non-sandbox errors
+            sess.notify_exec_command_end(&sub_id, &call_id, "", &e.to_string(), -1)
+                .await;
             ResponseInputItem::FunctionCallOutput {
                 call_id,
                 output: FunctionCallOutputPayload {
-                    content: format!("execution error: {e}"),
-                    success: None,
+                    content: format!(
+                        "execution error: {e}; the shell was stopped and the next command will run in a fresh shell"
+                    ),
+                    success: Some(false),
                 },
             }
         }
@@ -1394,6 +2098,16 @@ async fn handle_sanbox_error(
     sub_id: String,
     call_id: String,
 ) -> ResponseInputItem {
+    // The error carries the command's output, which is shown to the model.
+    let error = match error {
+        SandboxErr::Denied(exit_code, stdout, stderr) => SandboxErr::Denied(
+            exit_code,
+            sess.secret_redactor.redact(&stdout).into_owned(),
+            sess.secret_redactor.redact(&stderr).into_owned(),
+        ),
+        error => error,
+    };
+
     // Early out if the user never wants to be asked for approval; just return to the model immediately
     if sess.approval_policy == AskForApproval::Never {
         return ResponseInputItem::FunctionCallOutput {
@@ -1417,6 +2131,7 @@ async fn handle_sanbox_error(
             sub_id.clone(),
             params.command.clone(),
             params.cwd.clone(),
+            params.stdin.clone(),
             Some("command failed; retry without sandbox?".to_string()),
         )
         .await;
@@ -1427,7 +2142,9 @@ async fn handle_sanbox_error(
             // remainder of the session so future
             // executions skip the sandbox directly.
             // TODO(ragona): Isn't this a bug? It always saves the command in an | fork?
-            sess.add_approved_command(params.command.clone());
+            if params.stdin.is_none() {
+                sess.add_approved_command(params.command.clone());
+            }
             // Inform UI we are retrying without sandbox.
             sess.notify_background_event(&sub_id, "retrying command without sandbox")
                 .await;
@@ -1440,11 +2157,20 @@ async fn handle_sanbox_error(
             // This is an escalated retry; the policy will not be
             // examined and the sandbox has been set to `None`.
             let retry_output_result = process_exec_tool_call(
-                params,
+                ExecParams {
+                    command: sess.command_for_user_shell(&params.command),
+                    ..params
+                },
                 SandboxType::None,
                 sess.ctrl_c.clone(),
                 &sess.sandbox_policy,
                 &sess.codex_linux_sandbox_exe,
+                Some(StdoutStream {
+                    sub_id: sub_id.clone(),
+                    call_id: retry_call_id.clone(),
+                    tx_event: sess.tx_event.clone(),
+                    redactor: sess.secret_redactor.clone(),
+                }),
             )
             .await;
 
@@ -1455,7 +2181,7 @@ async fn handle_sanbox_error(
                         stdout,
                         stderr,
                         duration,
-                    } = retry_output;
+                    } = sess.redact_exec_output(&sub_id, retry_output).await;
 
                     sess.notify_exec_command_end(
                         &sub_id,
@@ -1512,6 +2238,22 @@ async fn apply_patch(
     call_id: String,
     action: ApplyPatchAction,
 ) -> ResponseInputItem {
+    if let Some(path) = adds_redaction_placeholder(&action) {
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: FunctionCallOutputPayload {
+                content: format!(
+                    "patch rejected: the new contents of {} contain `{REDACTED}`, which stands for a secret hidden from you; leave the secret as it is or ask the user to fill it in",
+                    path.display()
+                ),
+                success: Some(false),
+            },
+        };
+    }
+
+    // Fingerprint the files before asking for approval so that edits made
+    // while the prompt is open are detected instead of overwritten.
+    let fingerprints = FileFingerprints::capture(&action);
     let writable_roots_snapshot = {
         let guard = sess.writable_roots.lock().unwrap();
         guard.clone()
@@ -1602,7 +2344,13 @@ async fn apply_patch(
     let mut stderr = Vec::new();
     // Enforce writable roots. If a write is blocked, collect offending root
     // and prompt the user to extend permissions.
-    let mut result = apply_changes_from_apply_patch_and_report(&action, &mut stdout, &mut stderr);
+    let mut result = apply_changes_from_apply_patch_and_report(
+        &action,
+        &fingerprints,
+        &sess.undo_journal,
+        &mut stdout,
+        &mut stderr,
+    );
 
     if let Err(err) = &result {
         if err.kind() == std::io::ErrorKind::PermissionDenied {
@@ -1611,8 +2359,9 @@ async fn apply_patch(
                 .changes()
                 .iter()
                 .flat_map(|(path, change)| match change {
-                    ApplyPatchFileChange::Add { .. } => vec![path.as_ref()],
-                    ApplyPatchFileChange::Delete => vec![path.as_ref()],
+                    ApplyPatchFileChange::Add { .. }
+                    | ApplyPatchFileChange::Delete
+                    | ApplyPatchFileChange::Symlink { .. } => vec![path.as_ref()],
                     ApplyPatchFileChange::Update {
                         move_path: Some(move_path),
                         ..
@@ -1665,6 +2414,8 @@ async fn apply_patch(
                     stderr.clear();
                     result = apply_changes_from_apply_patch_and_report(
                         &action,
+                        &fingerprints,
+                        &sess.undo_journal,
                         &mut stdout,
                         &mut stderr,
                     );
@@ -1673,6 +2424,23 @@ async fn apply_patch(
         }
     }
 
+    if let Err(err) = &result
+        && let Some(stale) = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<StaleFilesError>())
+    {
+        let _ = sess
+            .tx_event
+            .send(Event {
+                id: sub_id.clone(),
+                msg: EventMsg::PatchConflict(PatchConflictEvent {
+                    call_id: call_id.clone(),
+                    paths: stale.paths.clone(),
+                }),
+            })
+            .await;
+    }
+
     // Emit PatchApplyEnd event.
     let success_flag = result.is_ok();
     let _ = sess
@@ -1719,6 +2487,7 @@ fn first_offending_path(
         let candidate = match change {
             ApplyPatchFileChange::Add { .. } => path,
             ApplyPatchFileChange::Delete => path,
+            ApplyPatchFileChange::Symlink { .. } => path,
             ApplyPatchFileChange::Update { move_path, .. } => move_path.as_ref().unwrap_or(path),
         };
 
@@ -1757,10 +2526,13 @@ fn convert_apply_patch_to_protocol(action: &ApplyPatchAction) -> HashMap<PathBuf
                 content: content.clone(),
             },
             ApplyPatchFileChange::Delete => FileChange::Delete,
+            ApplyPatchFileChange::Symlink { target } => FileChange::Symlink {
+                target: target.clone(),
+            },
             ApplyPatchFileChange::Update {
                 unified_diff,
                 move_path,
-                new_content: _new_content,
+                ..
             } => FileChange::Update {
                 unified_diff: unified_diff.clone(),
                 move_path: move_path.clone(),
@@ -1771,83 +2543,94 @@ fn convert_apply_patch_to_protocol(action: &ApplyPatchAction) -> HashMap<PathBuf
     result
 }
 
+/// Applies `action` and writes a summary to `stdout` or the failure to
+/// `stderr`. Fails with a [`StaleFilesError`] if a file changed on disk since
+/// `fingerprints` were captured.
 fn apply_changes_from_apply_patch_and_report(
     action: &ApplyPatchAction,
+    fingerprints: &FileFingerprints,
+    undo_journal: &UndoJournal,
     stdout: &mut impl std::io::Write,
     stderr: &mut impl std::io::Write,
 ) -> std::io::Result<()> {
-    match apply_changes_from_apply_patch(action) {
-        Ok(affected_paths) => {
+    match apply_changes_from_apply_patch(action, fingerprints) {
+        Ok((affected_paths, pre_images, move_sources)) => {
+            // The patch is already on disk; failing to journal it only
+            // means it cannot be undone.
+            if let Err(e) = undo_journal.record(pre_images, &move_sources) {
+                warn!("failed to record patch in undo journal: {e}");
+            }
             print_summary(&affected_paths, stdout)?;
         }
-        Err(err) => {
-            writeln!(stderr, "{err:?}")?;
-        }
+        Err(err) => match err.downcast::<StaleFilesError>() {
+            Ok(stale) => return Err(std::io::Error::other(stale)),
+            Err(err) => writeln!(stderr, "{err:?}")?,
+        },
     }
 
     Ok(())
 }
 
-fn apply_changes_from_apply_patch(action: &ApplyPatchAction) -> anyhow::Result<AffectedPaths> {
+/// Applies every change in `action`, or none of them if any change fails or
+/// a touched file no longer matches `fingerprints`. Also returns the
+/// pre-image of each touched path and, for moves, a map from destination to
+/// source.
+fn apply_changes_from_apply_patch(
+    action: &ApplyPatchAction,
+    fingerprints: &FileFingerprints,
+) -> anyhow::Result<(AffectedPaths, Vec<FilePreImage>, HashMap<PathBuf, PathBuf>)> {
+    fingerprints.verify()?;
     let mut added: Vec<PathBuf> = Vec::new();
     let mut modified: Vec<PathBuf> = Vec::new();
     let mut deleted: Vec<PathBuf> = Vec::new();
+    let mut move_sources: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut transaction = FileTransaction::new();
 
     let changes = action.changes();
     for (path, change) in changes {
         match change {
             ApplyPatchFileChange::Add { content } => {
-                if let Some(parent) = path.parent() {
-                    if !parent.as_os_str().is_empty() {
-                        std::fs::create_dir_all(parent).with_context(|| {
-                            format!("Failed to create parent directories for {}", path.display())
-                        })?;
-                    }
-                }
-                std::fs::write(path, content)
-                    .with_context(|| format!("Failed to write file {}", path.display()))?;
+                transaction.write(path, content.as_str());
                 added.push(path.clone());
             }
             ApplyPatchFileChange::Delete => {
-                std::fs::remove_file(path)
-                    .with_context(|| format!("Failed to delete file {}", path.display()))?;
+                transaction.delete(path)?;
                 deleted.push(path.clone());
             }
+            ApplyPatchFileChange::Symlink { target } => {
+                transaction.symlink(path, target);
+                added.push(path.clone());
+            }
             ApplyPatchFileChange::Update {
-                unified_diff: _unified_diff,
                 move_path,
                 new_content,
+                ..
             } => {
                 if let Some(move_path) = move_path {
-                    if let Some(parent) = move_path.parent() {
-                        if !parent.as_os_str().is_empty() {
-                            std::fs::create_dir_all(parent).with_context(|| {
-                                format!(
-                                    "Failed to create parent directories for {}",
-                                    move_path.display()
-                                )
-                            })?;
-                        }
-                    }
-
-                    std::fs::rename(path, move_path)
-                        .with_context(|| format!("Failed to rename file {}", path.display()))?;
-                    std::fs::write(move_path, new_content)?;
+                    transaction.rename(path, move_path, new_content.as_str())?;
                     modified.push(move_path.clone());
-                    deleted.push(path.clone());
+                    if move_path != path {
+                        deleted.push(path.clone());
+                        move_sources.insert(move_path.clone(), path.clone());
+                    }
                 } else {
-                    std::fs::write(path, new_content)?;
+                    transaction.write(path, new_content.as_str());
                     modified.push(path.clone());
                 }
             }
         }
     }
+    let pre_images = transaction.commit()?;
 
-    Ok(AffectedPaths {
-        added,
-        modified,
-        deleted,
-    })
+    Ok((
+        AffectedPaths {
+            added,
+            modified,
+            deleted,
+        },
+        pre_images,
+        move_sources,
+    ))
 }
 
 fn get_writable_roots(cwd: &Path) -> Vec<std::path::PathBuf> {
@@ -1,10 +1,14 @@
 use crate::config_profile::ConfigProfile;
+use crate::config_types::ExecTimeouts;
+use crate::config_types::ExecTimeoutsToml;
 use crate::config_types::History;
 use crate::config_types::McpServerConfig;
 use crate::config_types::ReasoningEffort;
 use crate::config_types::ReasoningSummary;
+use crate::config_types::SecretRedactionToml;
 use crate::config_types::ShellEnvironmentPolicy;
 use crate::config_types::ShellEnvironmentPolicyToml;
+use crate::config_types::ShellOutputLimits;
 use crate::config_types::Tui;
 use crate::config_types::UriBasedFileOpener;
 use crate::flags::OPENAI_DEFAULT_MODEL;
@@ -13,6 +17,7 @@ use crate::model_provider_info::built_in_model_providers;
 use crate::protocol::AskForApproval;
 use crate::protocol::SandboxPermission;
 use crate::protocol::SandboxPolicy;
+use crate::redact::SecretRedactor;
 use dirs::home_dir;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -44,6 +49,24 @@ pub struct Config {
 
     pub shell_environment_policy: ShellEnvironmentPolicy,
 
+    /// Truncation limits for the output of `shell` tool calls.
+    pub shell_output: ShellOutputLimits,
+
+    /// Timeouts for `shell` tool calls.
+    pub exec_timeouts: ExecTimeouts,
+
+    /// Whether to offer the model the `persistent_shell` tool, which keeps a
+    /// bash process alive for the whole session.
+    pub persistent_shell: bool,
+
+    /// Whether to run shell commands in the user's login shell, with its
+    /// profile and rc file loaded.
+    pub login_shell: bool,
+
+    /// Redacts secrets from command output before it reaches the model, and
+    /// from what is written to rollout and history files.
+    pub secret_redaction: SecretRedactor,
+
     /// When `true`, `AgentReasoning` events emitted by the backend will be
     /// suppressed from the frontend output. This can reduce visual noise when
     /// users are only interested in the final agent responses.
@@ -122,6 +145,14 @@ pub struct Config {
     /// If not "none", the value to use for `reasoning.summary` when making a
     /// request using the Responses API.
     pub model_reasoning_summary: ReasoningSummary,
+
+    /// When set, every model request payload and SSE event is appended to
+    /// this file, with credentials redacted.
+    pub trace_http: Option<PathBuf>,
+
+    /// JSON schema the final assistant message must conform to. Like
+    /// `codex_linux_sandbox_exe`, this can only be set via [`ConfigOverrides`].
+    pub output_schema: Option<serde_json::Value>,
 }
 
 impl Config {
@@ -288,12 +319,34 @@ pub struct ConfigToml {
     /// Collection of settings that are specific to the TUI.
     pub tui: Option<Tui>,
 
+    /// Truncation limits for the output of `shell` tool calls.
+    pub shell_output: Option<ShellOutputLimits>,
+
+    /// Default, maximum and per-command timeouts for `shell` tool calls.
+    #[serde(default)]
+    pub exec_timeouts: ExecTimeoutsToml,
+
+    /// Offer the model a shell that persists between tool calls. Defaults to
+    /// `false`.
+    pub persistent_shell: Option<bool>,
+
+    /// Run shell commands in the user's login shell. Defaults to `false`.
+    pub login_shell: Option<bool>,
+
+    /// Extra patterns for secrets to redact, or `enabled = false` to turn
+    /// redaction off.
+    #[serde(default)]
+    pub secret_redaction: SecretRedactionToml,
+
     /// When set to `true`, `AgentReasoning` events will be hidden from the
     /// UI/output. Defaults to `false`.
     pub hide_agent_reasoning: Option<bool>,
 
     pub model_reasoning_effort: Option<ReasoningEffort>,
     pub model_reasoning_summary: Option<ReasoningSummary>,
+
+    /// Append every model request payload and SSE event to this file.
+    pub trace_http: Option<PathBuf>,
 }
 
 fn deserialize_sandbox_permissions<'de, D>(
@@ -332,6 +385,8 @@ pub struct ConfigOverrides {
     pub model_provider: Option<String>,
     pub config_profile: Option<String>,
     pub codex_linux_sandbox_exe: Option<PathBuf>,
+    pub output_schema: Option<serde_json::Value>,
+    pub trace_http: Option<PathBuf>,
 }
 
 impl Config {
@@ -353,6 +408,8 @@ impl Config {
             model_provider,
             config_profile: config_profile_key,
             codex_linux_sandbox_exe,
+            output_schema,
+            trace_http,
         } = overrides;
 
         let config_profile = match config_profile_key.or(cfg.profile) {
@@ -427,6 +484,17 @@ impl Config {
 
         let history = cfg.history.unwrap_or_default();
 
+        let secret_redaction = SecretRedactor::new(
+            cfg.secret_redaction.enabled.unwrap_or(true),
+            &cfg.secret_redaction.patterns.unwrap_or_default(),
+        )
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid pattern in secret_redaction: {e}"),
+            )
+        })?;
+
         let config = Self {
             model: model
                 .or(config_profile.model)
@@ -441,6 +509,11 @@ impl Config {
                 .unwrap_or_else(AskForApproval::default),
             sandbox_policy,
             shell_environment_policy,
+            shell_output: cfg.shell_output.unwrap_or_default(),
+            exec_timeouts: cfg.exec_timeouts.into(),
+            persistent_shell: cfg.persistent_shell.unwrap_or(false),
+            login_shell: cfg.login_shell.unwrap_or(false),
+            secret_redaction,
             disable_response_storage: config_profile
                 .disable_response_storage
                 .or(cfg.disable_response_storage)
@@ -459,6 +532,8 @@ impl Config {
             hide_agent_reasoning: cfg.hide_agent_reasoning.unwrap_or(false),
             model_reasoning_effort: cfg.model_reasoning_effort.unwrap_or_default(),
             model_reasoning_summary: cfg.model_reasoning_summary.unwrap_or_default(),
+            trace_http: trace_http.or(cfg.trace_http),
+            output_schema,
         };
         Ok(config)
     }
@@ -788,6 +863,11 @@ disable_response_storage = true
                 approval_policy: AskForApproval::Never,
                 sandbox_policy: SandboxPolicy::new_read_only_policy(),
                 shell_environment_policy: ShellEnvironmentPolicy::default(),
+                shell_output: ShellOutputLimits::default(),
+                exec_timeouts: ExecTimeouts::default(),
+                persistent_shell: false,
+                login_shell: false,
+                secret_redaction: SecretRedactor::default(),
                 disable_response_storage: false,
                 instructions: None,
                 notify: None,
@@ -803,6 +883,8 @@ disable_response_storage = true
                 hide_agent_reasoning: false,
                 model_reasoning_effort: ReasoningEffort::default(),
                 model_reasoning_summary: ReasoningSummary::default(),
+                trace_http: None,
+                output_schema: None,
             },
             o3_profile_config
         );
@@ -830,6 +912,11 @@ disable_response_storage = true
             approval_policy: AskForApproval::UnlessAllowListed,
             sandbox_policy: SandboxPolicy::new_read_only_policy(),
             shell_environment_policy: ShellEnvironmentPolicy::default(),
+            shell_output: ShellOutputLimits::default(),
+            exec_timeouts: ExecTimeouts::default(),
+            persistent_shell: false,
+            login_shell: false,
+            secret_redaction: SecretRedactor::default(),
             disable_response_storage: false,
             instructions: None,
             notify: None,
@@ -845,6 +932,8 @@ disable_response_storage = true
             hide_agent_reasoning: false,
             model_reasoning_effort: ReasoningEffort::default(),
             model_reasoning_summary: ReasoningSummary::default(),
+            trace_http: None,
+            output_schema: None,
         };
 
         assert_eq!(expected_gpt3_profile_config, gpt3_profile_config);
@@ -887,6 +976,11 @@ disable_response_storage = true
             approval_policy: AskForApproval::OnFailure,
             sandbox_policy: SandboxPolicy::new_read_only_policy(),
             shell_environment_policy: ShellEnvironmentPolicy::default(),
+            shell_output: ShellOutputLimits::default(),
+            exec_timeouts: ExecTimeouts::default(),
+            persistent_shell: false,
+            login_shell: false,
+            secret_redaction: SecretRedactor::default(),
             disable_response_storage: true,
             instructions: None,
             notify: None,
@@ -902,6 +996,8 @@ disable_response_storage = true
             hide_agent_reasoning: false,
             model_reasoning_effort: ReasoningEffort::default(),
             model_reasoning_summary: ReasoningSummary::default(),
+            trace_http: None,
+            output_schema: None,
         };
 
         assert_eq!(expected_zdr_profile_config, zdr_profile_config);
@@ -90,6 +90,104 @@ pub struct Tui {
     pub disable_mouse_capture: bool,
 }
 
+/// How much of each output stream of a `shell` tool call is sent to the
+/// model. Output beyond these limits is elided from the middle, keeping the
+/// first and last half of the budget, and the complete output is saved to a
+/// file the model can read.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ShellOutputLimits {
+    /// Maximum number of bytes kept per stream.
+    pub max_bytes: usize,
+
+    /// Maximum number of lines kept per stream.
+    pub max_lines: usize,
+}
+
+impl Default for ShellOutputLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024,
+            max_lines: 256,
+        }
+    }
+}
+
+/// Timeouts for `shell` tool calls, as written in `config.toml`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ExecTimeoutsToml {
+    /// Timeout for commands that match no rule and for which the model does
+    /// not request one.
+    pub default_ms: Option<u64>,
+
+    /// Upper bound on any timeout, including those requested by the model.
+    pub max_ms: Option<u64>,
+
+    /// Checked in order; the first rule matching the command applies.
+    pub rules: Option<Vec<ExecTimeoutRuleToml>>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ExecTimeoutRuleToml {
+    /// Glob matched against the command's arguments joined with spaces.
+    pub pattern: String,
+
+    pub timeout_ms: u64,
+}
+
+pub type CommandPattern = WildMatchPattern<'*', '?'>;
+
+/// Resolved [`ExecTimeoutsToml`]. The timeout of a command is the one the
+/// model requested, or else that of the first matching rule, or else
+/// `default_ms`; in every case capped at `max_ms`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecTimeouts {
+    pub default_ms: u64,
+
+    pub max_ms: Option<u64>,
+
+    /// `(pattern, timeout_ms)` pairs.
+    pub rules: Vec<(CommandPattern, u64)>,
+}
+
+impl Default for ExecTimeouts {
+    fn default() -> Self {
+        Self {
+            default_ms: crate::exec::DEFAULT_TIMEOUT_MS,
+            max_ms: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl From<ExecTimeoutsToml> for ExecTimeouts {
+    fn from(toml: ExecTimeoutsToml) -> Self {
+        let defaults = Self::default();
+        let rules = toml
+            .rules
+            .unwrap_or_default()
+            .into_iter()
+            .map(|rule| (CommandPattern::new(&rule.pattern), rule.timeout_ms))
+            .collect();
+        Self {
+            default_ms: toml.default_ms.unwrap_or(defaults.default_ms),
+            max_ms: toml.max_ms,
+            rules,
+        }
+    }
+}
+
+/// Redaction of secrets from command output and messages, as written in
+/// `config.toml`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SecretRedactionToml {
+    /// Defaults to `true`.
+    pub enabled: Option<bool>,
+
+    /// Regexes for secrets, redacted in addition to the built-in patterns.
+    pub patterns: Option<Vec<String>>,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum ShellEnvironmentPolicyInherit {
@@ -0,0 +1,204 @@
+//! The `edit_file` and `write_file` function tools.
+//!
+//! These are a structured alternative to embedding an `apply_patch` call in
+//! the `shell` tool, for models that struggle with the patch grammar. Each
+//! call is turned into an [`ApplyPatchAction`] so that it goes through the
+//! same safety checks, approval flow and events as a patch, and files are
+//! read the way patches read them: binary files are refused, and a file's
+//! CRLF line endings are kept.
+
+use std::path::Path;
+
+use codex_apply_patch::ApplyPatchAction;
+use codex_apply_patch::LineEnding;
+use codex_apply_patch::decode_text_file;
+
+use crate::models::EditFileToolCallParams;
+use crate::models::WriteFileToolCallParams;
+
+/// Replaces `old_string` with `new_string` in an existing file. Unless
+/// `replace_all` is set, `old_string` must occur exactly once. Errors are
+/// phrased for the model.
+pub(crate) fn edit_file_action(
+    params: EditFileToolCallParams,
+    cwd: &Path,
+) -> Result<ApplyPatchAction, String> {
+    let EditFileToolCallParams {
+        path,
+        old_string,
+        new_string,
+        replace_all,
+    } = params;
+    let path = cwd.join(path);
+    if old_string.is_empty() {
+        return Err("old_string must not be empty; use write_file to create a file".to_string());
+    }
+    if old_string == new_string {
+        return Err("old_string and new_string are identical; nothing to change".to_string());
+    }
+    let original = read_text(&path)?.ok_or_else(|| {
+        format!(
+            "{} does not exist; use write_file to create it",
+            path.display()
+        )
+    })?;
+
+    // Match and replace with `\n` line endings, as patches do.
+    let line_ending = LineEnding::detect(&original);
+    let text = line_ending.normalize(&original);
+    let old_string = line_ending.normalize(&old_string);
+    let new_string = line_ending.normalize(&new_string);
+
+    let new_content = match text.matches(old_string.as_ref()).count() {
+        0 => {
+            return Err(format!(
+                "old_string was not found in {}; re-read the file and copy the text exactly, including whitespace",
+                path.display()
+            ));
+        }
+        1 => text.replacen(old_string.as_ref(), &new_string, 1),
+        _ if replace_all => text.replace(old_string.as_ref(), &new_string),
+        count => {
+            return Err(format!(
+                "old_string occurs {count} times in {}; include more surrounding context to make it unique, or set replace_all to replace every occurrence",
+                path.display()
+            ));
+        }
+    };
+    let new_content = line_ending.restore(&new_content).into_owned();
+    Ok(ApplyPatchAction::new_write(
+        &path,
+        Some(&original),
+        new_content,
+    ))
+}
+
+/// Creates a file, or replaces the contents of an existing one.
+pub(crate) fn write_file_action(
+    params: WriteFileToolCallParams,
+    cwd: &Path,
+) -> Result<ApplyPatchAction, String> {
+    let WriteFileToolCallParams { path, content } = params;
+    let path = cwd.join(path);
+    let original = read_text(&path)?;
+    // An existing file keeps its line endings.
+    let content = match &original {
+        Some(original) => LineEnding::detect(original).restore(&content).into_owned(),
+        None => content,
+    };
+    if original.as_deref() == Some(content.as_str()) {
+        return Err(format!(
+            "{} already has this content; nothing to change",
+            path.display()
+        ));
+    }
+    Ok(ApplyPatchAction::new_write(
+        &path,
+        original.as_deref(),
+        content,
+    ))
+}
+
+/// Contents of `path` as text, or `None` if it does not exist.
+fn read_text(path: &Path) -> Result<Option<String>, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => decode_text_file(path, bytes)
+            .map(Some)
+            .map_err(|_| format!("{} is a binary file and cannot be edited", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use codex_apply_patch::ApplyPatchFileChange;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    fn edit(path: &str, old: &str, new: &str, replace_all: bool) -> EditFileToolCallParams {
+        EditFileToolCallParams {
+            path: path.to_string(),
+            old_string: old.to_string(),
+            new_string: new.to_string(),
+            replace_all,
+        }
+    }
+
+    fn new_content(action: &ApplyPatchAction) -> &str {
+        match action.changes().values().next().unwrap() {
+            ApplyPatchFileChange::Update { new_content, .. } => new_content,
+            ApplyPatchFileChange::Add { content } => content,
+            other => panic!("unexpected change {other:?}"),
+        }
+    }
+
+    #[test]
+    fn edit_requires_unique_match_unless_replace_all() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "x = 1\ny = 1\n").unwrap();
+
+        let action = edit_file_action(edit("a.txt", "x = 1", "x = 2", false), dir.path()).unwrap();
+        assert_eq!(new_content(&action), "x = 2\ny = 1\n");
+
+        let err = edit_file_action(edit("a.txt", "= 1", "= 2", false), dir.path()).unwrap_err();
+        assert!(err.contains("occurs 2 times"), "{err}");
+
+        let action = edit_file_action(edit("a.txt", "= 1", "= 2", true), dir.path()).unwrap();
+        assert_eq!(new_content(&action), "x = 2\ny = 2\n");
+
+        let err = edit_file_action(edit("a.txt", "z = 1", "z = 2", false), dir.path()).unwrap_err();
+        assert!(err.contains("was not found"), "{err}");
+
+        let err = edit_file_action(edit("b.txt", "x", "y", false), dir.path()).unwrap_err();
+        assert!(err.contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn write_creates_or_replaces() {
+        let dir = tempdir().unwrap();
+        let params = |content: &str| WriteFileToolCallParams {
+            path: "new.txt".to_string(),
+            content: content.to_string(),
+        };
+
+        let action = write_file_action(params("hello\n"), dir.path()).unwrap();
+        assert!(matches!(
+            action.changes().get(&dir.path().join("new.txt")),
+            Some(ApplyPatchFileChange::Add { .. })
+        ));
+
+        std::fs::write(dir.path().join("new.txt"), "hello\n").unwrap();
+        assert!(write_file_action(params("hello\n"), dir.path()).is_err());
+        let action = write_file_action(params("bye\n"), dir.path()).unwrap();
+        assert_eq!(new_content(&action), "bye\n");
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\r\ntwo\r\nthree\r\n").unwrap();
+
+        let action =
+            edit_file_action(edit("a.txt", "one\ntwo", "uno\ndos", false), dir.path()).unwrap();
+        assert_eq!(new_content(&action), "uno\r\ndos\r\nthree\r\n");
+
+        let params = WriteFileToolCallParams {
+            path: "a.txt".to_string(),
+            content: "a\nb\n".to_string(),
+        };
+        let action = write_file_action(params, dir.path()).unwrap();
+        assert_eq!(new_content(&action), "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn refuses_binary_files() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("data.bin"), b"abc\0def").unwrap();
+        let err = edit_file_action(edit("data.bin", "abc", "xyz", false), dir.path()).unwrap_err();
+        assert!(err.contains("binary file"), "{err}");
+    }
+}
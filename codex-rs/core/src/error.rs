@@ -4,6 +4,8 @@ use std::io;
 use thiserror::Error;
 use tokio::task::JoinError;
 
+use crate::exec::Termination;
+
 pub type Result<T> = std::result::Result<T, CodexErr>;
 
 #[derive(Error, Debug)]
@@ -22,9 +24,13 @@ pub enum SandboxErr {
     #[error("seccomp backend error")]
     SeccompBackend(#[from] seccompiler::BackendError),
 
-    /// Command timed out
-    #[error("command timed out")]
-    Timeout,
+    /// Command timed out and was stopped
+    #[error("command timed out; {0}")]
+    Timeout(Termination),
+
+    /// Command was interrupted by the user and stopped
+    #[error("command was interrupted; {0}")]
+    Interrupted(Termination),
 
     /// Command was killed by a signal
     #[error("command was killed by a signal")]
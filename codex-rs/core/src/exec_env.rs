@@ -15,6 +15,15 @@ pub fn create_env(policy: &ShellEnvironmentPolicy) -> HashMap<String, String> {
     populate_env(std::env::vars(), policy)
 }
 
+/// Like [`create_env`], but starting from `vars` instead of the environment
+/// of the Codex process, e.g. a snapshot of the user's login shell.
+pub fn create_env_from_vars(
+    vars: &HashMap<String, String>,
+    policy: &ShellEnvironmentPolicy,
+) -> HashMap<String, String> {
+    populate_env(vars.clone(), policy)
+}
+
 fn populate_env<I>(vars: I, policy: &ShellEnvironmentPolicy) -> HashMap<String, String>
 where
     I: IntoIterator<Item = (String, String)>,
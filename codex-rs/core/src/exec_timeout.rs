@@ -0,0 +1,78 @@
+use crate::config_types::ExecTimeouts;
+
+/// Returns the timeout for running `command`, given the timeout the model
+/// requested, if any, following the precedence documented on
+/// [`ExecTimeouts`].
+///
+/// Rules are matched against the arguments joined with spaces and, for
+/// commands of the form `bash -lc <script>` that models commonly write, also
+/// against the script itself.
+pub fn effective_timeout_ms(
+    timeouts: &ExecTimeouts,
+    command: &[String],
+    requested_ms: Option<u64>,
+) -> u64 {
+    let timeout_ms = requested_ms
+        .or_else(|| {
+            let joined = command.join(" ");
+            let script = match command {
+                [_, flag, script] if flag == "-lc" || flag == "-c" => Some(script.as_str()),
+                _ => None,
+            };
+            timeouts
+                .rules
+                .iter()
+                .find(|(pattern, _)| {
+                    pattern.matches(&joined) || script.is_some_and(|s| pattern.matches(s))
+                })
+                .map(|(_, timeout_ms)| *timeout_ms)
+        })
+        .unwrap_or(timeouts.default_ms);
+    match timeouts.max_ms {
+        Some(max_ms) => timeout_ms.min(max_ms),
+        None => timeout_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_types::CommandPattern;
+    use pretty_assertions::assert_eq;
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn requested_then_rule_then_default_capped_at_max() {
+        let timeouts = ExecTimeouts {
+            default_ms: 10_000,
+            max_ms: Some(900_000),
+            rules: vec![
+                (CommandPattern::new("cargo test*"), 600_000),
+                (CommandPattern::new("cargo *"), 300_000),
+            ],
+        };
+
+        let cargo_test = command(&["cargo", "test", "-p", "codex-core"]);
+        assert_eq!(effective_timeout_ms(&timeouts, &cargo_test, None), 600_000);
+        assert_eq!(
+            effective_timeout_ms(&timeouts, &cargo_test, Some(5_000)),
+            5_000
+        );
+        assert_eq!(
+            effective_timeout_ms(&timeouts, &cargo_test, Some(3_600_000)),
+            900_000
+        );
+
+        let bash_cargo_build = command(&["bash", "-lc", "cargo build --release"]);
+        assert_eq!(
+            effective_timeout_ms(&timeouts, &bash_cargo_build, None),
+            300_000
+        );
+
+        let ls = command(&["ls", "-la"]);
+        assert_eq!(effective_timeout_ms(&timeouts, &ls, None), 10_000);
+    }
+}
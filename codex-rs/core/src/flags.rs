@@ -22,4 +22,9 @@ env_flags! {
 
     /// Fixture path for offline tests (see client.rs).
     pub CODEX_RS_SSE_FIXTURE: Option<&str> = None;
+
+    /// Directory holding a record/replay cassette of model traffic (see cassette.rs).
+    pub CODEX_RS_CASSETTE_DIR: Option<&str> = None;
+    /// One of `record`, `replay` (in request order) or `replay-by-hash`.
+    pub CODEX_RS_CASSETTE_MODE: &str = "replay";
 }
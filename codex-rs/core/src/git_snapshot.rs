@@ -0,0 +1,342 @@
+//! Per-turn snapshots of the working tree for sessions inside a git repo.
+//!
+//! At the start of every turn we record the full working tree (tracked and
+//! untracked files, honouring `.gitignore`) as a commit under the private ref
+//! `refs/codex/<session_id>/<turn>`. The snapshot is built with a throwaway
+//! index (`GIT_INDEX_FILE`) so the user's index, HEAD and branches are never
+//! touched. Snapshots can later be restored or diffed against the current
+//! working tree. Snapshots of earlier sessions are deleted once they are
+//! older than [`SNAPSHOT_RETENTION`], so their refs (and the objects only
+//! they reach) do not pile up in the repository.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use tokio::process::Command;
+use uuid::Uuid;
+
+/// Identity used for snapshot commits so they work even when the user has no
+/// `user.name`/`user.email` configured.
+const SNAPSHOT_AUTHOR_NAME: &str = "Codex";
+const SNAPSHOT_AUTHOR_EMAIL: &str = "codex@localhost";
+
+/// Prefix of the refs snapshots are stored under.
+const SNAPSHOT_REF_PREFIX: &str = "refs/codex/";
+
+/// How long the snapshots of other sessions are kept after they were taken.
+const SNAPSHOT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Snapshots for one session in one repository.
+#[derive(Debug, Clone)]
+pub(crate) struct GitSnapshots {
+    repo_root: PathBuf,
+    session_id: Uuid,
+}
+
+impl GitSnapshots {
+    /// Returns `None` if `cwd` is not inside a git work tree.
+    pub(crate) async fn for_cwd(cwd: &Path, session_id: Uuid) -> Option<Self> {
+        if !crate::util::is_path_inside_git_repo(cwd) {
+            return None;
+        }
+        let repo_root = git(cwd, None, &["rev-parse", "--show-toplevel"])
+            .await
+            .ok()?;
+        Some(Self {
+            repo_root: PathBuf::from(repo_root),
+            session_id,
+        })
+    }
+
+    fn ref_name(&self, turn: u64) -> String {
+        format!("{SNAPSHOT_REF_PREFIX}{}/{turn}", self.session_id)
+    }
+
+    /// Deletes the snapshots of other sessions taken more than
+    /// [`SNAPSHOT_RETENTION`] ago.
+    pub(crate) async fn prune_expired(&self) -> anyhow::Result<()> {
+        self.prune_older_than(SNAPSHOT_RETENTION).await
+    }
+
+    async fn prune_older_than(&self, retention: Duration) -> anyhow::Result<()> {
+        let refs = git(
+            &self.repo_root,
+            None,
+            &[
+                "for-each-ref",
+                "--format=%(refname) %(committerdate:unix)",
+                SNAPSHOT_REF_PREFIX,
+            ],
+        )
+        .await?;
+        let own_prefix = format!("{SNAPSHOT_REF_PREFIX}{}/", self.session_id);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for line in refs.lines() {
+            let Some((ref_name, taken_at)) = line.split_once(' ') else {
+                continue;
+            };
+            let expired = taken_at
+                .parse::<u64>()
+                .is_ok_and(|taken_at| now.saturating_sub(taken_at) >= retention.as_secs());
+            if expired && !ref_name.starts_with(&own_prefix) {
+                git(&self.repo_root, None, &["update-ref", "-d", ref_name]).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the current working tree as the snapshot for `turn`.
+    pub(crate) async fn create(&self, turn: u64) -> anyhow::Result<()> {
+        let tree = self.write_tree().await?;
+        let message = format!("codex snapshot: session {} turn {turn}", self.session_id);
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+        let head = git(
+            &self.repo_root,
+            None,
+            &["rev-parse", "--verify", "-q", "HEAD"],
+        )
+        .await
+        .ok();
+        if let Some(head) = &head {
+            args.extend(["-p", head.as_str()]);
+        }
+        let commit = git(&self.repo_root, None, &args).await?;
+        git(
+            &self.repo_root,
+            None,
+            &["update-ref", &self.ref_name(turn), &commit],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Makes the working tree match the snapshot for `turn`: files recorded
+    /// in it are rewritten and files created since are removed.
+    pub(crate) async fn restore(&self, turn: u64) -> anyhow::Result<()> {
+        let target = self.snapshot_commit(turn).await?;
+        let current = self.write_tree().await?;
+        let added = git(
+            &self.repo_root,
+            None,
+            &[
+                "diff-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--no-renames",
+                "--diff-filter=A",
+                &target,
+                &current,
+            ],
+        )
+        .await?;
+        for path in added.split('\0').filter(|p| !p.is_empty()) {
+            let path = self.repo_root.join(path);
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+
+        let index = TempIndex::new(&self.repo_root).await?;
+        git(&self.repo_root, Some(&index.path), &["read-tree", &target]).await?;
+        git(
+            &self.repo_root,
+            Some(&index.path),
+            &["checkout-index", "--all", "--force"],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Unified diff from the first snapshot of the session to the current
+    /// working tree. Empty if nothing changed.
+    pub(crate) async fn diff_since_start(&self) -> anyhow::Result<String> {
+        let start = self.snapshot_commit(1).await?;
+        let current = self.write_tree().await?;
+        git(
+            &self.repo_root,
+            None,
+            &["diff", "--no-color", "--no-ext-diff", &start, &current],
+        )
+        .await
+    }
+
+    async fn snapshot_commit(&self, turn: u64) -> anyhow::Result<String> {
+        let ref_name = self.ref_name(turn);
+        git(
+            &self.repo_root,
+            None,
+            &[
+                "rev-parse",
+                "--verify",
+                "-q",
+                &format!("{ref_name}^{{commit}}"),
+            ],
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("No snapshot for turn {turn} in this session"))
+    }
+
+    /// Writes the working tree to the object database and returns its tree id.
+    async fn write_tree(&self) -> anyhow::Result<String> {
+        let index = TempIndex::new(&self.repo_root).await?;
+        git(&self.repo_root, Some(&index.path), &["add", "--all"]).await?;
+        git(&self.repo_root, Some(&index.path), &["write-tree"]).await
+    }
+}
+
+/// Private index file, seeded from the real index so `git add` can reuse its
+/// stat cache. Removed on drop.
+struct TempIndex {
+    path: PathBuf,
+}
+
+impl TempIndex {
+    async fn new(repo_root: &Path) -> anyhow::Result<Self> {
+        let git_dir =
+            PathBuf::from(git(repo_root, None, &["rev-parse", "--absolute-git-dir"]).await?);
+        let path = git_dir.join(format!("codex-snapshot-index-{}", Uuid::new_v4()));
+        match std::fs::copy(git_dir.join("index"), &path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to copy git index"),
+        }
+        Ok(Self { path })
+    }
+}
+
+impl Drop for TempIndex {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Runs `git` in `dir` and returns its trimmed stdout.
+async fn git(dir: &Path, index: Option<&Path>, args: &[&str]) -> anyhow::Result<String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", SNAPSHOT_AUTHOR_NAME)
+        .env("GIT_AUTHOR_EMAIL", SNAPSHOT_AUTHOR_EMAIL)
+        .env("GIT_COMMITTER_NAME", SNAPSHOT_AUTHOR_NAME)
+        .env("GIT_COMMITTER_EMAIL", SNAPSHOT_AUTHOR_EMAIL)
+        .kill_on_drop(true);
+    if let Some(index) = index {
+        cmd.env("GIT_INDEX_FILE", index);
+    }
+    let output = cmd
+        .output()
+        .await
+        .with_context(|| format!("Failed to run git {}", args.join(" ")))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    async fn init_repo(dir: &Path) {
+        git(dir, None, &["init", "-q"]).await.unwrap();
+        std::fs::write(dir.join("tracked.txt"), "v1\n").unwrap();
+        git(dir, None, &["add", "tracked.txt"]).await.unwrap();
+        git(dir, None, &["commit", "-q", "-m", "init"])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn restore_and_diff_round_trip() {
+        let dir = tempdir().unwrap();
+        init_repo(dir.path()).await;
+        std::fs::write(dir.path().join("untracked.txt"), "draft\n").unwrap();
+        let index_before = std::fs::read(dir.path().join(".git/index")).unwrap();
+
+        let snapshots = GitSnapshots::for_cwd(dir.path(), Uuid::new_v4())
+            .await
+            .unwrap();
+        snapshots.create(1).await.unwrap();
+        assert_eq!(snapshots.diff_since_start().await.unwrap(), "");
+
+        std::fs::write(dir.path().join("tracked.txt"), "v2\n").unwrap();
+        std::fs::remove_file(dir.path().join("untracked.txt")).unwrap();
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+        let diff = snapshots.diff_since_start().await.unwrap();
+        assert!(diff.contains("+v2"), "{diff}");
+        assert!(diff.contains("deleted file mode"), "{diff}");
+        assert!(diff.contains("+new"), "{diff}");
+
+        snapshots.restore(1).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("tracked.txt")).unwrap(),
+            "v1\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("untracked.txt")).unwrap(),
+            "draft\n"
+        );
+        assert!(!dir.path().join("new.txt").exists());
+
+        // The user's index and HEAD are untouched.
+        assert_eq!(
+            std::fs::read(dir.path().join(".git/index")).unwrap(),
+            index_before
+        );
+        let status = git(dir.path(), None, &["status", "--porcelain"])
+            .await
+            .unwrap();
+        assert_eq!(status, "?? untracked.txt");
+        assert!(snapshots.restore(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_the_current_session() {
+        let dir = tempdir().unwrap();
+        init_repo(dir.path()).await;
+        let earlier = GitSnapshots::for_cwd(dir.path(), Uuid::new_v4())
+            .await
+            .unwrap();
+        earlier.create(1).await.unwrap();
+        earlier.create(2).await.unwrap();
+        let current = GitSnapshots::for_cwd(dir.path(), Uuid::new_v4())
+            .await
+            .unwrap();
+        current.create(1).await.unwrap();
+
+        current.prune_expired().await.unwrap();
+        assert!(earlier.snapshot_commit(2).await.is_ok());
+
+        current.prune_older_than(Duration::ZERO).await.unwrap();
+        assert!(earlier.snapshot_commit(1).await.is_err());
+        assert!(earlier.snapshot_commit(2).await.is_err());
+        assert!(current.snapshot_commit(1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn not_a_repo() {
+        let dir = tempdir().unwrap();
+        assert!(
+            GitSnapshots::for_cwd(dir.path(), Uuid::new_v4())
+                .await
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Optional trace of model traffic, enabled with `--trace-http <file>` or the
+//! `trace_http` config key.
+//!
+//! Every request payload and every SSE event received is appended to the file
+//! as one JSON object per line, with a timestamp. Credentials are redacted
+//! with [`crate::redact::redact_secrets`] before anything is written: first
+//! in each JSON string, where nested JSON (e.g. tool call arguments) is still
+//! unescaped, then in the serialized line.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+use time::OffsetDateTime;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use tracing::warn;
+
+use crate::redact::SecretRedactor;
+use crate::redact::redact_secrets;
+
+const TIMESTAMP_FORMAT: &[FormatItem] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z");
+
+#[derive(Debug)]
+pub(crate) struct HttpTrace {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TraceRecord<'a> {
+    Request { url: &'a str, body: Value },
+    SseEvent { event: &'a str, data: String },
+}
+
+impl HttpTrace {
+    pub(crate) fn open(path: &Path) -> std::io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        // The trace contains full conversations, so keep it private.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        Ok(Self {
+            file: Mutex::new(options.open(path)?),
+        })
+    }
+
+    pub(crate) fn record_request(&self, url: &str, body: &Value) {
+        let mut body = body.clone();
+        SecretRedactor::default().redact_json(&mut body);
+        self.write(TraceRecord::Request { url, body });
+    }
+
+    pub(crate) fn record_sse_event(&self, event: &str, data: &str) {
+        let data = match serde_json::from_str::<Value>(data) {
+            Ok(mut value) => {
+                SecretRedactor::default().redact_json(&mut value);
+                value.to_string()
+            }
+            Err(_) => data.to_string(),
+        };
+        self.write(TraceRecord::SseEvent { event, data });
+    }
+
+    fn write(&self, record: TraceRecord<'_>) {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            timestamp: String,
+            #[serde(flatten)]
+            record: TraceRecord<'a>,
+        }
+
+        let timestamp = OffsetDateTime::now_utc()
+            .format(TIMESTAMP_FORMAT)
+            .unwrap_or_default();
+        let line = match serde_json::to_string(&Line { timestamp, record }) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("failed to serialize HTTP trace record: {e}");
+                return;
+            }
+        };
+        let line = redact_secrets(&line);
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{line}") {
+            warn!("failed to write HTTP trace: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn writes_redacted_json_lines() {
+        let file = NamedTempFile::new().unwrap();
+        let trace = HttpTrace::open(file.path()).unwrap();
+        trace.record_request(
+            "https://example.com/v1/responses",
+            &json!({ "input": "my key is sk-abcdefghijklmnopqrstuvwxyz" }),
+        );
+        trace.record_sse_event("response.completed", r#"{"type":"response.completed"}"#);
+
+        let contents = std::fs::read_to_string(file.path()).unwrap();
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(lines[0]["kind"], "request");
+        assert_eq!(lines[0]["body"]["input"], "my key is [REDACTED]");
+        assert_eq!(lines[1]["kind"], "sse_event");
+        assert_eq!(lines[1]["event"], "response.completed");
+    }
+
+    #[test]
+    fn redacts_keys_inside_escaped_json() {
+        let file = NamedTempFile::new().unwrap();
+        let trace = HttpTrace::open(file.path()).unwrap();
+        let arguments = r#"{"api_key": "0123456789abcdef", "model": "o3"}"#;
+        trace.record_request(
+            "https://example.com/v1/responses",
+            &json!({ "input": [{ "type": "function_call", "arguments": arguments }] }),
+        );
+        let data =
+            json!({ "type": "response.output_item.done", "item": { "arguments": arguments } });
+        trace.record_sse_event("response.output_item.done", &data.to_string());
+
+        let contents = std::fs::read_to_string(file.path()).unwrap();
+        assert!(!contents.contains("0123456789abcdef"), "{contents}");
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let redacted = r#"{"api_key": "[REDACTED]", "model": "o3"}"#;
+        assert_eq!(lines[0]["body"]["input"][0]["arguments"], redacted);
+        let data: Value = serde_json::from_str(lines[1]["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data["item"]["arguments"], redacted);
+    }
+}
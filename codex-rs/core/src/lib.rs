@@ -5,6 +5,8 @@
 // the TUI or the tracing stack).
 #![deny(clippy::print_stdout, clippy::print_stderr)]
 
+mod background_jobs;
+mod cassette;
 mod chat_completions;
 mod client;
 mod client_common;
@@ -15,10 +17,14 @@ pub mod config;
 pub mod config_profile;
 pub mod config_types;
 mod conversation_history;
+mod edit_tool;
 pub mod error;
 pub mod exec;
 pub mod exec_env;
+pub mod exec_timeout;
 mod flags;
+mod git_snapshot;
+mod http_trace;
 mod is_safe_command;
 mod mcp_connection_manager;
 mod mcp_tool_call;
@@ -29,10 +35,18 @@ pub use model_provider_info::WireApi;
 mod models;
 pub mod openai_api_key;
 mod openai_tools;
+mod output_capture;
+mod persistent_shell;
 mod project_doc;
 pub mod protocol;
+#[cfg(unix)]
+mod pty;
+pub mod redact;
 mod rollout;
 mod safety;
+mod shell;
+mod stale_files;
+pub mod undo;
 mod user_notification;
 pub mod util;
 
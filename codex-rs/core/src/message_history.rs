@@ -68,7 +68,7 @@ pub(crate) async fn append_entry(text: &str, session_id: &Uuid, config: &Config)
         }
     }
 
-    // TODO: check `text` for sensitive patterns
+    let text = config.secret_redaction.redact(text);
 
     // Resolve `~/.codex/history.jsonl` and ensure the parent directory exists.
     let path = history_filepath(config);
@@ -86,7 +86,7 @@ pub(crate) async fn append_entry(text: &str, session_id: &Uuid, config: &Config)
     let entry = HistoryEntry {
         session_id: session_id.to_string(),
         ts,
-        text: text.to_string(),
+        text: text.into_owned(),
     };
     let mut line = serde_json::to_string(&entry)
         .map_err(|e| std::io::Error::other(format!("failed to serialise history entry: {e}")))?;
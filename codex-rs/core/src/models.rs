@@ -175,6 +175,67 @@ pub struct ShellToolCallParams {
     // The wire format uses `timeout`, which has ambiguous units, so we use
     // `timeout_ms` as the field name so it is clear in code.
     pub timeout_ms: Option<u64>,
+
+    /// Run the command in a pseudo-terminal.
+    #[serde(default)]
+    pub tty: bool,
+
+    /// Input for the command to read on stdin.
+    pub stdin: Option<String>,
+}
+
+/// Arguments of the `edit_file` function tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EditFileToolCallParams {
+    pub path: String,
+    pub old_string: String,
+    pub new_string: String,
+    #[serde(default)]
+    pub replace_all: bool,
+}
+
+/// Arguments of the `write_file` function tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WriteFileToolCallParams {
+    pub path: String,
+    pub content: String,
+}
+
+/// Arguments of the `exec_background` function tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ExecBackgroundToolCallParams {
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub workdir: Option<String>,
+    /// Run the job in a pseudo-terminal, which also lets `send_input` write
+    /// to it.
+    #[serde(default)]
+    pub tty: bool,
+}
+
+/// Arguments of the `read_output` and `kill` function tools.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BackgroundJobToolCallParams {
+    pub job_id: String,
+}
+
+/// Arguments of the `send_input` function tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SendInputToolCallParams {
+    pub job_id: String,
+    pub input: String,
+}
+
+/// Arguments of the `persistent_shell` function tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PersistentShellToolCallParams {
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Replace the shell with a fresh one before running `command`.
+    #[serde(default)]
+    pub restart: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -274,6 +335,8 @@ mod tests {
                 command: vec!["ls".to_string(), "-l".to_string()],
                 workdir: Some("/tmp".to_string()),
                 timeout_ms: Some(1000),
+                tty: false,
+                stdin: None,
             },
             params
         );
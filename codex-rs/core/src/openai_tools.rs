@@ -30,6 +30,7 @@ pub(crate) enum OpenAiTool {
 pub(crate) enum JsonSchema {
     String,
     Number,
+    Boolean,
     Array {
         items: Box<JsonSchema>,
     },
@@ -52,18 +53,141 @@ static DEFAULT_TOOLS: LazyLock<Vec<OpenAiTool>> = LazyLock::new(|| {
     );
     properties.insert("workdir".to_string(), JsonSchema::String);
     properties.insert("timeout".to_string(), JsonSchema::Number);
+    properties.insert("tty".to_string(), JsonSchema::Boolean);
+    properties.insert("stdin".to_string(), JsonSchema::String);
 
-    vec![OpenAiTool::Function(ResponsesApiTool {
-        name: "shell",
-        description: "Runs a shell command, and returns its output.",
+    vec![
+        OpenAiTool::Function(ResponsesApiTool {
+            name: "shell",
+            description: "Runs a shell command, and returns its output. Set `tty` to run it in a pseudo-terminal, for programs that behave differently when not attached to one; stdout and stderr are then combined. Pass data the command should read on standard input in `stdin`, rather than writing it to a temporary file or a heredoc.",
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties,
+                required: &["command"],
+                additional_properties: false,
+            },
+        }),
+        create_edit_file_tool(),
+        create_write_file_tool(),
+        create_exec_background_tool(),
+        create_background_job_tool(
+            "read_output",
+            "Returns the output a background job produced since the previous read_output call, and whether it is still running. Once a job has exited and its output has been read, its id is no longer valid.",
+        ),
+        create_background_job_tool("kill", "Stops a background job."),
+        create_send_input_tool(),
+    ]
+});
+
+fn create_edit_file_tool() -> OpenAiTool {
+    let properties = BTreeMap::from([
+        ("path".to_string(), JsonSchema::String),
+        ("old_string".to_string(), JsonSchema::String),
+        ("new_string".to_string(), JsonSchema::String),
+        ("replace_all".to_string(), JsonSchema::Boolean),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: "edit_file",
+        description: "Replaces `old_string` with `new_string` in an existing file. `old_string` must match the file exactly, including whitespace, and must occur exactly once unless `replace_all` is true.",
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: &["path", "old_string", "new_string"],
+            additional_properties: false,
+        },
+    })
+}
+
+fn create_write_file_tool() -> OpenAiTool {
+    let properties = BTreeMap::from([
+        ("path".to_string(), JsonSchema::String),
+        ("content".to_string(), JsonSchema::String),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: "write_file",
+        description: "Creates a file with the given content, or replaces the content of an existing file.",
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: &["path", "content"],
+            additional_properties: false,
+        },
+    })
+}
+
+fn create_exec_background_tool() -> OpenAiTool {
+    let properties = BTreeMap::from([
+        (
+            "command".to_string(),
+            JsonSchema::Array {
+                items: Box::new(JsonSchema::String),
+            },
+        ),
+        ("workdir".to_string(), JsonSchema::String),
+        ("tty".to_string(), JsonSchema::Boolean),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: "exec_background",
+        description: "Starts a long-running command, such as a dev server or file watcher, without waiting for it to exit, and returns a job id. Use read_output to see its output and kill to stop it. Jobs are stopped when the session ends, and processes a job leaves behind are stopped when it exits, so do not start daemons that detach from it. Set `tty` to run it in a pseudo-terminal, which is needed to interact with it through send_input.",
         strict: false,
         parameters: JsonSchema::Object {
             properties,
             required: &["command"],
             additional_properties: false,
         },
-    })]
-});
+    })
+}
+
+fn create_background_job_tool(name: &'static str, description: &'static str) -> OpenAiTool {
+    let properties = BTreeMap::from([("job_id".to_string(), JsonSchema::String)]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name,
+        description,
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: &["job_id"],
+            additional_properties: false,
+        },
+    })
+}
+
+fn create_send_input_tool() -> OpenAiTool {
+    let properties = BTreeMap::from([
+        ("job_id".to_string(), JsonSchema::String),
+        ("input".to_string(), JsonSchema::String),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: "send_input",
+        description: "Types `input` into a background job started with `tty`, such as a REPL or an interactive prompt. Include \"\\n\" to press Enter and control characters such as \"\\u0003\" for Ctrl-C. If the job runs outside the sandbox, the user is asked to approve each input.",
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: &["job_id", "input"],
+            additional_properties: false,
+        },
+    })
+}
+
+fn create_persistent_shell_tool() -> OpenAiTool {
+    let properties = BTreeMap::from([
+        ("command".to_string(), JsonSchema::String),
+        ("timeout_ms".to_string(), JsonSchema::Number),
+        ("restart".to_string(), JsonSchema::Boolean),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: "persistent_shell",
+        description: "Runs a bash command in a shell that persists for the whole session, so the working directory, environment variables and activated virtualenvs carry over between calls. Set `restart` to start over with a fresh shell; if the shell exits or a command times out, a fresh shell is started for the next call. Commands always run in the session's sandbox, even when the user approves them; use the `shell` tool for commands that need more access.",
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: &[],
+            additional_properties: false,
+        },
+    })
+}
+
+static PERSISTENT_SHELL_TOOL: LazyLock<OpenAiTool> = LazyLock::new(create_persistent_shell_tool);
 
 static DEFAULT_CODEX_MODEL_TOOLS: LazyLock<Vec<OpenAiTool>> =
     LazyLock::new(|| vec![OpenAiTool::LocalShell {}]);
@@ -81,14 +205,20 @@ pub(crate) fn create_tools_json_for_responses_api(
     } else {
         &DEFAULT_TOOLS
     };
-    let mut tools_json = Vec::with_capacity(default_tools.len() + prompt.extra_tools.len());
+    let mut tools_json = Vec::with_capacity(default_tools.len() + prompt.extra_tools.len() + 1);
     for t in default_tools.iter() {
         tools_json.push(serde_json::to_value(t)?);
     }
+    if prompt.persistent_shell {
+        tools_json.push(serde_json::to_value(&*PERSISTENT_SHELL_TOOL)?);
+    }
+    // Sort MCP tools by name: `extra_tools` is a HashMap, and an order that
+    // changes from request to request would defeat provider-side prompt
+    // caching, which only matches on an identical prefix.
+    let mut extra_tools: Vec<_> = prompt.extra_tools.clone().into_iter().collect();
+    extra_tools.sort_by(|(a, _), (b, _)| a.cmp(b));
     tools_json.extend(
-        prompt
-            .extra_tools
-            .clone()
+        extra_tools
             .into_iter()
             .map(|(name, tool)| mcp_tool_to_openai_tool(name, tool)),
     );
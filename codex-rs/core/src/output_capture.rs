@@ -0,0 +1,380 @@
+//! Head-and-tail truncation of a command's output stream.
+//!
+//! The interesting part of a long build or test run is often at the end (the
+//! compiler error, the failing test summary), so rather than keeping only the
+//! first N bytes we keep the first and last half of the budget and elide the
+//! middle. Once a stream exceeds its limits, everything it produces is also
+//! written to a spill file so nothing is lost. The spill file is offered to
+//! the model, so secrets in it are redacted once the stream ends. Spill files
+//! are private to the user, and those of old sessions are deleted by
+//! [`prune_shell_output`].
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::config_types::ShellOutputLimits;
+use crate::redact::SecretRedactor;
+
+/// Subdirectory of `codex_home` where the complete output of truncated
+/// commands is saved, in one directory per session and tool call.
+pub(crate) const SHELL_OUTPUT_SUBDIR: &str = "shell_output";
+
+/// How long the saved output of a session is kept after it was last written.
+const SHELL_OUTPUT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Deletes the session directories under `shell_output_root` that have not
+/// been written to for [`SHELL_OUTPUT_RETENTION`].
+pub(crate) fn prune_shell_output(shell_output_root: &Path) -> std::io::Result<()> {
+    let entries = match std::fs::read_dir(shell_output_root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let expired = now
+            .duration_since(metadata.modified()?)
+            .is_ok_and(|age| age > SHELL_OUTPUT_RETENTION);
+        if metadata.is_dir() && expired {
+            std::fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes a tool call id safe to use as a single path component.
+pub(crate) fn file_name_for_call_id(call_id: &str) -> String {
+    call_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Accumulates one output stream, keeping at most `limits` of it.
+pub(crate) struct TruncatingCapture {
+    limits: ShellOutputLimits,
+    spill_path: Option<PathBuf>,
+    redactor: SecretRedactor,
+
+    /// All output until the limits are exceeded; afterwards only the head.
+    head: Vec<u8>,
+    /// Set once the limits are exceeded.
+    overflow: Option<Overflow>,
+
+    total_bytes: usize,
+    total_lines: usize,
+}
+
+struct Overflow {
+    /// The most recent output, at most the tail's byte budget.
+    tail: VecDeque<u8>,
+    /// Receives the complete output; `None` if it could not be created.
+    spill: Option<BufWriter<File>>,
+}
+
+impl TruncatingCapture {
+    /// `spill_path` is where the complete output is written if it has to be
+    /// truncated. The file is only created when that happens.
+    pub(crate) fn new(
+        limits: ShellOutputLimits,
+        spill_path: Option<PathBuf>,
+        redactor: SecretRedactor,
+    ) -> Self {
+        Self {
+            limits,
+            spill_path,
+            redactor,
+            head: Vec::new(),
+            overflow: None,
+            total_bytes: 0,
+            total_lines: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        self.total_bytes += chunk.len();
+        self.total_lines += count_lines(chunk);
+        let tail_bytes = self.tail_bytes();
+
+        let Some(overflow) = &mut self.overflow else {
+            self.head.extend_from_slice(chunk);
+            if self.total_bytes > self.limits.max_bytes || self.total_lines > self.limits.max_lines
+            {
+                self.start_overflow();
+            }
+            return;
+        };
+
+        overflow.tail.extend(chunk);
+        let excess = overflow.tail.len().saturating_sub(tail_bytes);
+        overflow.tail.drain(..excess);
+        if let Some(spill) = &mut overflow.spill
+            && let Err(e) = spill.write_all(chunk)
+        {
+            tracing::warn!("failed to write full command output: {e}");
+            overflow.spill = None;
+        }
+    }
+
+    /// Splits the output read so far into head and tail and starts spilling.
+    fn start_overflow(&mut self) {
+        let spill = self.spill_path.as_ref().and_then(|path| {
+            let file = path
+                .parent()
+                .map_or(Ok(()), create_private_dir_all)
+                .and_then(|()| create_private_file(path));
+            let mut writer = match file {
+                Ok(file) => BufWriter::new(file),
+                Err(e) => {
+                    tracing::warn!("failed to create {}: {e}", path.display());
+                    return None;
+                }
+            };
+            match writer.write_all(&self.head) {
+                Ok(()) => Some(writer),
+                Err(e) => {
+                    tracing::warn!("failed to write {}: {e}", path.display());
+                    None
+                }
+            }
+        });
+
+        let head_len = self.head_len();
+        let rest = self.head.split_off(head_len);
+        let mut tail: VecDeque<u8> = rest.into();
+        let excess = tail.len().saturating_sub(self.tail_bytes());
+        tail.drain(..excess);
+        self.overflow = Some(Overflow { tail, spill });
+    }
+
+    /// Length of the head: at most half of each budget, ending on a line
+    /// boundary if the line budget is what limits it.
+    fn head_len(&self) -> usize {
+        let max_bytes = self.limits.max_bytes / 2;
+        let max_lines = self.limits.max_lines / 2;
+        let line_end = match max_lines.checked_sub(1) {
+            None => 0,
+            Some(last_line) => self
+                .head
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .nth(last_line)
+                .map_or(self.head.len(), |(i, _)| i + 1),
+        };
+        line_end.min(max_bytes)
+    }
+
+    fn tail_bytes(&self) -> usize {
+        self.limits.max_bytes - self.limits.max_bytes / 2
+    }
+
+    fn tail_lines(&self) -> usize {
+        self.limits.max_lines - self.limits.max_lines / 2
+    }
+
+    /// Returns the captured output: all of it if it fit within the limits,
+    /// otherwise the head, an elision marker, and the tail.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        let tail_lines = self.tail_lines();
+        let Some(Overflow { tail, spill }) = self.overflow else {
+            return self.head;
+        };
+        let spilled = match (spill.map(|mut spill| spill.flush()), &self.spill_path) {
+            (Some(Ok(())), Some(path)) => match redact_file(path, &self.redactor) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    // Rather than leave secrets in the file, drop it.
+                    tracing::warn!("failed to redact {}: {e}", path.display());
+                    let _ = std::fs::remove_file(path);
+                    None
+                }
+            },
+            (Some(Err(e)), _) => {
+                tracing::warn!("failed to write full command output: {e}");
+                None
+            }
+            _ => None,
+        };
+
+        let mut tail: Vec<u8> = tail.into();
+        // Keep only the last `tail_lines` lines, not counting a trailing
+        // newline as the start of another line.
+        let body_end = tail.len() - usize::from(tail.last() == Some(&b'\n'));
+        if let Some((start, _)) = tail[..body_end]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(tail_lines.saturating_sub(1))
+        {
+            tail.drain(..=start);
+        }
+        if tail_lines == 0 {
+            tail.clear();
+        }
+
+        let omitted_bytes = self.total_bytes - self.head.len() - tail.len();
+        let omitted_lines = self
+            .total_lines
+            .saturating_sub(count_lines(&self.head) + count_lines(&tail));
+
+        let mut output = self.head;
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            output.push(b'\n');
+        }
+        let marker = match spilled {
+            Some(path) => format!(
+                "[... {omitted_lines} lines ({omitted_bytes} bytes) omitted; full output saved to {} ...]\n",
+                path.display()
+            ),
+            None => format!("[... {omitted_lines} lines ({omitted_bytes} bytes) omitted ...]\n"),
+        };
+        output.extend_from_slice(marker.as_bytes());
+        output.extend_from_slice(&tail);
+        output
+    }
+}
+
+/// Command output can contain anything the user can read, so only they may
+/// read the spill files.
+fn create_private_dir_all(path: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+fn create_private_file(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Rewrites the file at `path` with secrets redacted, if it contains any.
+fn redact_file(path: &Path, redactor: &SecretRedactor) -> std::io::Result<()> {
+    let bytes = std::fs::read(path)?;
+    if let Cow::Owned(redacted) = redactor.redact(&String::from_utf8_lossy(&bytes)) {
+        std::fs::write(path, redacted)?;
+    }
+    Ok(())
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| **b == b'\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    fn numbered_lines(range: std::ops::RangeInclusive<usize>) -> String {
+        range.map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn keeps_short_output_intact() {
+        let mut capture = TruncatingCapture::new(
+            ShellOutputLimits::default(),
+            None,
+            SecretRedactor::default(),
+        );
+        capture.push(b"hello\n");
+        capture.push(b"world\n");
+        assert_eq!(capture.finish(), b"hello\nworld\n");
+    }
+
+    #[test]
+    fn keeps_head_and_tail_and_spills_everything() {
+        let dir = tempdir().unwrap();
+        let spill_path = dir.path().join("call").join("stdout");
+        let limits = ShellOutputLimits {
+            max_bytes: 1024,
+            max_lines: 4,
+        };
+        let mut capture =
+            TruncatingCapture::new(limits, Some(spill_path.clone()), SecretRedactor::default());
+        let input = numbered_lines(1..=10);
+        for chunk in input.as_bytes().chunks(7) {
+            capture.push(chunk);
+        }
+
+        let output = String::from_utf8(capture.finish()).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "line 1\nline 2\n[... 6 lines (42 bytes) omitted; full output saved to {} ...]\nline 9\nline 10\n",
+                spill_path.display()
+            )
+        );
+        assert_eq!(std::fs::read_to_string(&spill_path).unwrap(), input);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&spill_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn prunes_only_expired_session_directories() {
+        let root = tempdir().unwrap();
+        let old = root.path().join("old-session");
+        let recent = root.path().join("recent-session");
+        for dir in [&old, &recent] {
+            std::fs::create_dir_all(dir.join("call")).unwrap();
+            std::fs::write(dir.join("call").join("stdout"), "output").unwrap();
+        }
+        let expired = SystemTime::now() - SHELL_OUTPUT_RETENTION - Duration::from_secs(60);
+        File::open(&old).unwrap().set_modified(expired).unwrap();
+
+        prune_shell_output(root.path()).unwrap();
+        assert!(!old.exists());
+        assert!(recent.join("call").join("stdout").exists());
+        prune_shell_output(&root.path().join("missing")).unwrap();
+    }
+
+    #[test]
+    fn truncates_long_lines_by_bytes() {
+        let limits = ShellOutputLimits {
+            max_bytes: 10,
+            max_lines: 256,
+        };
+        let mut capture = TruncatingCapture::new(limits, None, SecretRedactor::default());
+        capture.push(&[b'a'; 20]);
+        capture.push(&[b'b'; 20]);
+        assert_eq!(
+            String::from_utf8(capture.finish()).unwrap(),
+            "aaaaa\n[... 0 lines (30 bytes) omitted ...]\nbbbbb"
+        );
+    }
+}
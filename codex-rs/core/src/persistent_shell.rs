@@ -0,0 +1,288 @@
+//! A long-lived `bash` for the opt-in `persistent_shell` tool.
+//!
+//! The `shell` tool spawns a fresh process for every call, so `cd`, `export`,
+//! activated virtualenvs and sourced scripts are lost between calls. Here one
+//! shell is kept per session, spawned under the same sandbox as regular
+//! commands. Each command is run with `eval` and followed by a sentinel line
+//! on stdout and stderr; the one on stdout carries the exit code and the
+//! shell's working directory, and both mark where the command's output ends.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::process::Child;
+use tokio::process::ChildStderr;
+use tokio::process::ChildStdin;
+use tokio::process::ChildStdout;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::config_types::ShellOutputLimits;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::error::SandboxErr;
+use crate::exec::DEFAULT_TIMEOUT_MS;
+use crate::exec::ExecToolCallOutput;
+use crate::exec::SandboxType;
+use crate::exec::StdioPolicy;
+use crate::exec::Termination;
+use crate::exec::kill_process_group;
+use crate::exec::spawn_command_under_sandbox;
+use crate::output_capture::TruncatingCapture;
+use crate::protocol::SandboxPolicy;
+use crate::redact::SecretRedactor;
+
+pub(crate) struct PersistentShell {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: BufReader<ChildStderr>,
+    /// Random marker that command output is unlikely to contain.
+    sentinel: String,
+    /// Working directory as of the end of the previous command.
+    cwd: PathBuf,
+}
+
+/// Result of a command that ran to completion, or that ended the shell.
+pub(crate) struct PersistentShellOutput {
+    pub output: ExecToolCallOutput,
+    /// The command made the shell itself exit (e.g. `exit 1`), so it must be
+    /// replaced before running another command.
+    pub shell_exited: bool,
+}
+
+impl PersistentShell {
+    pub(crate) async fn spawn(
+        cwd: PathBuf,
+        env: HashMap<String, String>,
+        sandbox_type: SandboxType,
+        sandbox_policy: &SandboxPolicy,
+        codex_linux_sandbox_exe: &Option<PathBuf>,
+    ) -> Result<Self> {
+        let command = ["bash", "--noprofile", "--norc"].map(String::from).to_vec();
+        let mut child = spawn_command_under_sandbox(
+            command,
+            cwd.clone(),
+            env,
+            sandbox_type,
+            sandbox_policy,
+            codex_linux_sandbox_exe,
+            StdioPolicy::Interactive,
+        )
+        .await?;
+
+        let missing = |name: &str| {
+            CodexErr::Io(io::Error::other(format!(
+                "{name} pipe was unexpectedly not available"
+            )))
+        };
+        let stdin = child.stdin.take().ok_or_else(|| missing("stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| missing("stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| missing("stderr"))?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr: BufReader::new(stderr),
+            sentinel: format!("__CODEX_COMMAND_DONE_{}__", Uuid::new_v4().simple()),
+            cwd,
+        })
+    }
+
+    /// The directory the next command starts in, which earlier commands may
+    /// have changed with `cd`.
+    pub(crate) fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Runs `command` in the shell. On timeout or interrupt the shell and its
+    /// process group are stopped, mirroring `consume_truncated_output`, and an
+    /// error is returned; the shell must not be used after any error.
+    pub(crate) async fn run(
+        &mut self,
+        command: &str,
+        timeout_ms: Option<u64>,
+        ctrl_c: Arc<Notify>,
+        output_limits: ShellOutputLimits,
+        full_output_dir: Option<PathBuf>,
+        redactor: &SecretRedactor,
+    ) -> Result<PersistentShellOutput> {
+        let start = Instant::now();
+        let sentinel = &self.sentinel;
+        // `< /dev/null` keeps the command from consuming the script we feed
+        // the shell on stdin.
+        let script = format!(
+            "eval {} < /dev/null\nprintf '\\n{sentinel}%s %s\\n' \"$?\" \"$PWD\"\nprintf '\\n{sentinel}\\n' >&2\n",
+            single_quote(command)
+        );
+        self.stdin.write_all(script.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let stdout_capture = TruncatingCapture::new(
+            output_limits,
+            full_output_dir.as_ref().map(|dir| dir.join("stdout")),
+            redactor.clone(),
+        );
+        let stderr_capture = TruncatingCapture::new(
+            output_limits,
+            full_output_dir.as_ref().map(|dir| dir.join("stderr")),
+            redactor.clone(),
+        );
+        let read_both = async {
+            tokio::try_join!(
+                read_until_sentinel(&mut self.stdout, sentinel, stdout_capture),
+                read_until_sentinel(&mut self.stderr, sentinel, stderr_capture),
+            )
+        };
+
+        let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+        // If the command has to be stopped, the error to report afterwards.
+        type Stopped = fn(Termination) -> SandboxErr;
+        let result = tokio::select! {
+            result = tokio::time::timeout(timeout, read_both) => {
+                result.map_err(|_| SandboxErr::Timeout as Stopped)
+            }
+            _ = ctrl_c.notified() => Err(SandboxErr::Interrupted as Stopped),
+        };
+        let ((stdout, status), (stderr, _)) = match result {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                self.child.start_kill()?;
+                return Err(e);
+            }
+            Err(stopped) => {
+                let termination = kill_process_group(&mut self.child).await?;
+                return Err(CodexErr::Sandbox(stopped(termination)));
+            }
+        };
+
+        let (exit_code, shell_exited) = match status {
+            Some(status) => {
+                let status = status.trim_end_matches(['\r', '\n']);
+                let (exit_code, cwd) = status.split_once(' ').unwrap_or((status, ""));
+                if !cwd.is_empty() {
+                    self.cwd = PathBuf::from(cwd);
+                }
+                (exit_code.parse().unwrap_or(-1), false)
+            }
+            None => {
+                let exit_status = self.child.wait().await?;
+                (exit_status.code().unwrap_or(-1), true)
+            }
+        };
+        Ok(PersistentShellOutput {
+            output: ExecToolCallOutput {
+                exit_code,
+                stdout: String::from_utf8_lossy(&stdout).to_string(),
+                stderr: String::from_utf8_lossy(&stderr).to_string(),
+                duration: start.elapsed(),
+            },
+            shell_exited,
+        })
+    }
+}
+
+/// Reads output until a line starting with `sentinel`, returning the output
+/// before it and the rest of the sentinel line, or `None` at end of file.
+async fn read_until_sentinel<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    sentinel: &str,
+    mut capture: TruncatingCapture,
+) -> Result<(Vec<u8>, Option<String>)> {
+    let mut line = Vec::new();
+    // The newline ending the previous line is held back, because the one
+    // right before the sentinel was printed by us rather than the command.
+    let mut held_newline = false;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            if held_newline {
+                capture.push(b"\n");
+            }
+            return Ok((capture.finish(), None));
+        }
+        if let Some(rest) = line.strip_prefix(sentinel.as_bytes()) {
+            return Ok((
+                capture.finish(),
+                Some(String::from_utf8_lossy(rest).to_string()),
+            ));
+        }
+        if held_newline {
+            capture.push(b"\n");
+        }
+        held_newline = line.ends_with(b"\n");
+        capture.push(&line[..line.len() - usize::from(held_newline)]);
+    }
+}
+
+fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    async fn run(shell: &mut PersistentShell, command: &str) -> PersistentShellOutput {
+        shell
+            .run(
+                command,
+                None,
+                Arc::new(Notify::new()),
+                ShellOutputLimits::default(),
+                None,
+                &SecretRedactor::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn state_persists_between_commands() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let env = HashMap::from([("PATH".to_string(), std::env::var("PATH").unwrap())]);
+        let mut shell = PersistentShell::spawn(
+            dir.path().to_path_buf(),
+            env,
+            SandboxType::None,
+            &SandboxPolicy::new_full_auto_policy(),
+            &None,
+        )
+        .await
+        .unwrap();
+
+        run(&mut shell, "cd sub && export GREETING='it''s here'").await;
+        let result = run(&mut shell, "pwd; echo \"$GREETING\"; echo oops >&2; false").await;
+        assert_eq!(result.output.exit_code, 1);
+        assert!(
+            result.output.stdout.ends_with("/sub\nits here\n"),
+            "{}",
+            result.output.stdout
+        );
+        assert_eq!(result.output.stderr, "oops\n");
+        assert!(!result.shell_exited);
+        assert_eq!(
+            shell.cwd().canonicalize().unwrap(),
+            dir.path().join("sub").canonicalize().unwrap()
+        );
+
+        let result = run(&mut shell, "printf 'no newline'; exit 3").await;
+        assert_eq!(result.output.stdout, "no newline");
+        assert_eq!(result.output.exit_code, 3);
+        assert!(result.shell_exited);
+    }
+}
@@ -68,6 +68,12 @@ pub enum Op {
         /// `ConfigureSession` operation so that the business-logic layer can
         /// operate deterministically.
         cwd: std::path::PathBuf,
+
+        /// JSON schema the final assistant message of each task must conform
+        /// to. Sent to the model as a structured output format.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        output_schema: Option<serde_json::Value>,
     },
 
     /// Abort current task.
@@ -107,6 +113,19 @@ pub enum Op {
 
     /// Request a single history entry identified by `log_id` + `offset`.
     GetHistoryEntryRequest { offset: usize, log_id: u64 },
+
+    /// Revert the most recent patch applied in this session, restoring every
+    /// file it touched. Answered with `PatchUndone` or `Error`.
+    UndoLastPatch,
+
+    /// Roll the working tree back to the snapshot taken at the start of
+    /// `turn` (1-based, one per user message). Only available for sessions
+    /// inside a git repo. Answered with `SnapshotRestored` or `Error`.
+    RestoreSnapshot { turn: u64 },
+
+    /// Request a diff of the working tree against the snapshot taken at the
+    /// start of the session. Answered with `SessionDiff` or `Error`.
+    GetSessionDiff,
 }
 
 /// Determines how liberally commands are auto‑approved by the system.
@@ -332,6 +351,10 @@ pub enum EventMsg {
     /// Agent text output message
     AgentMessage(AgentMessageEvent),
 
+    /// Size of the request just sent to the model. Meant for debugging;
+    /// front-ends need not display it.
+    ModelRequestSize(ModelRequestSizeEvent),
+
     /// Reasoning event from agent.
     AgentReasoning(AgentReasoningEvent),
 
@@ -345,8 +368,17 @@ pub enum EventMsg {
     /// Notification that the server is about to execute a command.
     ExecCommandBegin(ExecCommandBeginEvent),
 
+    /// Incremental chunk of output from a running command.
+    ExecCommandOutputDelta(ExecCommandOutputDeltaEvent),
+
     ExecCommandEnd(ExecCommandEndEvent),
 
+    /// A background job was started with the `exec_background` tool.
+    BackgroundJobBegin(BackgroundJobBeginEvent),
+
+    /// A background job exited, either on its own or because it was killed.
+    BackgroundJobEnd(BackgroundJobEndEvent),
+
     ExecApprovalRequest(ExecApprovalRequestEvent),
 
     ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent),
@@ -360,8 +392,21 @@ pub enum EventMsg {
     /// Notification that a patch application has finished.
     PatchApplyEnd(PatchApplyEndEvent),
 
+    /// A patch was not applied because files it touches changed on disk after
+    /// it was proposed.
+    PatchConflict(PatchConflictEvent),
+
     /// Response to GetHistoryEntryRequest.
     GetHistoryEntryResponse(GetHistoryEntryResponseEvent),
+
+    /// Response to UndoLastPatch.
+    PatchUndone(PatchUndoneEvent),
+
+    /// Response to RestoreSnapshot.
+    SnapshotRestored(SnapshotRestoredEvent),
+
+    /// Response to GetSessionDiff.
+    SessionDiff(SessionDiffEvent),
 }
 
 // Individual event payload types matching each `EventMsg` variant.
@@ -423,6 +468,27 @@ pub struct ExecCommandBeginEvent {
     pub command: Vec<String>,
     /// The command's working directory if not the default cwd for the agent.
     pub cwd: PathBuf,
+    /// Time after which the command is stopped, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecOutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExecCommandOutputDeltaEvent {
+    /// Identifier for the ExecCommandBegin that produced this output.
+    pub call_id: String,
+    /// Which stream the output was read from.
+    pub stream: ExecOutputStream,
+    /// Raw bytes as read from the stream. A chunk is not necessarily split on
+    /// a line or UTF-8 character boundary.
+    #[serde(with = "serde_bytes")]
+    pub chunk: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -437,12 +503,47 @@ pub struct ExecCommandEndEvent {
     pub exit_code: i32,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackgroundJobBeginEvent {
+    /// Identifier the model uses to refer to the job, paired with the
+    /// BackgroundJobEnd event.
+    pub job_id: String,
+    /// The command being run.
+    pub command: Vec<String>,
+    /// The command's working directory.
+    pub cwd: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackgroundJobEndEvent {
+    /// Identifier of the BackgroundJobBegin that finished.
+    pub job_id: String,
+    /// The job's exit code, or `None` if it was terminated by a signal.
+    pub exit_code: Option<i32>,
+}
+
+/// Serialized size, in bytes, of each part of a request sent to the model,
+/// for diagnosing slow or expensive sessions.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModelRequestSizeEvent {
+    pub instructions_bytes: usize,
+    pub tools_bytes: usize,
+    /// Items the model has already seen in an earlier request.
+    pub history_bytes: usize,
+    /// Items appended since the model's last output.
+    pub new_input_bytes: usize,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ExecApprovalRequestEvent {
     /// The command to be executed.
     pub command: Vec<String>,
     /// The command's working directory.
     pub cwd: PathBuf,
+    /// Input the command will read on stdin. It is data for the command, not
+    /// something the shell runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
     /// Optional human-readable reason for the approval (e.g. retry without sandbox).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
@@ -486,6 +587,31 @@ pub struct PatchApplyEndEvent {
     pub success: bool,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatchConflictEvent {
+    /// Identifier of the `apply_patch` call that was refused.
+    pub call_id: String,
+    /// Files whose contents changed since the patch was proposed.
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatchUndoneEvent {
+    /// Files restored to their state before the patch.
+    pub restored: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SnapshotRestoredEvent {
+    pub turn: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionDiffEvent {
+    /// Unified diff; empty if nothing changed since the session started.
+    pub diff: String,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GetHistoryEntryResponseEvent {
     pub offset: usize,
@@ -539,6 +665,9 @@ pub enum FileChange {
         content: String,
     },
     Delete,
+    Symlink {
+        target: PathBuf,
+    },
     Update {
         unified_diff: String,
         move_path: Option<PathBuf>,
@@ -0,0 +1,103 @@
+//! Pseudo-terminal support for commands run with `tty: true`.
+//!
+//! Tool calls normally get pipes for stdout and stderr and `/dev/null` for
+//! stdin, so programs that check `isatty` drop colors, refuse to prompt, or
+//! exit. In PTY mode the child gets the slave side of a new pseudo-terminal
+//! as its controlling terminal and all three standard streams, and Codex
+//! reads the combined output from, and writes input to, the master side.
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+
+use tokio::process::Child;
+use tokio::process::ChildStdin;
+use tokio::process::ChildStdout;
+use tokio::process::Command;
+
+/// Terminal size reported to the child. Wide, so that output meant for the
+/// model is not wrapped.
+const PTY_COLS: u16 = 200;
+const PTY_ROWS: u16 = 50;
+
+/// Master side of a pseudo-terminal whose slave side was given to a command.
+pub(crate) struct PtyMaster(OwnedFd);
+
+/// Opens a pseudo-terminal and configures `cmd` to use it as its controlling
+/// terminal and standard streams. Once `cmd` is spawned, the returned master
+/// must be handed to the child with [`PtyMaster::attach_to`].
+pub(crate) fn attach_pty(cmd: &mut Command) -> io::Result<PtyMaster> {
+    let mut master = -1;
+    let mut slave = -1;
+    let mut winsize = libc::winsize {
+        ws_row: PTY_ROWS,
+        ws_col: PTY_COLS,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: `openpty` only writes the two descriptors; the name and termios
+    // arguments may be null.
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // `*mut` on macOS, `*const` elsewhere.
+            std::ptr::addr_of_mut!(winsize),
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `openpty` succeeded, so both are open descriptors we now own.
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    // `openpty` does not set close-on-exec. The master must not leak into the
+    // child, or the terminal would never report end of output.
+    set_cloexec(&master)?;
+    set_cloexec(&slave)?;
+
+    cmd.stdin(slave.try_clone()?)
+        .stdout(slave.try_clone()?)
+        .stderr(slave);
+    // SAFETY: only async-signal-safe functions are called between fork and
+    // exec.
+    unsafe {
+        cmd.pre_exec(|| {
+            // A new session has no controlling terminal, so the PTY (already
+            // on fd 0) can become it.
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(PtyMaster(master))
+}
+
+impl PtyMaster {
+    /// Installs the master as the child's stdin and stdout. The child has no
+    /// separate stderr: it is interleaved with stdout on the terminal.
+    pub(crate) fn attach_to(self, child: &mut Child) -> io::Result<()> {
+        let input = std::process::ChildStdin::from(self.0.try_clone()?);
+        child.stdin = Some(ChildStdin::from_std(input)?);
+        let output = std::process::ChildStdout::from(self.0);
+        child.stdout = Some(ChildStdout::from_std(output)?);
+        Ok(())
+    }
+}
+
+/// Whether `e` is the error a PTY master returns on read once every slave
+/// descriptor has been closed, i.e. the end of the output.
+pub(crate) fn is_closed_error(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EIO)
+}
+
+fn set_cloexec(fd: &OwnedFd) -> io::Result<()> {
+    // SAFETY: `fd` is an open descriptor.
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -18,6 +18,7 @@ use uuid::Uuid;
 
 use crate::config::Config;
 use crate::models::ResponseItem;
+use crate::redact::SecretRedactor;
 
 /// Folder inside `~/.codex` that holds saved rollouts.
 const SESSIONS_SUBDIR: &str = "sessions";
@@ -42,6 +43,7 @@ struct SessionMeta {
 #[derive(Clone)]
 pub(crate) struct RolloutRecorder {
     tx: Sender<String>,
+    redactor: SecretRedactor,
 }
 
 impl RolloutRecorder {
@@ -101,7 +103,10 @@ impl RolloutRecorder {
             }
         });
 
-        let recorder = Self { tx };
+        let recorder = Self {
+            tx,
+            redactor: config.secret_redaction.clone(),
+        };
         // Ensure SessionMeta is the first item in the file.
         recorder.record_item(&meta).await?;
         Ok(recorder)
@@ -131,8 +136,14 @@ impl RolloutRecorder {
     async fn record_item(&self, item: &impl Serialize) -> std::io::Result<()> {
         // Serialize the item to JSON first so that the writer thread only has
         // to perform the actual write.
-        let json = serde_json::to_string(item)
-            .map_err(|e| IoError::other(format!("failed to serialize response items: {e}")))?;
+        let serialize_error = |e: serde_json::Error| {
+            IoError::other(format!("failed to serialize response items: {e}"))
+        };
+        // Redacting string values rather than the serialized line keeps the
+        // line valid JSON.
+        let mut value = serde_json::to_value(item).map_err(serialize_error)?;
+        self.redactor.redact_json(&mut value);
+        let json = serde_json::to_string(&value).map_err(serialize_error)?;
 
         self.tx
             .send(json)
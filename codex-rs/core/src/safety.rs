@@ -68,22 +68,39 @@ pub fn assess_command_safety(
     approval_policy: AskForApproval,
     sandbox_policy: &SandboxPolicy,
     approved: &HashSet<Vec<String>>,
+    stdin: Option<&str>,
 ) -> SafetyCheck {
     let approve_without_sandbox = || SafetyCheck::AutoApprove {
         sandbox_type: SandboxType::None,
     };
 
+    // An approval covers the command, not what it reads on stdin, which an
+    // interpreter such as `bash` or `python3` runs as code. So commands given
+    // stdin are never matched against earlier approvals.
+    let previously_approved = stdin.is_none() && approved.contains(command);
+
     // Previously approved or allow-listed commands
     // All approval modes allow these commands to continue without sandboxing
-    if is_known_safe_command(command) || approved.contains(command) {
+    if is_known_safe_command(command) || previously_approved {
         // TODO(ragona): I think we should consider running even these inside the sandbox, but it's
         // a change in behavior so I'm keeping it at parity with upstream for now.
         return approve_without_sandbox();
     }
 
-    // Command was not known-safe or allow-listed
+    assess_unlisted_command_safety(approval_policy, sandbox_policy)
+}
+
+/// Safety of a command that is neither known to be safe nor previously
+/// approved, or for which those checks are unsound, such as a command for a
+/// shell whose earlier commands may have redefined `ls` or `PATH`.
+pub fn assess_unlisted_command_safety(
+    approval_policy: AskForApproval,
+    sandbox_policy: &SandboxPolicy,
+) -> SafetyCheck {
     if sandbox_policy.is_unrestricted() {
-        approve_without_sandbox()
+        SafetyCheck::AutoApprove {
+            sandbox_type: SandboxType::None,
+        }
     } else {
         match get_platform_sandbox() {
             // We have a sandbox, so we can approve the command in all modes
@@ -140,16 +157,18 @@ fn is_write_patch_constrained_to_writable_paths(
     }
 
     // Determine whether `path` is inside **any** writable root. Both `path`
-    // and roots are converted to absolute, normalized forms before the
-    // prefix check.
-    let is_path_writable = |p: &PathBuf| {
+    // and roots are converted to absolute, normalized forms with symlinks
+    // resolved before the prefix check, so that a link inside a root cannot
+    // be used to write outside of it. `follow_final` is false for operations
+    // on a link itself (deleting or replacing it).
+    let is_path_writable = |p: &PathBuf, follow_final: bool| {
         let abs = if p.is_absolute() {
             p.clone()
         } else {
             cwd.join(p)
         };
         let abs = match normalize(&abs) {
-            Some(v) => v,
+            Some(v) => resolve_symlinks(&v, follow_final),
             None => return false,
         };
 
@@ -160,25 +179,31 @@ fn is_write_patch_constrained_to_writable_paths(
                 normalize(&cwd.join(root)).unwrap_or_else(|| cwd.join(root))
             };
 
-            abs.starts_with(&root_abs)
+            abs.starts_with(resolve_symlinks(&root_abs, true))
         })
     };
 
     for (path, change) in action.changes() {
         match change {
-            ApplyPatchFileChange::Add { .. } | ApplyPatchFileChange::Delete => {
-                if !is_path_writable(path) {
+            ApplyPatchFileChange::Add { .. } => {
+                if !is_path_writable(path, true) {
+                    return false;
+                }
+            }
+            ApplyPatchFileChange::Delete | ApplyPatchFileChange::Symlink { .. } => {
+                if !is_path_writable(path, false) {
                     return false;
                 }
             }
             ApplyPatchFileChange::Update { move_path, .. } => {
-                if !is_path_writable(path) {
+                // A moved file's source is removed rather than written.
+                if !is_path_writable(path, move_path.is_none()) {
                     return false;
                 }
-                if let Some(dest) = move_path {
-                    if !is_path_writable(dest) {
-                        return false;
-                    }
+                if let Some(dest) = move_path
+                    && !is_path_writable(dest, true)
+                {
+                    return false;
                 }
             }
         }
@@ -187,6 +212,30 @@ fn is_write_patch_constrained_to_writable_paths(
     true
 }
 
+/// Resolves symlinks in the longest existing prefix of `path`; components
+/// that do not exist yet are appended as is. The final component is only
+/// resolved if `follow_final` is true.
+fn resolve_symlinks(path: &Path, follow_final: bool) -> PathBuf {
+    let (mut existing, mut missing) = match (follow_final, path.parent(), path.file_name()) {
+        (false, Some(parent), Some(name)) => (parent.to_path_buf(), vec![name.to_os_string()]),
+        _ => (path.to_path_buf(), Vec::new()),
+    };
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            let mut resolved = resolved;
+            resolved.extend(missing.iter().rev());
+            return resolved;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::unwrap_used)]
@@ -224,4 +273,74 @@ mod tests {
             &cwd,
         ))
     }
+
+    #[test]
+    fn approvals_do_not_cover_commands_given_stdin() {
+        let command = vec!["python3".to_string()];
+        let approved = HashSet::from([command.clone()]);
+        let policy = SandboxPolicy::new_read_only_policy();
+        let assess = |stdin| {
+            assess_command_safety(
+                &command,
+                AskForApproval::UnlessAllowListed,
+                &policy,
+                &approved,
+                stdin,
+            )
+        };
+
+        assert!(matches!(
+            assess(None),
+            SafetyCheck::AutoApprove {
+                sandbox_type: SandboxType::None
+            }
+        ));
+        assert!(!matches!(
+            assess(Some("import os; os.remove('x')")),
+            SafetyCheck::AutoApprove {
+                sandbox_type: SandboxType::None
+            }
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_out_of_writable_root_is_not_writable() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret\n").unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("secret-link.txt"),
+        )
+        .unwrap();
+        let roots = [root.path().to_path_buf()];
+
+        let through_dir =
+            ApplyPatchAction::new_add_for_test(&root.path().join("escape/new.txt"), String::new());
+        assert!(!is_write_patch_constrained_to_writable_paths(
+            &through_dir,
+            &roots,
+            root.path(),
+        ));
+
+        let through_file =
+            ApplyPatchAction::new_add_for_test(&root.path().join("secret-link.txt"), String::new());
+        assert!(!is_write_patch_constrained_to_writable_paths(
+            &through_file,
+            &roots,
+            root.path(),
+        ));
+
+        let inside = ApplyPatchAction::new_add_for_test(
+            &root.path().join("not-yet/created.txt"),
+            String::new(),
+        );
+        assert!(is_write_patch_constrained_to_writable_paths(
+            &inside,
+            &roots,
+            root.path(),
+        ));
+    }
 }
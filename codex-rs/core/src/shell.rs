@@ -0,0 +1,251 @@
+//! Running commands through the user's own shell.
+//!
+//! Models tend to run `bash -lc "<script>"` or a bare argv, neither of which
+//! sees the aliases, functions and `PATH` changes (e.g. from `nvm` or `pyenv`)
+//! that the user's interactive shell sets up. With `login_shell` enabled,
+//! Codex detects the user's shell, snapshots the environment its profile
+//! produces once per session, and runs commands in that shell with its rc
+//! file sourced.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+/// How long the shell may take to load its profile for the environment
+/// snapshot.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Separates anything the profile prints from the environment dump.
+const SNAPSHOT_MARKER: &str = "__CODEX_ENV_SNAPSHOT__";
+
+/// A shell whose startup files Codex knows how to load. Other shells (such as
+/// fish) do not accept the POSIX syntax models write, so they are not used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserShell {
+    pub path: PathBuf,
+    /// The file an interactive shell would source, e.g. `~/.zshrc`.
+    pub rc_path: Option<PathBuf>,
+}
+
+impl UserShell {
+    /// Detects the user's shell from `$SHELL`, falling back to the password
+    /// database. Returns `None` if it is not bash or zsh.
+    pub fn detect() -> Option<Self> {
+        let path = std::env::var_os("SHELL")
+            .filter(|shell| !shell.is_empty())
+            .map(PathBuf::from)
+            .or_else(passwd_shell)?;
+        Self::from_path(path, dirs::home_dir().as_deref())
+    }
+
+    fn from_path(path: PathBuf, home: Option<&Path>) -> Option<Self> {
+        let rc_name = match path.file_name()?.to_str()? {
+            "bash" => ".bashrc",
+            "zsh" => ".zshrc",
+            _ => return None,
+        };
+        let rc_path = home
+            .map(|home| home.join(rc_name))
+            .filter(|rc_path| rc_path.is_file());
+        Some(Self { path, rc_path })
+    }
+
+    /// Rewrites `command` to run as a script in this shell, as a login shell
+    /// and with the rc file sourced. A `-c <script>` or `-lc <script>` for this
+    /// shell is unwrapped so that the script runs directly. Returns `None` if
+    /// `command` runs a script in another shell, whose syntax the script may
+    /// rely on, or cannot be quoted.
+    pub fn wrap_command(&self, command: &[String]) -> Option<Vec<String>> {
+        let script = match command {
+            [shell, flag, script]
+                if matches!(flag.as_str(), "-c" | "-lc")
+                    && matches!(shell_name(shell), Some("bash" | "sh" | "zsh")) =>
+            {
+                if shell_name(shell) != shell_name(&self.path.to_string_lossy()) {
+                    return None;
+                }
+                script.clone()
+            }
+            _ => shlex::try_join(command.iter().map(String::as_str)).ok()?,
+        };
+        Some(vec![
+            self.path.to_string_lossy().to_string(),
+            "-lc".to_string(),
+            format!("{}{script}", self.source_rc_prefix()),
+        ])
+    }
+
+    /// Runs the shell once to capture the environment its profile and rc
+    /// file produce. Returns `None`, after logging why, if that fails.
+    pub async fn snapshot_env(&self) -> Option<HashMap<String, String>> {
+        let script = format!(
+            "{}printf '%s' {SNAPSHOT_MARKER}; env -0 2>/dev/null || env",
+            self.source_rc_prefix()
+        );
+        let output = Command::new(&self.path)
+            .args(["-lc", &script])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = match tokio::time::timeout(SNAPSHOT_TIMEOUT, output).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                tracing::warn!("failed to run {}: {e}", self.path.display());
+                return None;
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "{} took longer than {SNAPSHOT_TIMEOUT:?} to load its profile",
+                    self.path.display()
+                );
+                return None;
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match stdout.split_once(SNAPSHOT_MARKER) {
+            Some((_, env)) => Some(parse_env(env)),
+            None => {
+                tracing::warn!("{} did not print its environment", self.path.display());
+                None
+            }
+        }
+    }
+
+    fn source_rc_prefix(&self) -> String {
+        // Anything the rc file prints would end up in the command's output.
+        match &self.rc_path {
+            Some(rc_path) => format!(
+                "source {} >/dev/null 2>&1; ",
+                shlex::try_quote(&rc_path.to_string_lossy()).unwrap_or_default()
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+fn shell_name(program: &str) -> Option<&str> {
+    Path::new(program).file_name()?.to_str()
+}
+
+/// Parses the output of `env -0`, or of `env` where `-0` is not supported.
+fn parse_env(output: &str) -> HashMap<String, String> {
+    let separator = if output.contains('\0') { '\0' } else { '\n' };
+    output
+        .split(separator)
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(unix)]
+fn passwd_shell() -> Option<PathBuf> {
+    use std::ffi::CStr;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // SAFETY: `getpwuid` returns null or a pointer to a record that stays
+    // valid until the next call; the shell is copied out before returning.
+    // This runs once per session, so the non-reentrant call is acceptable.
+    unsafe {
+        let passwd = libc::getpwuid(libc::getuid());
+        if passwd.is_null() || (*passwd).pw_shell.is_null() {
+            return None;
+        }
+        let shell = CStr::from_ptr((*passwd).pw_shell).to_bytes();
+        (!shell.is_empty()).then(|| PathBuf::from(OsStr::from_bytes(shell)))
+    }
+}
+
+#[cfg(not(unix))]
+fn passwd_shell() -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn wraps_commands_in_the_user_shell() {
+        let home = tempdir().unwrap();
+        std::fs::write(home.path().join(".zshrc"), "").unwrap();
+        let shell = UserShell::from_path(PathBuf::from("/bin/zsh"), Some(home.path())).unwrap();
+        let source = format!(
+            "source {} >/dev/null 2>&1; ",
+            home.path().join(".zshrc").display()
+        );
+
+        assert_eq!(
+            shell.wrap_command(&args(&["zsh", "-lc", "npm test && echo ok"])),
+            Some(args(&[
+                "/bin/zsh",
+                "-lc",
+                &format!("{source}npm test && echo ok")
+            ]))
+        );
+        assert_eq!(
+            shell.wrap_command(&args(&["rg", "two words", "src"])),
+            Some(args(&[
+                "/bin/zsh",
+                "-lc",
+                &format!("{source}rg 'two words' src")
+            ]))
+        );
+
+        // A script written for another shell keeps its interpreter.
+        assert_eq!(
+            shell.wrap_command(&args(&["bash", "-lc", "shopt -s globstar"])),
+            None
+        );
+
+        let no_rc = UserShell::from_path(PathBuf::from("/usr/bin/bash"), None).unwrap();
+        assert_eq!(
+            no_rc.wrap_command(&args(&["ls"])),
+            Some(args(&["/usr/bin/bash", "-lc", "ls"]))
+        );
+        assert_eq!(
+            UserShell::from_path(PathBuf::from("/usr/bin/fish"), None),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_env_output() {
+        let expected = HashMap::from([
+            ("PATH".to_string(), "/a:/b".to_string()),
+            ("EMPTY".to_string(), String::new()),
+        ]);
+        assert_eq!(parse_env("PATH=/a:/b\0EMPTY=\0"), expected);
+        assert_eq!(parse_env("PATH=/a:/b\nEMPTY=\n"), expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn snapshot_includes_rc_file_changes() {
+        let home = tempdir().unwrap();
+        std::fs::write(
+            home.path().join(".bashrc"),
+            "echo noise; export CODEX_TEST_FROM_RC='a b'\n",
+        )
+        .unwrap();
+        let shell = UserShell::from_path(PathBuf::from("bash"), Some(home.path())).unwrap();
+        let env = shell.snapshot_env().await.unwrap();
+        assert_eq!(
+            env.get("CODEX_TEST_FROM_RC").map(String::as_str),
+            Some("a b")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Detection of files that changed between proposing a patch and applying it.
+//!
+//! The new contents of an `apply_patch` are computed from the files as they
+//! were when the patch was proposed. If the user edits one of those files
+//! while the approval prompt is open, writing the patch would silently discard
+//! their edit, so we fingerprint every path the patch touches up front and
+//! refuse to apply it if any fingerprint has changed. An updated file is
+//! fingerprinted from the contents its new contents were computed from, so an
+//! edit made between reading the file and capturing is caught as well.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use codex_apply_patch::ApplyPatchAction;
+use codex_apply_patch::ApplyPatchFileChange;
+use sha2::Digest;
+use sha2::Sha256;
+
+/// Content hash of each path a patch touches; `None` if the path did not
+/// exist (or could not be read).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FileFingerprints {
+    hashes: BTreeMap<PathBuf, Option<[u8; 32]>>,
+}
+
+impl FileFingerprints {
+    /// Fingerprints every source and destination path in `action`.
+    pub(crate) fn capture(action: &ApplyPatchAction) -> Self {
+        let mut hashes = BTreeMap::new();
+        for (path, change) in action.changes() {
+            match change {
+                ApplyPatchFileChange::Update {
+                    move_path,
+                    original_content,
+                    ..
+                } => {
+                    if let Some(move_path) = move_path {
+                        hashes.insert(move_path.clone(), hash_file(move_path));
+                    }
+                    hashes.insert(path.clone(), Some(hash(original_content.as_bytes())));
+                }
+                ApplyPatchFileChange::Add { .. }
+                | ApplyPatchFileChange::Delete
+                | ApplyPatchFileChange::Symlink { .. } => {
+                    hashes.insert(path.clone(), hash_file(path));
+                }
+            }
+        }
+        Self { hashes }
+    }
+
+    /// Fails with the paths whose contents differ from when the fingerprints
+    /// were captured.
+    pub(crate) fn verify(&self) -> Result<(), StaleFilesError> {
+        let paths: Vec<PathBuf> = self
+            .hashes
+            .iter()
+            .filter(|(path, hash)| hash_file(path) != **hash)
+            .map(|(path, _)| path.clone())
+            .collect();
+        if paths.is_empty() {
+            Ok(())
+        } else {
+            Err(StaleFilesError { paths })
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Option<[u8; 32]> {
+    std::fs::read(path).ok().map(|contents| hash(&contents))
+}
+
+fn hash(contents: &[u8]) -> [u8; 32] {
+    Sha256::digest(contents).into()
+}
+
+/// Files changed on disk after the patch touching them was proposed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "patch not applied: {} changed on disk after the patch was proposed; re-read the file and create a new patch",
+    display_paths(paths)
+)]
+pub(crate) struct StaleFilesError {
+    pub paths: Vec<PathBuf>,
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use codex_apply_patch::MaybeApplyPatchVerified;
+    use codex_apply_patch::maybe_parse_apply_patch_verified;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn detects_edits_made_after_capture() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file.txt");
+        std::fs::write(&file, "one\n").unwrap();
+        let patch = "*** Begin Patch\n*** Update File: file.txt\n@@\n-one\n+two\n*** Add File: new.txt\n+new\n*** End Patch";
+        let argv = vec!["apply_patch".to_string(), patch.to_string()];
+        let MaybeApplyPatchVerified::Body(action) =
+            maybe_parse_apply_patch_verified(&argv, dir.path())
+        else {
+            panic!("expected a valid patch");
+        };
+
+        let fingerprints = FileFingerprints::capture(&action);
+        assert_eq!(fingerprints.verify(), Ok(()));
+
+        std::fs::write(&file, "one\nedited by the user\n").unwrap();
+        std::fs::write(dir.path().join("new.txt"), "also created\n").unwrap();
+        assert_eq!(
+            fingerprints.verify(),
+            Err(StaleFilesError {
+                paths: vec![file, dir.path().join("new.txt")],
+            })
+        );
+    }
+
+    #[test]
+    fn detects_edits_made_before_capture() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file.txt");
+        std::fs::write(&file, "one\n").unwrap();
+        let patch = "*** Begin Patch\n*** Update File: file.txt\n@@\n-one\n+two\n*** End Patch";
+        let argv = vec!["apply_patch".to_string(), patch.to_string()];
+        let MaybeApplyPatchVerified::Body(action) =
+            maybe_parse_apply_patch_verified(&argv, dir.path())
+        else {
+            panic!("expected a valid patch");
+        };
+
+        // Edited after the new contents were computed, but before capturing.
+        std::fs::write(&file, "one\nedited by the user\n").unwrap();
+        let fingerprints = FileFingerprints::capture(&action);
+        assert_eq!(
+            fingerprints.verify(),
+            Err(StaleFilesError { paths: vec![file] })
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Per-session undo journal for patches applied by the agent.
+//!
+//! Every successful `apply_patch` appends one [`UndoEntry`] to
+//! `~/.codex/undo/<session_id>.jsonl` recording the pre-image (contents and
+//! permissions) of each file it touched. [`UndoJournal::undo_last`] pops the
+//! newest entry and restores those pre-images with the same all-or-nothing
+//! guarantee used to apply the patch, so reverting an edit does not depend on
+//! the working directory being a git repository. Each entry also fingerprints what the patch left on disk, and
+//! undo refuses to overwrite files that have changed since.
+//!
+//! The journal holds full file contents, so it is private to the user and
+//! its oldest entries are dropped once it exceeds [`MAX_JOURNAL_BYTES`].
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::fs::Permissions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use base64::Engine;
+use codex_apply_patch::FilePreImage;
+use codex_apply_patch::FileTransaction;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Folder inside `~/.codex` that holds the undo journals.
+const UNDO_SUBDIR: &str = "undo";
+
+/// Size a journal may grow to before its oldest entries are dropped.
+const MAX_JOURNAL_BYTES: usize = 16 * 1024 * 1024;
+
+/// One applied patch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UndoEntry {
+    /// Seconds since the Unix epoch when the patch was applied.
+    pub ts: u64,
+    pub files: Vec<UndoFile>,
+}
+
+/// State of a single path before a patch was applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UndoFile {
+    pub path: PathBuf,
+    /// Base64-encoded contents; `None` if the file did not exist or was a
+    /// symlink.
+    pub contents: Option<String>,
+    /// Unix permission bits of the file, restored along with its contents;
+    /// `None` in entries written before this was recorded, or off Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Where the path pointed if it was a symlink.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<PathBuf>,
+    /// If the patch moved a file to `path`, the path it was moved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub move_source: Option<PathBuf>,
+    /// State of `path` right after the patch was applied; `None` in entries
+    /// written before this was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_image: Option<PostImage>,
+}
+
+/// Fingerprint of a path, used to detect edits made after a patch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostImage {
+    Missing,
+    Symlink(PathBuf),
+    /// Base64-encoded SHA-256 of the contents.
+    Sha256(String),
+}
+
+impl PostImage {
+    fn capture(path: &Path) -> std::io::Result<Self> {
+        if path.is_symlink() {
+            return std::fs::read_link(path).map(Self::Symlink);
+        }
+        match std::fs::read(path) {
+            Ok(contents) => Ok(Self::Sha256(
+                base64::engine::general_purpose::STANDARD.encode(Sha256::digest(contents)),
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::Missing),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Append-only list of [`UndoEntry`]s for one session, newest last.
+#[derive(Debug, Clone)]
+pub struct UndoJournal {
+    path: PathBuf,
+}
+
+impl UndoJournal {
+    pub fn new(codex_home: &Path, session_id: Uuid) -> Self {
+        Self {
+            path: codex_home
+                .join(UNDO_SUBDIR)
+                .join(format!("{session_id}.jsonl")),
+        }
+    }
+
+    /// Journal of the session that most recently applied a patch, if any.
+    pub fn most_recent(codex_home: &Path) -> std::io::Result<Option<Self>> {
+        let dir = codex_home.join(UNDO_SUBDIR);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut newest = None;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            let modified = entry.metadata()?.modified()?;
+            if newest.as_ref().is_none_or(|(ts, _)| modified > *ts) {
+                newest = Some((modified, path));
+            }
+        }
+        Ok(newest.map(|(_, path)| Self { path }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the pre-images of a successfully applied patch, together with
+    /// fingerprints of the files as the patch left them, so it must be called
+    /// right after the patch is applied. `move_sources` maps each move
+    /// destination to the path it was moved from.
+    pub fn record(
+        &self,
+        pre_images: Vec<FilePreImage>,
+        move_sources: &HashMap<PathBuf, PathBuf>,
+    ) -> std::io::Result<()> {
+        let files = pre_images
+            .into_iter()
+            .map(|pre_image| {
+                Ok(UndoFile {
+                    move_source: move_sources.get(&pre_image.path).cloned(),
+                    post_image: Some(PostImage::capture(&pre_image.path)?),
+                    mode: pre_image.permissions.as_ref().and_then(permission_bits),
+                    symlink_target: pre_image.symlink_target,
+                    contents: pre_image
+                        .contents
+                        .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes)),
+                    path: pre_image.path,
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+        let ts = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut line = serde_json::to_string(&UndoEntry { ts, files })
+            .map_err(|e| std::io::Error::other(format!("failed to serialize undo entry: {e}")))?;
+        line.push('\n');
+        if line.len() > MAX_JOURNAL_BYTES {
+            return Err(std::io::Error::other(
+                "patch is too large to record for undo",
+            ));
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        // The journal holds the contents of the user's files.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+        let journal_len = file.metadata()?.len() as usize;
+        if journal_len + line.len() > MAX_JOURNAL_BYTES {
+            let journal = std::fs::read_to_string(&self.path)?;
+            let mut kept = journal.as_str();
+            while kept.len() + line.len() > MAX_JOURNAL_BYTES {
+                kept = kept.split_once('\n').map_or("", |(_, rest)| rest);
+            }
+            // Rewriting keeps the file, and with it its permissions.
+            std::fs::write(&self.path, format!("{kept}{line}"))
+        } else {
+            file.write_all(line.as_bytes())
+        }
+    }
+
+    /// Restores the files touched by the most recent patch and removes it from
+    /// the journal. Returns the restored paths, or `None` if there is nothing
+    /// to undo.
+    pub fn undo_last(&self) -> anyhow::Result<Option<Vec<PathBuf>>> {
+        let journal = match std::fs::read_to_string(&self.path) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()));
+            }
+        };
+        let mut lines: Vec<&str> = journal.lines().filter(|l| !l.trim().is_empty()).collect();
+        let Some(last) = lines.pop() else {
+            return Ok(None);
+        };
+        let entry: UndoEntry = serde_json::from_str(last)
+            .with_context(|| format!("Corrupt undo entry in {}", self.path.display()))?;
+
+        let mut changed = Vec::new();
+        for file in &entry.files {
+            if let Some(post_image) = &file.post_image
+                && PostImage::capture(&file.path).ok().as_ref() != Some(post_image)
+            {
+                changed.push(file.path.display().to_string());
+            }
+        }
+        if !changed.is_empty() {
+            anyhow::bail!(
+                "{} changed since the patch was applied; undoing it would discard those changes",
+                changed.join(", ")
+            );
+        }
+
+        let mut transaction = FileTransaction::new();
+        let mut restored = Vec::with_capacity(entry.files.len());
+        for UndoFile {
+            path,
+            contents,
+            mode,
+            symlink_target,
+            ..
+        } in entry.files
+        {
+            match (contents, symlink_target) {
+                (_, Some(target)) => transaction.symlink(&path, target),
+                (Some(encoded), None) => {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .with_context(|| format!("Corrupt undo entry for {}", path.display()))?;
+                    // Replace a symlink the patch put here rather than
+                    // writing through it.
+                    if path.is_symlink() {
+                        transaction.delete(&path)?;
+                    }
+                    match mode.and_then(permissions_from_bits) {
+                        Some(permissions) => {
+                            transaction.write_with_permissions(&path, bytes, permissions)
+                        }
+                        None => transaction.write(&path, bytes),
+                    }
+                }
+                // Already gone, e.g. removed by hand since the patch.
+                (None, None) if !path.exists() && !path.is_symlink() => continue,
+                (None, None) => transaction.delete(&path)?,
+            }
+            restored.push(path);
+        }
+        transaction.commit()?;
+
+        let mut remaining = lines.join("\n");
+        if !remaining.is_empty() {
+            remaining.push('\n');
+        }
+        std::fs::write(&self.path, remaining)
+            .with_context(|| format!("Failed to update {}", self.path.display()))?;
+        Ok(Some(restored))
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(permissions: &Permissions) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(permissions.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_permissions: &Permissions) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn permissions_from_bits(mode: u32) -> Option<Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn permissions_from_bits(_mode: u32) -> Option<Permissions> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn undo_restores_pre_images_newest_first() {
+        let codex_home = tempdir().unwrap();
+        let work = tempdir().unwrap();
+        let edited = work.path().join("edited.txt");
+        let moved_from = work.path().join("old.txt");
+        let moved_to = work.path().join("new.txt");
+        let journal = UndoJournal::new(codex_home.path(), Uuid::new_v4());
+
+        // First patch: edit `edited.txt`.
+        std::fs::write(&edited, "v1\n").unwrap();
+        journal
+            .record(
+                vec![FilePreImage {
+                    path: edited.clone(),
+                    contents: Some(b"v0\n".to_vec()),
+                    permissions: None,
+                    symlink_target: None,
+                }],
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        // Second patch: move `old.txt` to `new.txt`.
+        std::fs::write(&moved_to, "moved\n").unwrap();
+        journal
+            .record(
+                vec![
+                    FilePreImage {
+                        path: moved_to.clone(),
+                        contents: None,
+                        permissions: None,
+                        symlink_target: None,
+                    },
+                    FilePreImage {
+                        path: moved_from.clone(),
+                        contents: Some(b"moved\n".to_vec()),
+                        permissions: None,
+                        symlink_target: None,
+                    },
+                ],
+                &HashMap::from([(moved_to.clone(), moved_from.clone())]),
+            )
+            .unwrap();
+
+        let restored = journal.undo_last().unwrap().unwrap();
+        assert_eq!(restored, vec![moved_to.clone(), moved_from.clone()]);
+        assert!(!moved_to.exists());
+        assert_eq!(std::fs::read_to_string(&moved_from).unwrap(), "moved\n");
+        assert_eq!(std::fs::read_to_string(&edited).unwrap(), "v1\n");
+
+        assert_eq!(journal.undo_last().unwrap().unwrap(), vec![edited.clone()]);
+        assert_eq!(std::fs::read_to_string(&edited).unwrap(), "v0\n");
+
+        assert_eq!(journal.undo_last().unwrap(), None);
+    }
+
+    #[test]
+    fn undo_refuses_to_overwrite_later_edits() {
+        let codex_home = tempdir().unwrap();
+        let work = tempdir().unwrap();
+        let edited = work.path().join("edited.txt");
+        let journal = UndoJournal::new(codex_home.path(), Uuid::new_v4());
+
+        std::fs::write(&edited, "patched\n").unwrap();
+        journal
+            .record(
+                vec![FilePreImage {
+                    path: edited.clone(),
+                    contents: Some(b"original\n".to_vec()),
+                    permissions: None,
+                    symlink_target: None,
+                }],
+                &HashMap::new(),
+            )
+            .unwrap();
+        std::fs::write(&edited, "patched\nedited by the user\n").unwrap();
+
+        let err = journal.undo_last().unwrap_err();
+        assert!(err.to_string().contains("changed since the patch"), "{err}");
+        assert_eq!(
+            std::fs::read_to_string(&edited).unwrap(),
+            "patched\nedited by the user\n"
+        );
+
+        // Once the edit is reverted, the patch can be undone.
+        std::fs::write(&edited, "patched\n").unwrap();
+        assert_eq!(journal.undo_last().unwrap().unwrap(), vec![edited.clone()]);
+        assert_eq!(std::fs::read_to_string(&edited).unwrap(), "original\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undo_restores_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let codex_home = tempdir().unwrap();
+        let work = tempdir().unwrap();
+        let script = work.path().join("run.sh");
+        std::fs::write(&script, "echo hi\n").unwrap();
+        std::fs::set_permissions(&script, Permissions::from_mode(0o755)).unwrap();
+        let journal = UndoJournal::new(codex_home.path(), Uuid::new_v4());
+
+        // A patch deletes the script.
+        let mut transaction = FileTransaction::new();
+        transaction.delete(&script).unwrap();
+        let pre_images = transaction.commit().unwrap();
+        journal.record(pre_images, &HashMap::new()).unwrap();
+        assert!(!script.exists());
+
+        assert_eq!(journal.undo_last().unwrap().unwrap(), vec![script.clone()]);
+        assert_eq!(std::fs::read_to_string(&script).unwrap(), "echo hi\n");
+        let mode = std::fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[test]
+    fn journal_is_private_and_drops_oldest_entries() {
+        let codex_home = tempdir().unwrap();
+        let journal = UndoJournal::new(codex_home.path(), Uuid::new_v4());
+        let pre_image = |n: u8| FilePreImage {
+            path: codex_home.path().join(format!("file{n}")),
+            contents: Some(vec![n; MAX_JOURNAL_BYTES / 3]),
+            permissions: None,
+            symlink_target: None,
+        };
+        for n in 0..4 {
+            journal.record(vec![pre_image(n)], &HashMap::new()).unwrap();
+        }
+
+        let contents = std::fs::read_to_string(journal.path()).unwrap();
+        assert!(contents.len() <= MAX_JOURNAL_BYTES);
+        let entries: Vec<UndoEntry> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Each entry takes up about 4/9 of the limit, so only two fit.
+        let paths: Vec<PathBuf> = entries
+            .into_iter()
+            .map(|entry| entry.files[0].path.clone())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                codex_home.path().join("file2"),
+                codex_home.path().join("file3"),
+            ]
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(journal.path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn most_recent_picks_latest_journal() {
+        let codex_home = tempdir().unwrap();
+        assert!(
+            UndoJournal::most_recent(codex_home.path())
+                .unwrap()
+                .is_none()
+        );
+
+        let journal = UndoJournal::new(codex_home.path(), Uuid::new_v4());
+        journal.record(Vec::new(), &HashMap::new()).unwrap();
+        let found = UndoJournal::most_recent(codex_home.path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.path(), journal.path());
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -48,7 +49,12 @@ pub(crate) fn backoff(attempt: u64) -> Duration {
 /// directory. If you need Codex to work from such a checkout simply pass the
 /// `--allow-no-git-exec` CLI flag that disables the repo requirement.
 pub fn is_inside_git_repo(config: &Config) -> bool {
-    let mut dir = config.cwd.to_path_buf();
+    is_path_inside_git_repo(&config.cwd)
+}
+
+/// Like [`is_inside_git_repo`], for an arbitrary directory.
+pub fn is_path_inside_git_repo(dir: &Path) -> bool {
+    let mut dir = dir.to_path_buf();
 
     loop {
         if dir.join(".git").exists() {
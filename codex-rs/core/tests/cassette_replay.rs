@@ -0,0 +1,112 @@
+//! Replays a recorded multi-turn session (tool call followed by a final
+//! message) from a cassette directory without any network access.
+
+use std::time::Duration;
+
+use codex_core::Codex;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+mod test_support;
+use serde_json::json;
+use tempfile::TempDir;
+use test_support::load_default_config_for_test;
+use tokio::time::timeout;
+
+fn sse(events: &[serde_json::Value]) -> String {
+    events
+        .iter()
+        .map(|event| {
+            format!(
+                "event: {}\ndata: {event}\n\n",
+                event["type"].as_str().unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+fn completed(id: &str) -> serde_json::Value {
+    json!({"type": "response.completed", "response": {"id": id, "output": []}})
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn replays_tool_call_and_final_message_from_cassette() {
+    #![allow(clippy::unwrap_used)]
+
+    let cassette = TempDir::new().unwrap();
+    let turns = [
+        sse(&[
+            json!({
+                "type": "response.output_item.done",
+                "item": {
+                    "type": "function_call",
+                    "name": "shell",
+                    "arguments": json!({"command": ["echo", "hi"]}).to_string(),
+                    "call_id": "call_1",
+                },
+            }),
+            completed("resp_1"),
+        ]),
+        sse(&[
+            json!({
+                "type": "response.output_item.done",
+                "item": {
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [{"type": "output_text", "text": "done"}],
+                },
+            }),
+            completed("resp_2"),
+        ]),
+    ];
+    for (index, body) in turns.iter().enumerate() {
+        // Ordered replay only warns when the recorded request differs, so a
+        // placeholder request is enough here.
+        std::fs::write(
+            cassette.path().join(format!("{index:04}.request.json")),
+            "{}",
+        )
+        .unwrap();
+        std::fs::write(cassette.path().join(format!("{index:04}.sse")), body).unwrap();
+    }
+
+    // See stream_no_completed.rs for why mutating the environment is `unsafe`.
+    unsafe {
+        std::env::set_var("CODEX_RS_CASSETTE_DIR", cassette.path());
+        std::env::set_var("CODEX_RS_CASSETTE_MODE", "replay");
+        std::env::set_var("OPENAI_REQUEST_MAX_RETRIES", "0");
+        std::env::set_var("OPENAI_STREAM_MAX_RETRIES", "0");
+    }
+
+    let ctrl_c = std::sync::Arc::new(tokio::sync::Notify::new());
+    let codex_home = TempDir::new().unwrap();
+    let config = load_default_config_for_test(&codex_home);
+    let (codex, _init_id) = Codex::spawn(config, ctrl_c).await.unwrap();
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "say hi".into(),
+            }],
+        })
+        .await
+        .unwrap();
+
+    let mut exec_stdout = None;
+    loop {
+        let ev = timeout(Duration::from_secs(10), codex.next_event())
+            .await
+            .unwrap()
+            .unwrap();
+        match ev.msg {
+            EventMsg::ExecCommandEnd(end) => exec_stdout = Some(end.stdout),
+            EventMsg::TaskComplete(complete) => {
+                assert_eq!(complete.last_agent_message.as_deref(), Some("done"));
+                break;
+            }
+            EventMsg::Error(err) => panic!("unexpected error: {}", err.message),
+            _ => {}
+        }
+    }
+    assert_eq!(exec_stdout.as_deref(), Some("hi\n"));
+}
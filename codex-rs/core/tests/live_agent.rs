@@ -190,6 +190,7 @@ async fn live_shell_function_call() {
                 command,
                 call_id: _,
                 cwd: _,
+                timeout_ms: _,
             }) => {
                 assert_eq!(command, vec!["echo", MARKER]);
                 saw_begin = true;
@@ -45,6 +45,18 @@ pub struct Cli {
     #[arg(long = "output-last-message")]
     pub last_message_file: Option<PathBuf>,
 
+    /// Path to a JSON schema the agent's final message must conform to. The
+    /// message is validated before being written to `--output-last-message`.
+    /// The schema must satisfy strict structured outputs: every object sets
+    /// `additionalProperties: false` and lists all its properties as required.
+    #[arg(long = "output-schema", value_name = "FILE")]
+    pub output_schema: Option<PathBuf>,
+
+    /// Append every model request payload and raw SSE event to FILE, with
+    /// timestamps and API keys redacted.
+    #[arg(long = "trace-http", value_name = "FILE")]
+    pub trace_http: Option<PathBuf>,
+
     /// Initial instructions for the agent. If not provided as an argument (or
     /// if `-` is used), instructions are read from stdin.
     #[arg(value_name = "PROMPT")]
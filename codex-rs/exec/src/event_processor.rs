@@ -1,24 +1,34 @@
+use codex_common::elapsed::format_duration;
 use codex_common::elapsed::format_elapsed;
 use codex_core::WireApi;
 use codex_core::config::Config;
 use codex_core::model_supports_reasoning_summaries;
 use codex_core::protocol::AgentMessageEvent;
 use codex_core::protocol::BackgroundEventEvent;
+use codex_core::protocol::BackgroundJobBeginEvent;
+use codex_core::protocol::BackgroundJobEndEvent;
 use codex_core::protocol::ErrorEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecCommandBeginEvent;
 use codex_core::protocol::ExecCommandEndEvent;
+use codex_core::protocol::ExecCommandOutputDeltaEvent;
+use codex_core::protocol::ExecOutputStream;
 use codex_core::protocol::FileChange;
 use codex_core::protocol::McpToolCallBeginEvent;
 use codex_core::protocol::McpToolCallEndEvent;
 use codex_core::protocol::PatchApplyBeginEvent;
 use codex_core::protocol::PatchApplyEndEvent;
+use codex_core::protocol::PatchConflictEvent;
+use codex_core::protocol::PatchUndoneEvent;
 use codex_core::protocol::SessionConfiguredEvent;
+use codex_core::protocol::SessionDiffEvent;
+use codex_core::protocol::SnapshotRestoredEvent;
 use owo_colors::OwoColorize;
 use owo_colors::Style;
 use shlex::try_join;
 use std::collections::HashMap;
+use std::time::Duration;
 use std::time::Instant;
 
 /// This should be configurable. When used in CI, users may not want to impose
@@ -91,6 +101,12 @@ impl EventProcessor {
 struct ExecCommandBegin {
     command: Vec<String>,
     start_time: Instant,
+    /// Whether any output was printed live from `ExecCommandOutputDelta`
+    /// events, in which case it is not repeated when the command ends.
+    streamed: bool,
+    /// Trailing output of each stream not yet terminated by a newline.
+    partial_stdout: Vec<u8>,
+    partial_stderr: Vec<u8>,
 }
 
 /// Metadata captured when an `McpToolCallBegin` event is received.
@@ -190,22 +206,52 @@ impl EventProcessor {
                 call_id,
                 command,
                 cwd,
+                timeout_ms,
             }) => {
                 self.call_id_to_command.insert(
                     call_id.clone(),
                     ExecCommandBegin {
                         command: command.clone(),
                         start_time: Instant::now(),
+                        streamed: false,
+                        partial_stdout: Vec::new(),
+                        partial_stderr: Vec::new(),
                     },
                 );
                 ts_println!(
                     self,
-                    "{} {} in {}",
+                    "{} {} in {} {}",
                     "exec".style(self.magenta),
                     escape_command(&command).style(self.bold),
                     cwd.to_string_lossy(),
+                    format!(
+                        "(timeout {})",
+                        format_duration(Duration::from_millis(timeout_ms))
+                    )
+                    .style(self.dimmed),
                 );
             }
+            EventMsg::ExecCommandOutputDelta(ExecCommandOutputDeltaEvent {
+                call_id,
+                stream,
+                chunk,
+            }) => {
+                let Some(exec_command) = self.call_id_to_command.get_mut(&call_id) else {
+                    return;
+                };
+                exec_command.streamed = true;
+                let partial = match stream {
+                    ExecOutputStream::Stdout => &mut exec_command.partial_stdout,
+                    ExecOutputStream::Stderr => &mut exec_command.partial_stderr,
+                };
+                partial.extend_from_slice(&chunk);
+                if let Some(end) = partial.iter().rposition(|&b| b == b'\n') {
+                    let complete: Vec<u8> = partial.drain(..=end).collect();
+                    for line in String::from_utf8_lossy(&complete).lines() {
+                        println!("{}", line.style(self.dimmed));
+                    }
+                }
+            }
             EventMsg::ExecCommandEnd(ExecCommandEndEvent {
                 call_id,
                 stdout,
@@ -213,11 +259,21 @@ impl EventProcessor {
                 exit_code,
             }) => {
                 let exec_command = self.call_id_to_command.remove(&call_id);
+                let mut streamed = false;
                 let (duration, call) = if let Some(ExecCommandBegin {
                     command,
                     start_time,
+                    streamed: was_streamed,
+                    partial_stdout,
+                    partial_stderr,
                 }) = exec_command
                 {
+                    streamed = was_streamed;
+                    for partial in [partial_stdout, partial_stderr] {
+                        if !partial.is_empty() {
+                            println!("{}", String::from_utf8_lossy(&partial).style(self.dimmed));
+                        }
+                    }
                     (
                         format!(" in {}", format_elapsed(start_time)),
                         format!("{}", escape_command(&command).style(self.bold)),
@@ -232,17 +288,51 @@ impl EventProcessor {
                     .take(MAX_OUTPUT_LINES_FOR_EXEC_TOOL_CALL)
                     .collect::<Vec<_>>()
                     .join("\n");
+                // Output that was already printed live is not repeated.
+                let colon = if streamed { "" } else { ":" };
                 match exit_code {
                     0 => {
-                        let title = format!("{call} succeeded{duration}:");
+                        let title = format!("{call} succeeded{duration}{colon}");
                         ts_println!(self, "{}", title.style(self.green));
                     }
                     _ => {
-                        let title = format!("{call} exited {exit_code}{duration}:");
+                        let title = format!("{call} exited {exit_code}{duration}{colon}");
+                        ts_println!(self, "{}", title.style(self.red));
+                    }
+                }
+                if !streamed {
+                    println!("{}", truncated_output.style(self.dimmed));
+                }
+            }
+            EventMsg::BackgroundJobBegin(BackgroundJobBeginEvent {
+                job_id,
+                command,
+                cwd,
+            }) => {
+                ts_println!(
+                    self,
+                    "{} {} {} in {}",
+                    "background".style(self.magenta),
+                    job_id.style(self.cyan),
+                    escape_command(&command).style(self.bold),
+                    cwd.to_string_lossy(),
+                );
+            }
+            EventMsg::BackgroundJobEnd(BackgroundJobEndEvent { job_id, exit_code }) => {
+                match exit_code {
+                    Some(0) => {
+                        let title = format!("background {job_id} succeeded");
+                        ts_println!(self, "{}", title.style(self.green));
+                    }
+                    Some(code) => {
+                        let title = format!("background {job_id} exited {code}");
+                        ts_println!(self, "{}", title.style(self.red));
+                    }
+                    None => {
+                        let title = format!("background {job_id} was killed");
                         ts_println!(self, "{}", title.style(self.red));
                     }
                 }
-                println!("{}", truncated_output.style(self.dimmed));
             }
             EventMsg::McpToolCallBegin(McpToolCallBeginEvent {
                 call_id,
@@ -360,6 +450,15 @@ impl EventProcessor {
                             );
                             println!("{}", header.style(self.magenta));
                         }
+                        FileChange::Symlink { target } => {
+                            let header = format!(
+                                "{} {} -> {}",
+                                format_file_change(change),
+                                path.to_string_lossy(),
+                                target.to_string_lossy()
+                            );
+                            println!("{}", header.style(self.magenta));
+                        }
                         FileChange::Update {
                             unified_diff,
                             move_path,
@@ -462,9 +561,36 @@ impl EventProcessor {
                 ts_println!(self, "model: {}", model);
                 println!();
             }
-            EventMsg::GetHistoryEntryResponse(_) => {
+            EventMsg::GetHistoryEntryResponse(_) | EventMsg::ModelRequestSize(_) => {
                 // Currently ignored in exec output.
             }
+            EventMsg::PatchConflict(PatchConflictEvent { call_id: _, paths }) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    "patch not applied: files changed on disk since it was proposed"
+                        .style(self.red)
+                );
+                for path in paths {
+                    println!("{}", path.display().style(self.dimmed));
+                }
+            }
+            EventMsg::PatchUndone(PatchUndoneEvent { restored }) => {
+                ts_println!(self, "{}", "undid last patch".style(self.magenta));
+                for path in restored {
+                    println!("{}", path.display().style(self.dimmed));
+                }
+            }
+            EventMsg::SnapshotRestored(SnapshotRestoredEvent { turn }) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    format!("restored snapshot from turn {turn}").style(self.magenta)
+                );
+            }
+            EventMsg::SessionDiff(SessionDiffEvent { diff }) => {
+                println!("{diff}");
+            }
         }
     }
 }
@@ -477,6 +603,7 @@ fn format_file_change(change: &FileChange) -> &'static str {
     match change {
         FileChange::Add { .. } => "A",
         FileChange::Delete => "D",
+        FileChange::Symlink { .. } => "L",
         FileChange::Update {
             move_path: Some(_), ..
         } => "R",
@@ -1,5 +1,6 @@
 mod cli;
 mod event_processor;
+mod output_schema;
 
 use std::io::IsTerminal;
 use std::io::Read;
@@ -20,6 +21,8 @@ use codex_core::protocol::SandboxPolicy;
 use codex_core::protocol::TaskCompleteEvent;
 use codex_core::util::is_inside_git_repo;
 use event_processor::EventProcessor;
+use output_schema::load_output_schema;
+use output_schema::validate_last_message;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
@@ -36,6 +39,8 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
         skip_git_repo_check,
         color,
         last_message_file,
+        output_schema: output_schema_path,
+        trace_http,
         prompt,
         config_overrides,
     } = cli;
@@ -90,6 +95,15 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
         sandbox.permissions.clone().map(Into::into)
     };
 
+    let output_schema = match output_schema_path.as_deref().map(load_output_schema) {
+        Some(Ok(schema)) => Some(schema),
+        Some(Err(e)) => {
+            eprintln!("Error loading --output-schema: {e}");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
     // Load configuration and determine approval policy
     let overrides = ConfigOverrides {
         model,
@@ -101,6 +115,8 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
         cwd: cwd.map(|p| p.canonicalize().unwrap_or(p)),
         model_provider: None,
         codex_linux_sandbox_exe,
+        output_schema,
+        trace_http,
     };
     // Parse `-c` overrides.
     let cli_kv_overrides = match config_overrides.parse_overrides() {
@@ -112,6 +128,7 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
     };
 
     let config = Config::load_with_cli_overrides(cli_kv_overrides, overrides)?;
+    let config_output_schema = config.output_schema.clone();
     let mut event_processor =
         EventProcessor::create_with_ansi(stdout_with_ansi, !config.hide_agent_reasoning);
     // Print the effective configuration and prompt so users can see what Codex
@@ -215,6 +232,18 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
         };
         event_processor.process_event(event);
         if is_last_event {
+            if let Some(schema) = &config_output_schema
+                && let Err(errors) = match last_assistant_message.as_deref() {
+                    Some(message) => validate_last_message(message, schema),
+                    None => Err(vec!["the task ended without a final message".to_string()]),
+                }
+            {
+                eprintln!("Last message does not match --output-schema:");
+                for error in errors {
+                    eprintln!("  {error}");
+                }
+                std::process::exit(1);
+            }
             handle_last_message(last_assistant_message, last_message_file.as_deref())?;
             break;
         }
@@ -0,0 +1,300 @@
+//! Support for `--output-schema`: loading the schema and checking the agent's
+//! final message against it.
+//!
+//! Only the subset of JSON Schema that structured outputs accept is checked:
+//! `type`, `properties`, `required`, `additionalProperties`, `items`, `enum`
+//! and `const`. Unknown keywords are ignored.
+//!
+//! The schema is sent with `strict: true`, which the provider rejects unless
+//! every object lists all of its properties as required and sets
+//! `additionalProperties: false`. That is checked when the schema is loaded
+//! so the run fails up front with the offending locations instead of with an
+//! API error after the session has started.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+/// Reads and parses the JSON schema at `path`.
+pub(crate) fn load_output_schema(path: &Path) -> anyhow::Result<Value> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+    let schema: Value = serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {e}", path.display()))?;
+    if !schema.is_object() {
+        anyhow::bail!("{} must contain a JSON object", path.display());
+    }
+    let mut errors = Vec::new();
+    check_strict(&schema, "$", &mut errors);
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "{} is not usable with strict structured outputs:\n  {}",
+            path.display(),
+            errors.join("\n  ")
+        );
+    }
+    Ok(schema)
+}
+
+/// Collects the places where `schema` breaks the rules of strict structured
+/// outputs: every object schema must set `additionalProperties: false` and
+/// list each of its properties in `required`.
+fn check_strict(schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let is_object = properties.is_some()
+        || match schema.get("type") {
+            Some(Value::String(kind)) => kind == "object",
+            Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == "object"),
+            _ => false,
+        };
+    if is_object {
+        if schema.get("additionalProperties") != Some(&Value::Bool(false)) {
+            errors.push(format!("{path}: must set `additionalProperties: false`"));
+        }
+        let required: Vec<&str> = match schema.get("required") {
+            Some(Value::Array(required)) => required.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        for key in properties
+            .into_iter()
+            .flat_map(|properties| properties.keys())
+        {
+            if !required.contains(&key.as_str()) {
+                errors.push(format!(
+                    "{path}: property `{key}` must be listed in `required` (allow `null` in its type to make it optional)"
+                ));
+            }
+        }
+    }
+
+    for (key, value) in properties.into_iter().flatten() {
+        check_strict(value, &format!("{path}.properties.{key}"), errors);
+    }
+    for keyword in ["items", "additionalProperties"] {
+        if let Some(value) = schema.get(keyword) {
+            check_strict(value, &format!("{path}.{keyword}"), errors);
+        }
+    }
+    for keyword in ["anyOf", "allOf"] {
+        if let Some(Value::Array(values)) = schema.get(keyword) {
+            for (idx, value) in values.iter().enumerate() {
+                check_strict(value, &format!("{path}.{keyword}[{idx}]"), errors);
+            }
+        }
+    }
+    for keyword in ["$defs", "definitions"] {
+        if let Some(Value::Object(definitions)) = schema.get(keyword) {
+            for (name, value) in definitions {
+                check_strict(value, &format!("{path}.{keyword}.{name}"), errors);
+            }
+        }
+    }
+}
+
+/// Parses `message` as JSON and validates it against `schema`. On failure,
+/// returns one human-readable error per violation.
+pub(crate) fn validate_last_message(message: &str, schema: &Value) -> Result<(), Vec<String>> {
+    let instance: Value = serde_json::from_str(message.trim())
+        .map_err(|e| vec![format!("last message is not valid JSON: {e}")])?;
+    let mut errors = Vec::new();
+    validate(&instance, schema, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate(instance: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true`/`false` schemas: `false` rejects everything.
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{path}: not allowed by schema"));
+        }
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(kind) => vec![kind.as_str()],
+            Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|kind| has_type(instance, kind)) {
+            errors.push(format!(
+                "{path}: expected {}, got {}",
+                allowed.join(" or "),
+                type_name(instance)
+            ));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum")
+        && !options.contains(instance)
+    {
+        errors.push(format!(
+            "{path}: {instance} is not one of the allowed values"
+        ));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != instance
+    {
+        errors.push(format!("{path}: expected {expected}, got {instance}"));
+    }
+
+    match instance {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        errors.push(format!("{path}: missing required property `{key}`"));
+                    }
+                }
+            }
+            for (key, value) in object {
+                let child_path = format!("{path}.{key}");
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property_schema) => validate(value, property_schema, &child_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{path}: unexpected property `{key}`"));
+                        }
+                        Some(additional @ Value::Object(_)) => {
+                            validate(value, additional, &child_path, errors);
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (idx, item) in items.iter().enumerate() {
+                    validate(item, item_schema, &format!("{path}[{idx}]"), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(instance: &Value, kind: &str) -> bool {
+    match kind {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "number" => instance.is_number(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        // Unknown type names are not ours to reject.
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["ok", "failed"] },
+                "files": { "type": "array", "items": { "type": "string" } },
+                "count": { "type": "integer" },
+            },
+            "required": ["status", "files", "count"],
+            "additionalProperties": false,
+        })
+    }
+
+    #[test]
+    fn accepts_conforming_message() {
+        let message = r#"{"status": "ok", "files": ["a.rs"], "count": 1}"#;
+        assert_eq!(validate_last_message(message, &schema()), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let message = r#"{"status": "maybe", "files": ["a.rs", 2], "count": 1.5, "extra": true}"#;
+        // Property order depends on serde_json's `preserve_order` feature.
+        let mut errors = validate_last_message(message, &schema()).unwrap_err();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "$.count: expected integer, got number".to_string(),
+                "$.files[1]: expected string, got number".to_string(),
+                "$.status: \"maybe\" is not one of the allowed values".to_string(),
+                "$: unexpected property `extra`".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_required_and_non_json() {
+        assert_eq!(
+            validate_last_message(r#"{"status": "ok", "count": 1}"#, &schema()),
+            Err(vec!["$: missing required property `files`".to_string()])
+        );
+        assert!(validate_last_message("All done!", &schema()).is_err());
+    }
+
+    #[test]
+    fn load_rejects_schemas_strict_mode_does_not_accept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schema.json");
+
+        std::fs::write(&path, schema().to_string()).unwrap();
+        assert_eq!(load_output_schema(&path).unwrap(), schema());
+
+        let lenient = json!({
+            "type": "object",
+            "properties": {
+                "status": { "type": "string" },
+                "files": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string" } },
+                        "required": ["name"],
+                    },
+                },
+            },
+            "required": ["status"],
+            "additionalProperties": false,
+        });
+        std::fs::write(&path, lenient.to_string()).unwrap();
+        let message = load_output_schema(&path).unwrap_err().to_string();
+        assert!(
+            message.contains("$: property `files` must be listed in `required`"),
+            "{message}"
+        );
+        assert!(
+            message.contains("$.properties.files.items: must set `additionalProperties: false`"),
+            "{message}"
+        );
+    }
+}
@@ -2,6 +2,7 @@
 #![expect(clippy::unwrap_used, clippy::expect_used)]
 
 use codex_core::config_types::ShellEnvironmentPolicy;
+use codex_core::config_types::ShellOutputLimits;
 use codex_core::error::CodexErr;
 use codex_core::error::SandboxErr;
 use codex_core::exec::ExecParams;
@@ -44,6 +45,10 @@ async fn run_cmd(cmd: &[&str], writable_roots: &[PathBuf], timeout_ms: u64) {
         cwd: std::env::current_dir().expect("cwd should exist"),
         timeout_ms: Some(timeout_ms),
         env: create_env_from_core_vars(),
+        output_limits: ShellOutputLimits::default(),
+        full_output_dir: None,
+        tty: false,
+        stdin: None,
     };
 
     let sandbox_policy = SandboxPolicy::new_read_only_policy_with_writable_roots(writable_roots);
@@ -56,6 +61,7 @@ async fn run_cmd(cmd: &[&str], writable_roots: &[PathBuf], timeout_ms: u64) {
         ctrl_c,
         &sandbox_policy,
         &codex_linux_sandbox_exe,
+        None,
     )
     .await
     .unwrap();
@@ -116,7 +122,7 @@ async fn test_writable_root() {
 }
 
 #[tokio::test]
-#[should_panic(expected = "Sandbox(Timeout)")]
+#[should_panic(expected = "Sandbox(Timeout(Terminated))")]
 async fn test_timeout() {
     run_cmd(&["sleep", "2"], &[], 50).await;
 }
@@ -134,6 +140,10 @@ async fn assert_network_blocked(cmd: &[&str]) {
         // do not stall the suite.
         timeout_ms: Some(NETWORK_TIMEOUT_MS),
         env: create_env_from_core_vars(),
+        output_limits: ShellOutputLimits::default(),
+        full_output_dir: None,
+        tty: false,
+        stdin: None,
     };
 
     let sandbox_policy = SandboxPolicy::new_read_only_policy();
@@ -146,6 +156,7 @@ async fn assert_network_blocked(cmd: &[&str]) {
         ctrl_c,
         &sandbox_policy,
         &codex_linux_sandbox_exe,
+        None,
     )
     .await;
 
@@ -169,6 +169,8 @@ impl CodexToolCallParam {
             sandbox_policy,
             model_provider: None,
             codex_linux_sandbox_exe,
+            output_schema: None,
+            trace_http: None,
         };
 
         let cli_overrides = cli_overrides
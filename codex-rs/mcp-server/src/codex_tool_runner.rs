@@ -163,14 +163,22 @@ pub async fn run_codex_tool_session(
                     EventMsg::Error(_)
                     | EventMsg::TaskStarted
                     | EventMsg::AgentReasoning(_)
+                    | EventMsg::ModelRequestSize(_)
                     | EventMsg::McpToolCallBegin(_)
                     | EventMsg::McpToolCallEnd(_)
                     | EventMsg::ExecCommandBegin(_)
+                    | EventMsg::ExecCommandOutputDelta(_)
                     | EventMsg::ExecCommandEnd(_)
+                    | EventMsg::BackgroundJobBegin(_)
+                    | EventMsg::BackgroundJobEnd(_)
                     | EventMsg::BackgroundEvent(_)
                     | EventMsg::PatchApplyBegin(_)
                     | EventMsg::PatchApplyEnd(_)
-                    | EventMsg::GetHistoryEntryResponse(_) => {
+                    | EventMsg::PatchConflict(_)
+                    | EventMsg::GetHistoryEntryResponse(_)
+                    | EventMsg::PatchUndone(_)
+                    | EventMsg::SnapshotRestored(_)
+                    | EventMsg::SessionDiff(_) => {
                         // For now, we do not do anything extra for these
                         // events. Note that
                         // send(codex_event_to_notification(&event)) above has
@@ -0,0 +1,11 @@
+//! Scripted stand-in for an OpenAI-compatible model provider.
+//!
+//! See `README.md` for how to point Codex at it.
+
+mod scenario;
+mod server;
+
+pub use scenario::Scenario;
+pub use scenario::ScenarioItem;
+pub use scenario::Turn;
+pub use server::serve;
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use codex_mock_model::Scenario;
+use codex_mock_model::serve;
+use tokio::net::TcpListener;
+
+/// Serve scripted model responses on `/v1/responses` and
+/// `/v1/chat/completions` for offline end-to-end testing.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Scenario file (YAML, or JSON if the extension is `.json`).
+    scenario: PathBuf,
+
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on. `0` picks a free port.
+    #[arg(long, short = 'p', default_value_t = 0)]
+    port: u16,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let scenario = Scenario::from_path(&cli.scenario)?;
+    let listener = TcpListener::bind((cli.host.as_str(), cli.port)).await?;
+
+    // Print the base URL on stdout so scripts can capture it when `--port 0`
+    // is used.
+    println!("http://{}/v1", listener.local_addr()?);
+
+    tokio::select! {
+        res = serve(listener, scenario) => res?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,338 @@
+//! Scripted scenarios and their rendering into SSE response bodies.
+//!
+//! A scenario is a list of turns. Every request the server receives consumes
+//! the next turn, regardless of which endpoint it was sent to:
+//!
+//! ```yaml
+//! turns:
+//!   - items:
+//!       - type: shell
+//!         command: ["echo", "hello"]
+//!   - items:
+//!       - type: message
+//!         text: "All done."
+//! ```
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json::json;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Turn {
+    pub items: Vec<ScenarioItem>,
+}
+
+/// A single output item produced by the mock model.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScenarioItem {
+    /// Assistant text.
+    Message { text: String },
+
+    /// A call to the `shell` function tool.
+    Shell {
+        command: Vec<String>,
+        #[serde(default)]
+        workdir: Option<String>,
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+
+    /// Shorthand for a `shell` call that runs `apply_patch` with `patch`.
+    ApplyPatch { patch: String },
+
+    /// A call to an arbitrary function tool (e.g. one exposed by an MCP
+    /// server). `arguments` is serialized to a JSON string on the wire.
+    FunctionCall {
+        name: String,
+        #[serde(default)]
+        arguments: Value,
+    },
+}
+
+impl Scenario {
+    /// Loads a scenario from a `.json` file, or from YAML for any other
+    /// extension.
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let scenario = if is_json {
+            serde_json::from_str(&contents)?
+        } else {
+            serde_yaml::from_str(&contents)?
+        };
+        Ok(scenario)
+    }
+}
+
+impl ScenarioItem {
+    /// Returns `(name, arguments)` for items that are function calls.
+    fn as_function_call(&self) -> Option<(String, String)> {
+        match self {
+            ScenarioItem::Message { .. } => None,
+            ScenarioItem::Shell {
+                command,
+                workdir,
+                timeout,
+            } => {
+                let mut arguments = json!({ "command": command });
+                if let Some(workdir) = workdir {
+                    arguments["workdir"] = json!(workdir);
+                }
+                if let Some(timeout) = timeout {
+                    arguments["timeout"] = json!(timeout);
+                }
+                Some(("shell".to_string(), arguments.to_string()))
+            }
+            ScenarioItem::ApplyPatch { patch } => Some((
+                "shell".to_string(),
+                json!({ "command": ["apply_patch", patch] }).to_string(),
+            )),
+            ScenarioItem::FunctionCall { name, arguments } => {
+                let arguments = match arguments {
+                    Value::Null => "{}".to_string(),
+                    other => other.to_string(),
+                };
+                Some((name.clone(), arguments))
+            }
+        }
+    }
+}
+
+impl Turn {
+    /// Renders this turn as a Responses API event stream.
+    pub fn to_responses_sse(&self, turn_index: usize) -> String {
+        let mut body = String::new();
+        for (item_index, item) in self.items.iter().enumerate() {
+            let item_json = match item.as_function_call() {
+                Some((name, arguments)) => json!({
+                    "type": "function_call",
+                    "name": name,
+                    "arguments": arguments,
+                    "call_id": call_id(turn_index, item_index),
+                }),
+                None => {
+                    let ScenarioItem::Message { text } = item else {
+                        continue;
+                    };
+                    json!({
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [{ "type": "output_text", "text": text }],
+                    })
+                }
+            };
+            push_event(
+                &mut body,
+                json!({ "type": "response.output_item.done", "item": item_json }),
+            );
+        }
+        push_event(
+            &mut body,
+            json!({
+                "type": "response.completed",
+                "response": { "id": format!("resp_mock_{turn_index}"), "output": [] },
+            }),
+        );
+        body
+    }
+
+    /// Renders this turn as a Chat Completions event stream. Function calls
+    /// become tool calls numbered by their position among the turn's calls,
+    /// as `tool_calls[].index` is in real streams.
+    pub fn to_chat_sse(&self, turn_index: usize) -> String {
+        let mut body = String::new();
+        let mut finish_reason = "stop";
+        let mut tool_call_index = 0;
+        for (item_index, item) in self.items.iter().enumerate() {
+            if let ScenarioItem::Message { text } = item {
+                push_data(
+                    &mut body,
+                    json!({ "choices": [{ "index": 0, "delta": { "content": text } }] }),
+                );
+            } else if let Some((name, arguments)) = item.as_function_call() {
+                finish_reason = "tool_calls";
+                push_data(
+                    &mut body,
+                    json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{
+                        "index": tool_call_index,
+                        "id": call_id(turn_index, item_index),
+                        "type": "function",
+                        "function": { "name": name, "arguments": arguments },
+                    }] } }] }),
+                );
+                tool_call_index += 1;
+            }
+        }
+        push_data(
+            &mut body,
+            json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": finish_reason }] }),
+        );
+        body.push_str("data: [DONE]\n\n");
+        body
+    }
+}
+
+fn call_id(turn_index: usize, item_index: usize) -> String {
+    format!("call_mock_{turn_index}_{item_index}")
+}
+
+fn push_event(body: &mut String, event: Value) {
+    let kind = event["type"].as_str().unwrap_or_default().to_string();
+    body.push_str(&format!("event: {kind}\ndata: {event}\n\n"));
+}
+
+fn push_data(body: &mut String, data: Value) {
+    body.push_str(&format!("data: {data}\n\n"));
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SCENARIO: &str = r#"
+turns:
+  - items:
+      - type: shell
+        command: ["echo", "hi"]
+        timeout: 1000
+  - items:
+      - type: message
+        text: done
+"#;
+
+    #[test]
+    fn parses_yaml_scenario() {
+        let scenario: Scenario = serde_yaml::from_str(SCENARIO).unwrap();
+        assert_eq!(
+            scenario.turns[0].items,
+            vec![ScenarioItem::Shell {
+                command: vec!["echo".to_string(), "hi".to_string()],
+                workdir: None,
+                timeout: Some(1000),
+            }]
+        );
+        assert_eq!(
+            scenario.turns[1].items,
+            vec![ScenarioItem::Message {
+                text: "done".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn renders_responses_function_call() {
+        let turn = Turn {
+            items: vec![ScenarioItem::ApplyPatch {
+                patch: "*** Begin Patch\n*** End Patch".to_string(),
+            }],
+        };
+        let body = turn.to_responses_sse(3);
+        let events: Vec<Value> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                json!({
+                    "type": "response.output_item.done",
+                    "item": {
+                        "type": "function_call",
+                        "name": "shell",
+                        "arguments": r#"{"command":["apply_patch","*** Begin Patch\n*** End Patch"]}"#,
+                        "call_id": "call_mock_3_0",
+                    },
+                }),
+                json!({
+                    "type": "response.completed",
+                    "response": { "id": "resp_mock_3", "output": [] },
+                }),
+            ]
+        );
+    }
+
+    fn chat_chunks(body: &str) -> Vec<Value> {
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn renders_chat_message_then_done() {
+        let turn = Turn {
+            items: vec![ScenarioItem::Message {
+                text: "hello".to_string(),
+            }],
+        };
+        let body = turn.to_chat_sse(0);
+        assert!(body.ends_with("data: [DONE]\n\n"));
+        let chunks = chat_chunks(&body);
+        assert_eq!(
+            chunks,
+            vec![
+                json!({ "choices": [{ "index": 0, "delta": { "content": "hello" } }] }),
+                json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }] }),
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_every_chat_tool_call() {
+        let turn = Turn {
+            items: vec![
+                ScenarioItem::Message {
+                    text: "checking".to_string(),
+                },
+                ScenarioItem::FunctionCall {
+                    name: "read_output".to_string(),
+                    arguments: json!({ "job_id": "job-1" }),
+                },
+                ScenarioItem::FunctionCall {
+                    name: "kill".to_string(),
+                    arguments: json!({ "job_id": "job-1" }),
+                },
+            ],
+        };
+        let chunks = chat_chunks(&turn.to_chat_sse(2));
+        let tool_calls: Vec<(u64, &str, &str)> = chunks
+            .iter()
+            .filter_map(|chunk| chunk["choices"][0]["delta"]["tool_calls"][0].as_object())
+            .map(|call| {
+                (
+                    call["index"].as_u64().unwrap(),
+                    call["id"].as_str().unwrap(),
+                    call["function"]["name"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tool_calls,
+            vec![
+                (0, "call_mock_2_1", "read_output"),
+                (1, "call_mock_2_2", "kill"),
+            ]
+        );
+        assert_eq!(
+            chunks.last().unwrap()["choices"][0]["finish_reason"],
+            "tool_calls"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Minimal HTTP/1.1 server that answers model requests from a [`Scenario`].
+//!
+//! Only what `reqwest` needs to talk to an OpenAI-compatible endpoint is
+//! implemented: one request per connection, a `Content-Length` body, and a
+//! `text/event-stream` response followed by closing the connection.
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::scenario::Scenario;
+
+/// Upper bound on the size of a request we are willing to buffer.
+const MAX_REQUEST_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+struct ServerState {
+    scenario: Scenario,
+    next_turn: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Responses,
+    ChatCompletions,
+}
+
+/// Serves `scenario` on `listener` until the task is cancelled.
+pub async fn serve(listener: TcpListener, scenario: Scenario) -> std::io::Result<()> {
+    let state = Arc::new(ServerState {
+        scenario,
+        next_turn: AtomicUsize::new(0),
+    });
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &state).await {
+                warn!("error handling connection from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &ServerState) -> std::io::Result<()> {
+    let Some((request_line, body)) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    debug!("{request_line}: {}", String::from_utf8_lossy(&body));
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let endpoint = if path.ends_with("/responses") {
+        Endpoint::Responses
+    } else if path.ends_with("/chat/completions") {
+        Endpoint::ChatCompletions
+    } else {
+        return write_response(&mut stream, "404 Not Found", "text/plain", "not found").await;
+    };
+
+    let turn_index = state.next_turn.fetch_add(1, Ordering::SeqCst);
+    let Some(turn) = state.scenario.turns.get(turn_index) else {
+        warn!("scenario exhausted after {turn_index} turns");
+        // 400 rather than 5xx so the client reports it instead of retrying.
+        let error = serde_json::json!({
+            "error": { "message": format!("mock scenario has no turn {turn_index}") },
+        });
+        return write_response(
+            &mut stream,
+            "400 Bad Request",
+            "application/json",
+            &error.to_string(),
+        )
+        .await;
+    };
+
+    info!("serving turn {turn_index} on {path}");
+    let body = match endpoint {
+        Endpoint::Responses => turn.to_responses_sse(turn_index),
+        Endpoint::ChatCompletions => turn.to_chat_sse(turn_index),
+    };
+    write_response(&mut stream, "200 OK", "text/event-stream", &body).await
+}
+
+/// Reads a single request, returning its request line and body. Returns
+/// `None` if the peer closed the connection before sending anything.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<(String, Vec<u8>)>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(pos) = find_header_end(&buf) {
+            break pos;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed mid-request",
+            ));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_BYTES {
+            return Err(std::io::Error::other("request too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default().to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_REQUEST_BYTES {
+        return Err(std::io::Error::other("request too large"));
+    }
+
+    let mut body = buf.split_off(header_end + 4);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    Ok(Some((request_line, body)))
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::scenario::ScenarioItem;
+    use crate::scenario::Turn;
+
+    async fn post(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let body = "{}";
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn serves_turns_in_order_across_endpoints() {
+        let scenario = Scenario {
+            turns: vec![
+                Turn {
+                    items: vec![ScenarioItem::Message {
+                        text: "first".to_string(),
+                    }],
+                },
+                Turn {
+                    items: vec![ScenarioItem::Message {
+                        text: "second".to_string(),
+                    }],
+                },
+            ],
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, scenario));
+
+        let first = post(addr, "/v1/responses").await;
+        assert!(first.starts_with("HTTP/1.1 200 OK"));
+        assert!(first.contains("event: response.completed"));
+        assert!(first.contains("first"));
+
+        let second = post(addr, "/v1/chat/completions").await;
+        assert!(second.starts_with("HTTP/1.1 200 OK"));
+        assert!(second.contains("second"));
+        assert!(second.ends_with("data: [DONE]\n\n"));
+
+        let exhausted = post(addr, "/v1/responses").await;
+        assert!(exhausted.starts_with("HTTP/1.1 400 Bad Request"));
+
+        let unknown = post(addr, "/v1/embeddings").await;
+        assert!(unknown.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
@@ -231,7 +231,7 @@ impl<'a> App<'a> {
                     AppState::Chat { widget } => widget.update_latest_log(line),
                     AppState::Login { .. } | AppState::GitWarning { .. } => {}
                 },
-                AppEvent::DispatchCommand(command) => match command {
+                AppEvent::DispatchCommand(command, args) => match command {
                     SlashCommand::New => {
                         let new_widget = Box::new(ChatWidget::new(
                             self.config.clone(),
@@ -242,6 +242,23 @@ impl<'a> App<'a> {
                         self.app_state = AppState::Chat { widget: new_widget };
                         self.app_event_tx.send(AppEvent::Redraw);
                     }
+                    SlashCommand::Undo => match &mut self.app_state {
+                        AppState::Chat { widget } => widget.submit_op(Op::UndoLastPatch),
+                        AppState::Login { .. } | AppState::GitWarning { .. } => {}
+                    },
+                    SlashCommand::Restore => match &mut self.app_state {
+                        AppState::Chat { widget } => match args.parse::<u64>() {
+                            Ok(turn) if turn > 0 => widget.submit_op(Op::RestoreSnapshot { turn }),
+                            _ => widget.add_error(format!(
+                                "Usage: /restore <turn> (turns are numbered from 1), got `{args}`"
+                            )),
+                        },
+                        AppState::Login { .. } | AppState::GitWarning { .. } => {}
+                    },
+                    SlashCommand::Diff => match &mut self.app_state {
+                        AppState::Chat { widget } => widget.submit_op(Op::GetSessionDiff),
+                        AppState::Login { .. } | AppState::GitWarning { .. } => {}
+                    },
                     SlashCommand::ToggleMouseMode => {
                         if let Err(e) = mouse_capture.toggle() {
                             tracing::error!("Failed to toggle mouse mode: {e}");
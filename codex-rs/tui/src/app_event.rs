@@ -26,6 +26,7 @@ pub(crate) enum AppEvent {
     LatestLog(String),
 
     /// Dispatch a recognized slash command from the UI (composer) to the app
-    /// layer so it can be handled centrally.
-    DispatchCommand(SlashCommand),
+    /// layer so it can be handled centrally, along with any text typed after
+    /// the command name (trimmed).
+    DispatchCommand(SlashCommand, String),
 }
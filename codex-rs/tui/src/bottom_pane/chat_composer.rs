@@ -133,8 +133,19 @@ impl ChatComposer<'_> {
                 ctrl: false,
             } => {
                 if let Some(cmd) = popup.selected_command() {
+                    // Everything after the command token is passed along as
+                    // its arguments, e.g. `/restore 3`.
+                    let args = self
+                        .textarea
+                        .lines()
+                        .first()
+                        .and_then(|line| line.trim_start().split_once(char::is_whitespace))
+                        .map(|(_, rest)| rest.trim().to_string())
+                        .unwrap_or_default();
+
                     // Send command to the app layer.
-                    self.app_event_tx.send(AppEvent::DispatchCommand(*cmd));
+                    self.app_event_tx
+                        .send(AppEvent::DispatchCommand(*cmd, args));
 
                     // Clear textarea so no residual text remains.
                     self.textarea.select_all();
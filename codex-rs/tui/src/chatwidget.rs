@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -6,17 +7,24 @@ use codex_core::config::Config;
 use codex_core::protocol::AgentMessageEvent;
 use codex_core::protocol::AgentReasoningEvent;
 use codex_core::protocol::ApplyPatchApprovalRequestEvent;
+use codex_core::protocol::BackgroundJobBeginEvent;
+use codex_core::protocol::BackgroundJobEndEvent;
 use codex_core::protocol::ErrorEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecApprovalRequestEvent;
 use codex_core::protocol::ExecCommandBeginEvent;
 use codex_core::protocol::ExecCommandEndEvent;
+use codex_core::protocol::ExecCommandOutputDeltaEvent;
 use codex_core::protocol::InputItem;
 use codex_core::protocol::McpToolCallBeginEvent;
 use codex_core::protocol::McpToolCallEndEvent;
 use codex_core::protocol::Op;
 use codex_core::protocol::PatchApplyBeginEvent;
+use codex_core::protocol::PatchConflictEvent;
+use codex_core::protocol::PatchUndoneEvent;
+use codex_core::protocol::SessionDiffEvent;
+use codex_core::protocol::SnapshotRestoredEvent;
 use codex_core::protocol::TaskCompleteEvent;
 use crossterm::event::KeyEvent;
 use ratatui::buffer::Buffer;
@@ -35,6 +43,7 @@ use crate::bottom_pane::BottomPane;
 use crate::bottom_pane::BottomPaneParams;
 use crate::bottom_pane::InputResult;
 use crate::conversation_history_widget::ConversationHistoryWidget;
+use crate::exec_command::strip_bash_lc_and_escape;
 use crate::history_cell::PatchEventType;
 use crate::user_approval_widget::ApprovalRequest;
 
@@ -46,6 +55,8 @@ pub(crate) struct ChatWidget<'a> {
     input_focus: InputFocus,
     config: Config,
     initial_user_message: Option<UserMessage>,
+    /// Background jobs that have not exited yet, keyed by job id.
+    running_jobs: BTreeMap<String, Vec<String>>,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -131,6 +142,7 @@ impl ChatWidget<'_> {
                 initial_prompt.unwrap_or_default(),
                 initial_images,
             ),
+            running_jobs: BTreeMap::new(),
         }
     }
 
@@ -257,12 +269,14 @@ impl ChatWidget<'_> {
             EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
                 command,
                 cwd,
+                stdin,
                 reason,
             }) => {
                 let request = ApprovalRequest::Exec {
                     id,
                     command,
                     cwd,
+                    stdin,
                     reason,
                 };
                 self.bottom_pane.push_approval_request(request);
@@ -301,6 +315,7 @@ impl ChatWidget<'_> {
                 call_id,
                 command,
                 cwd: _,
+                timeout_ms: _,
             }) => {
                 self.conversation_history
                     .add_active_exec_command(call_id, command);
@@ -320,6 +335,15 @@ impl ChatWidget<'_> {
                 }
                 self.request_redraw();
             }
+            EventMsg::ExecCommandOutputDelta(ExecCommandOutputDeltaEvent {
+                call_id,
+                stream: _,
+                chunk,
+            }) => {
+                self.conversation_history
+                    .append_exec_command_output(&call_id, &chunk);
+                self.request_redraw();
+            }
             EventMsg::ExecCommandEnd(ExecCommandEndEvent {
                 call_id,
                 exit_code,
@@ -330,6 +354,41 @@ impl ChatWidget<'_> {
                     .record_completed_exec_command(call_id, stdout, stderr, exit_code);
                 self.request_redraw();
             }
+            EventMsg::BackgroundJobBegin(BackgroundJobBeginEvent {
+                job_id,
+                command,
+                cwd: _,
+            }) => {
+                self.conversation_history.add_background_event(format!(
+                    "started background {job_id}: {}",
+                    strip_bash_lc_and_escape(&command)
+                ));
+                self.running_jobs.insert(job_id, command);
+                self.request_redraw();
+            }
+            EventMsg::BackgroundJobEnd(BackgroundJobEndEvent { job_id, exit_code }) => {
+                self.running_jobs.remove(&job_id);
+                let outcome = match exit_code {
+                    Some(code) => format!("exited {code}"),
+                    None => "was killed".to_string(),
+                };
+                let still_running = if self.running_jobs.is_empty() {
+                    String::new()
+                } else {
+                    let jobs = self
+                        .running_jobs
+                        .iter()
+                        .map(|(id, command)| {
+                            format!("{id} ({})", strip_bash_lc_and_escape(command))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!("; still running: {jobs}")
+                };
+                self.conversation_history
+                    .add_background_event(format!("background {job_id} {outcome}{still_running}"));
+                self.request_redraw();
+            }
             EventMsg::McpToolCallBegin(McpToolCallBeginEvent {
                 call_id,
                 server,
@@ -358,6 +417,41 @@ impl ChatWidget<'_> {
                 self.bottom_pane
                     .on_history_entry_response(log_id, offset, entry.map(|e| e.text));
             }
+            EventMsg::PatchConflict(PatchConflictEvent { call_id: _, paths }) => {
+                let mut message =
+                    "Patch not applied: these files changed since it was proposed:".to_string();
+                for path in paths {
+                    message.push_str(&format!("\n  {}", path.display()));
+                }
+                self.conversation_history.add_error(message);
+                self.request_redraw();
+            }
+            EventMsg::PatchUndone(PatchUndoneEvent { restored }) => {
+                let mut message = "Undid last patch. Restored:".to_string();
+                for path in restored {
+                    message.push_str(&format!("\n  {}", path.display()));
+                }
+                self.conversation_history.add_background_event(message);
+                self.request_redraw();
+            }
+            EventMsg::SnapshotRestored(SnapshotRestoredEvent { turn }) => {
+                self.conversation_history
+                    .add_background_event(format!("Restored snapshot from turn {turn}."));
+                self.request_redraw();
+            }
+            EventMsg::SessionDiff(SessionDiffEvent { diff }) => {
+                let message = if diff.is_empty() {
+                    "No changes since the session started.".to_string()
+                } else {
+                    diff
+                };
+                self.conversation_history.add_background_event(message);
+                self.conversation_history.scroll_to_bottom();
+                self.request_redraw();
+            }
+            EventMsg::ModelRequestSize(_) => {
+                // Only useful for debugging; it is logged by the core.
+            }
             event => {
                 self.conversation_history
                     .add_background_event(format!("{event:?}"));
@@ -389,6 +483,12 @@ impl ChatWidget<'_> {
         self.request_redraw();
     }
 
+    /// Show an error in the conversation that did not come from codex.
+    pub(crate) fn add_error(&mut self, message: String) {
+        self.conversation_history.add_error(message);
+        self.request_redraw();
+    }
+
     /// Forward an `Op` directly to codex.
     pub(crate) fn submit_op(&self, op: Op) {
         if let Err(e) = self.codex_op_tx.send(op) {
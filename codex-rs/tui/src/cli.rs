@@ -41,6 +41,11 @@ pub struct Cli {
     #[arg(long = "skip-git-repo-check", default_value_t = false)]
     pub skip_git_repo_check: bool,
 
+    /// Append every model request payload and raw SSE event to FILE, with
+    /// timestamps and API keys redacted.
+    #[arg(long = "trace-http", value_name = "FILE")]
+    pub trace_http: Option<PathBuf>,
+
     #[clap(skip)]
     pub config_overrides: CliConfigOverrides,
 }
@@ -2,6 +2,7 @@ use crate::cell_widget::CellWidget;
 use crate::history_cell::CommandOutput;
 use crate::history_cell::HistoryCell;
 use crate::history_cell::PatchEventType;
+use crate::text_block::TextBlock;
 use codex_core::config::Config;
 use codex_core::protocol::FileChange;
 use codex_core::protocol::SessionConfiguredEvent;
@@ -245,6 +246,32 @@ impl ConversationHistoryWidget {
         });
     }
 
+    /// Appends live output to the running command with `call_id` so its cell
+    /// shows the latest lines.
+    pub fn append_exec_command_output(&mut self, call_id: &str, chunk: &[u8]) {
+        let width = self.cached_width.get();
+        for entry in self.entries.iter_mut() {
+            if let HistoryCell::ActiveExecCommand {
+                call_id: history_id,
+                command,
+                output,
+                view,
+                ..
+            } = &mut entry.cell
+                && history_id == call_id
+            {
+                output.extend_from_slice(chunk);
+                *view = TextBlock::new(HistoryCell::active_exec_command_lines(command, output));
+
+                // Update cached line count.
+                if width > 0 {
+                    entry.line_count.set(entry.cell.height(width));
+                }
+                break;
+            }
+        }
+    }
+
     pub fn record_completed_exec_command(
         &mut self,
         call_id: String,
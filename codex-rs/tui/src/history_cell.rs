@@ -66,6 +66,8 @@ pub(crate) enum HistoryCell {
         /// The shell command, escaped and formatted.
         command: String,
         start: Instant,
+        /// Output received so far, stdout and stderr interleaved.
+        output: Vec<u8>,
         view: TextBlock,
     },
 
@@ -227,20 +229,33 @@ impl HistoryCell {
         let command_escaped = escape_command(&command);
         let start = Instant::now();
 
-        let lines: Vec<Line<'static>> = vec![
-            Line::from(vec!["command".magenta(), " running...".dim()]),
-            Line::from(format!("$ {command_escaped}")),
-            Line::from(""),
-        ];
+        let lines = Self::active_exec_command_lines(&command_escaped, &[]);
 
         HistoryCell::ActiveExecCommand {
             call_id,
             command: command_escaped,
             start,
+            output: Vec::new(),
             view: TextBlock::new(lines),
         }
     }
 
+    /// Lines for a running command: the command followed by the last few
+    /// lines of its output so far.
+    pub(crate) fn active_exec_command_lines(command: &str, output: &[u8]) -> Vec<Line<'static>> {
+        let mut lines: Vec<Line<'static>> = vec![
+            Line::from(vec!["command".magenta(), " running...".dim()]),
+            Line::from(format!("$ {command}")),
+        ];
+        let output = String::from_utf8_lossy(output);
+        let tail: Vec<&str> = output.lines().rev().take(TOOL_CALL_MAX_LINES).collect();
+        for raw in tail.into_iter().rev() {
+            lines.push(ansi_escape_line(raw).dim());
+        }
+        lines.push(Line::from(""));
+        lines
+    }
+
     pub(crate) fn new_completed_exec_command(command: String, output: CommandOutput) -> Self {
         let CommandOutput {
             exit_code,
@@ -626,6 +641,9 @@ fn create_diff_summary(changes: HashMap<PathBuf, FileChange>) -> Vec<String> {
             Delete => {
                 summaries.push(format!("D {}", path.display()));
             }
+            Symlink { target } => {
+                summaries.push(format!("L {} → {}", path.display(), target.display()));
+            }
             Update {
                 unified_diff,
                 move_path,
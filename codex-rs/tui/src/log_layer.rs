@@ -10,6 +10,7 @@
 
 use std::fmt::Write as _;
 
+use codex_core::redact::redact_secrets;
 use tokio::sync::mpsc::UnboundedSender;
 use tracing::Event;
 use tracing::Subscriber;
@@ -67,6 +68,10 @@ where
 
         event.record(&mut Visitor { buf: &mut buf });
 
+        // Redact before truncating so a partially cut-off key cannot slip
+        // past the patterns.
+        let mut buf = redact_secrets(&buf).into_owned();
+
         // `String::truncate` operates on UTF‑8 code‑point boundaries and will
         // panic if the provided index is not one.  Because we limit the log
         // line by its **byte** length we can not guarantee that the index we
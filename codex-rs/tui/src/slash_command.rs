@@ -13,6 +13,9 @@ use strum_macros::IntoStaticStr;
 #[strum(serialize_all = "kebab-case")]
 pub enum SlashCommand {
     New,
+    Undo,
+    Restore,
+    Diff,
     ToggleMouseMode,
     Quit,
 }
@@ -22,6 +25,11 @@ impl SlashCommand {
     pub fn description(self) -> &'static str {
         match self {
             SlashCommand::New => "Start a new chat.",
+            SlashCommand::Undo => "Revert the last patch applied in this session.",
+            SlashCommand::Restore => {
+                "Restore files to the snapshot taken at the start of turn N (git repos only)."
+            }
+            SlashCommand::Diff => "Show changes since the session started (git repos only).",
             SlashCommand::ToggleMouseMode => {
                 "Toggle mouse mode (enable for scrolling, disable for text selection)"
             }
@@ -38,6 +38,7 @@ pub(crate) enum ApprovalRequest {
         id: String,
         command: Vec<String>,
         cwd: PathBuf,
+        stdin: Option<String>,
         reason: Option<String>,
     },
     ApplyPatch {
@@ -116,6 +117,9 @@ pub(crate) struct UserApprovalWidget<'a> {
     done: bool,
 }
 
+/// Lines of a command's stdin shown in the prompt; the rest are summarized.
+const MAX_STDIN_LINES: usize = 10;
+
 // Number of lines automatically added by ratatui’s [`Block`] when
 // borders are enabled (one at the top, one at the bottom).
 const BORDER_LINES: u16 = 2;
@@ -127,6 +131,7 @@ impl UserApprovalWidget<'_> {
             ApprovalRequest::Exec {
                 command,
                 cwd,
+                stdin,
                 reason,
                 ..
             } => {
@@ -146,6 +151,19 @@ impl UserApprovalWidget<'_> {
                     ]),
                     Line::from(""),
                 ];
+                if let Some(stdin) = stdin {
+                    contents.push(Line::from("with stdin:".dim()));
+                    let lines: Vec<&str> = stdin.lines().collect();
+                    for line in lines.iter().take(MAX_STDIN_LINES) {
+                        contents.push(Line::from(line.to_string()));
+                    }
+                    if lines.len() > MAX_STDIN_LINES {
+                        contents.push(Line::from(
+                            format!("... {} more lines", lines.len() - MAX_STDIN_LINES).dim(),
+                        ));
+                    }
+                    contents.push(Line::from(""));
+                }
                 if let Some(reason) = reason {
                     contents.push(Line::from(reason.clone().italic()));
                     contents.push(Line::from(""));